@@ -1,21 +1,27 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Parser;
 
-use spool::archive::archive_tasks;
+use spool::archive::{archive_matching, unarchive_task, ArchiveRule};
 use spool::cli::{
-    add_stream, add_task, assign_task, claim_task, complete_task, delete_stream, free_task,
-    list_streams, list_tasks, reopen_task, show_stream, show_task, update_stream_cmd, update_task,
-    Cli, Commands, OutputFormat, StreamCommands,
+    add_stream, add_task, assign_task, claim_task, comment_task, complete_task, delete_comment,
+    delete_stream, edit_comment, free_task, link_task, list_conflicts, list_ready, list_streams,
+    list_tags, list_tasks, next_task, reopen_task, resolve_conflict, show_deps, show_stream,
+    show_task, show_tree, tag_task, unlink_task, unlock, update_stream_cmd, update_task, Cli,
+    Commands, OutputFormat, StreamCommands,
 };
+use spool::config::Config;
 use spool::context::{init, SpoolContext};
+use spool::gitref::{sync, SyncReport};
+use spool::merge::merge_driver;
+use spool::snapshot::compact;
 use spool::state::rebuild;
-use spool::validation::validate;
+use spool::validation::{remove_duplicates, repair, validate};
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Init => init(),
+        Commands::Init { git, events_ref } => init(git, events_ref),
         Commands::Add {
             title,
             description,
@@ -43,6 +49,8 @@ fn main() -> Result<()> {
             stream,
             stream_name,
             no_stream,
+            archived,
+            include_archived,
             format,
         } => {
             let ctx = SpoolContext::discover()?;
@@ -56,6 +64,8 @@ fn main() -> Result<()> {
                 stream.as_deref(),
                 stream_name.as_deref(),
                 no_stream,
+                archived,
+                include_archived,
                 fmt,
             )
         }
@@ -67,23 +77,115 @@ fn main() -> Result<()> {
             let ctx = SpoolContext::discover()?;
             rebuild(&ctx)
         }
-        Commands::Archive { days, dry_run } => {
+        Commands::Compact => {
             let ctx = SpoolContext::discover()?;
-            archive_tasks(&ctx, days, dry_run)?;
+            let path = compact(&ctx)?;
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            println!("Wrote snapshots/{}", name);
             Ok(())
         }
-        Commands::Validate { strict } => {
+        Commands::Sync { remote } => {
             let ctx = SpoolContext::discover()?;
+            let report = sync(&ctx, &remote)?;
+            if report.merged {
+                println!("Merged events from {}", remote);
+            } else if report.pulled {
+                println!("Pulled events from {}", remote);
+            }
+            if report.pushed {
+                println!("Pushed events to {}", remote);
+            }
+            if report == SyncReport::default() {
+                println!("Already in sync with {}", remote);
+            }
+            Ok(())
+        }
+        Commands::Archive {
+            days,
+            streams,
+            resolutions,
+            tags,
+            policy,
+            dry_run,
+        } => {
+            let ctx = SpoolContext::discover()?;
+            let rules = if policy {
+                let config = Config::load(&ctx)?;
+                if config.retention.is_empty() {
+                    return Err(anyhow!(
+                        "No retention policy configured in .spool/config.json"
+                    ));
+                }
+                config.retention
+            } else {
+                // Selecting tasks by what they are archives them whenever completed
+                let selected = !(streams.is_empty() && resolutions.is_empty() && tags.is_empty());
+                vec![ArchiveRule {
+                    days: days.unwrap_or(if selected { 0 } else { 30 }),
+                    streams,
+                    resolutions,
+                    tags,
+                }]
+            };
+            archive_matching(&ctx, &rules, dry_run)?;
+            Ok(())
+        }
+        Commands::Unarchive { id } => {
+            let ctx = SpoolContext::discover()?;
+            let restored = unarchive_task(&ctx, &id)?;
+            println!("Unarchived task {} ({} event(s) restored)", id, restored);
+            Ok(())
+        }
+        Commands::Validate { strict, dedupe } => {
+            let ctx = SpoolContext::discover()?;
+            if dedupe {
+                let removed = remove_duplicates(&ctx)?;
+                println!("Removed {} duplicate event(s)", removed);
+            }
             validate(&ctx, strict)?;
             Ok(())
         }
-        Commands::Complete { id, resolution } => {
+        Commands::Repair { dry_run } => {
+            let ctx = SpoolContext::discover()?;
+            let quarantined = repair(&ctx, dry_run)?;
+            if quarantined == 0 {
+                println!("No corrupt lines found");
+            } else if dry_run {
+                println!("Would quarantine {} line(s)", quarantined);
+            } else {
+                println!("Quarantined {} line(s) to .spool/rejected/", quarantined);
+            }
+            Ok(())
+        }
+        Commands::Conflicts {
+            id,
+            resolve,
+            keep,
+            format,
+        } => {
+            let ctx = SpoolContext::discover()?;
+            match (id, resolve) {
+                (Some(id), Some(field)) => resolve_conflict(&ctx, &id, &field, keep.as_deref()),
+                (id, _) => list_conflicts(&ctx, id.as_deref(), OutputFormat::from_str(&format)),
+            }
+        }
+        Commands::Unlock { force } => {
+            let ctx = SpoolContext::discover()?;
+            unlock(&ctx, force)
+        }
+        Commands::MergeDriver { base, ours, theirs } => merge_driver(&base, &ours, &theirs),
+        Commands::Complete {
+            id,
+            resolution,
+            force,
+            if_version,
+        } => {
             let ctx = SpoolContext::discover()?;
-            complete_task(&ctx, &id, Some(&resolution))
+            complete_task(&ctx, &id, Some(&resolution), force, if_version.as_deref())
         }
-        Commands::Reopen { id } => {
+        Commands::Reopen { id, if_version } => {
             let ctx = SpoolContext::discover()?;
-            reopen_task(&ctx, &id)
+            reopen_task(&ctx, &id, if_version.as_deref())
         }
         Commands::Update {
             id,
@@ -91,6 +193,7 @@ fn main() -> Result<()> {
             description,
             priority,
             stream,
+            if_version,
         } => {
             let ctx = SpoolContext::discover()?;
             update_task(
@@ -100,19 +203,83 @@ fn main() -> Result<()> {
                 description.as_deref(),
                 priority.as_deref(),
                 stream.as_deref(),
+                if_version.as_deref(),
             )
         }
-        Commands::Assign { id, assignee } => {
+        Commands::Assign {
+            id,
+            assignee,
+            if_version,
+        } => {
+            let ctx = SpoolContext::discover()?;
+            assign_task(&ctx, &id, &assignee, if_version.as_deref())
+        }
+        Commands::Claim { id, if_version } => {
+            let ctx = SpoolContext::discover()?;
+            claim_task(&ctx, &id, if_version.as_deref())
+        }
+        Commands::Free { id, if_version } => {
+            let ctx = SpoolContext::discover()?;
+            free_task(&ctx, &id, if_version.as_deref())
+        }
+        Commands::Comment {
+            id,
+            body,
+            reference,
+            edit,
+            delete,
+        } => {
+            let ctx = SpoolContext::discover()?;
+            match (edit, delete) {
+                (Some(comment), _) => {
+                    edit_comment(&ctx, &id, &comment, body.as_deref(), reference.as_deref())
+                }
+                (_, Some(comment)) => delete_comment(&ctx, &id, &comment),
+                _ => comment_task(&ctx, &id, body.as_deref(), reference.as_deref()),
+            }
+        }
+        Commands::Tag { id, changes } => {
             let ctx = SpoolContext::discover()?;
-            assign_task(&ctx, &id, &assignee)
+            tag_task(&ctx, &id, &changes)
         }
-        Commands::Claim { id } => {
+        Commands::Tags { format } => {
             let ctx = SpoolContext::discover()?;
-            claim_task(&ctx, &id)
+            list_tags(&ctx, OutputFormat::from_str(&format))
+        }
+        Commands::Link { id, link } => {
+            let ctx = SpoolContext::discover()?;
+            let (rel, target) = link.link();
+            link_task(&ctx, &id, rel, target)
+        }
+        Commands::Unlink { id, link } => {
+            let ctx = SpoolContext::discover()?;
+            let (rel, target) = link.link();
+            unlink_task(&ctx, &id, rel, target)
+        }
+        Commands::Deps { id, reverse } => {
+            let ctx = SpoolContext::discover()?;
+            show_deps(&ctx, &id, reverse)
+        }
+        Commands::Tree { id } => {
+            let ctx = SpoolContext::discover()?;
+            show_tree(&ctx, id.as_deref())
+        }
+        Commands::Ready {
+            filter,
+            limit,
+            format,
+        } => {
+            let ctx = SpoolContext::discover()?;
+            list_ready(
+                &ctx,
+                &filter.query(),
+                limit,
+                OutputFormat::from_str(&format),
+            )
         }
-        Commands::Free { id } => {
+        Commands::Next { filter, claim } => {
             let ctx = SpoolContext::discover()?;
-            free_task(&ctx, &id)
+            next_task(&ctx, &filter.query(), claim)
         }
         Commands::Stream { command } => {
             let ctx = SpoolContext::discover()?;
@@ -79,6 +79,28 @@ fn test_list_shows_tasks() {
         .stdout(predicate::str::contains("p1"));
 }
 
+#[test]
+fn test_list_skips_unknown_operations() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        concat!(
+            r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Test task"}}"#,
+            "\n",
+            r#"{"v":1,"op":"estimate","id":"task-001","ts":"2024-01-15T10:01:00Z","by":"@tester","branch":"main","d":{"points":3}}"#,
+        ),
+    );
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("task-001"))
+        .stderr(predicate::str::contains("unknown operation 'estimate'"));
+}
+
 #[test]
 fn test_list_json_format() {
     let temp_dir = TempDir::new().unwrap();
@@ -239,6 +261,273 @@ fn test_complete_task() {
         .stdout(predicate::str::contains("task-001"));
 }
 
+#[test]
+fn test_comment_add_edit_delete() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Test task"}}"#,
+    );
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["comment", "task-001", "Fixed in", "--ref", "abc123"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Commented on task: task-001"));
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["comment", "task-001"])
+        .write_stdin("From stdin\n")
+        .assert()
+        .success();
+
+    let output = spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["show", "task-001"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("  Fixed in\n  ref: abc123"));
+    assert!(stdout.contains("  From stdin\n"));
+    let ids: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("  ["))
+        .map(|line| &line[..8])
+        .collect();
+    assert_eq!(ids.len(), 2);
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["comment", "task-001", "--edit", ids[0], "Fixed in main"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Edited comment {} on task: task-001",
+            ids[0]
+        )));
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["comment", "task-001", "--delete", ids[1]])
+        .assert()
+        .success();
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["comment", "task-001", "--delete", "ffffffff"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No comment ffffffff"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["show", "task-001"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "(edited)\n  Fixed in main\n  ref: abc123",
+        ))
+        .stdout(predicate::str::contains("From stdin").not());
+}
+
+#[test]
+fn test_comment_rejects_empty_body() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Test task"}}"#,
+    );
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["comment", "task-001", "  "])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("empty comment"));
+}
+
+#[test]
+fn test_link_and_deps() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Release"}}
+{"v":1,"op":"create","id":"task-002","ts":"2024-01-15T10:01:00Z","by":"@tester","branch":"main","d":{"title":"Docs"}}
+{"v":1,"op":"create","id":"task-003","ts":"2024-01-15T10:02:00Z","by":"@tester","branch":"main","d":{"title":"Tests"}}
+{"v":1,"op":"create","id":"task-004","ts":"2024-01-15T10:03:00Z","by":"@tester","branch":"main","d":{"title":"Examples"}}
+"#,
+    );
+    let run = |args: &[&str]| spool_cmd().current_dir(temp_dir.path()).args(args).assert();
+
+    run(&["link", "task-001", "--blocked-by", "task-002"])
+        .success()
+        .stdout("Linked task task-001: blocked_by task-002\n");
+    run(&["link", "task-001", "--blocked-by", "task-003"]).success();
+    run(&["link", "task-004", "--blocks", "task-002"]).success();
+    run(&["link", "task-004", "--blocks", "task-003"]).success();
+    run(&["link", "task-004", "--blocked-by", "task-001"])
+        .failure()
+        .stderr(predicate::str::contains("would create a cycle"));
+
+    run(&["show", "task-002"])
+        .success()
+        .stdout(predicate::str::contains("Blocks:   task-001"))
+        .stdout(predicate::str::contains("Blocked by: task-004"));
+    run(&["deps", "task-001"]).success().stdout(
+        "task-001  Release
+├── task-002  Docs
+│   └── task-004  Examples
+└── task-003  Tests
+    └── task-004  Examples (see above)
+",
+    );
+    run(&["deps", "task-004", "--reverse"])
+        .success()
+        .stdout(predicate::str::contains(
+            "    └── task-001  Release (see above)",
+        ));
+
+    run(&["unlink", "task-002", "--blocks", "task-001"]).success();
+    run(&["deps", "task-001"]).success().stdout(
+        "task-001  Release
+└── task-003  Tests
+    └── task-004  Examples
+",
+    );
+}
+
+#[test]
+fn test_ready_and_next_claim() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Backlog"}}
+{"v":1,"op":"create","id":"task-002","ts":"2024-01-15T10:01:00Z","by":"@tester","branch":"main","d":{"title":"Urgent","priority":"p0","blocked_by":["task-003"]}}
+{"v":1,"op":"create","id":"task-003","ts":"2024-01-15T10:02:00Z","by":"@tester","branch":"main","d":{"title":"Prerequisite","priority":"p1","blocks":["task-002"]}}
+"#,
+    );
+    let run = |args: &[&str]| {
+        spool_cmd()
+            .current_dir(temp_dir.path())
+            .env("USER", "agent")
+            .args(args)
+            .assert()
+    };
+
+    run(&["ready", "-f", "ids"])
+        .success()
+        .stdout("task-003\ntask-001\n");
+    run(&["next"]).success().stdout("task-003  Prerequisite\n");
+
+    run(&["next", "--claim"])
+        .success()
+        .stdout(predicate::str::starts_with("Claimed task task-003"));
+    run(&["ready", "-f", "ids"]).success().stdout("task-001\n");
+
+    run(&["complete", "task-003"]).success();
+    run(&["ready", "-f", "ids", "-n", "1"])
+        .success()
+        .stdout("task-002\n");
+    run(&["next", "--claim"]).success();
+    run(&["next", "--claim"]).success();
+    run(&["next", "--claim"])
+        .failure()
+        .stderr(predicate::str::contains("No tasks ready"));
+}
+
+#[test]
+fn test_subtask_tree_and_rollup() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Epic"}}
+{"v":1,"op":"create","id":"task-002","ts":"2024-01-15T10:01:00Z","by":"@tester","branch":"main","d":{"title":"Part one","parent":"task-001"}}
+{"v":1,"op":"create","id":"task-003","ts":"2024-01-15T10:02:00Z","by":"@tester","branch":"main","d":{"title":"Part two","parent":"task-001"}}
+{"v":1,"op":"create","id":"task-004","ts":"2024-01-15T10:03:00Z","by":"@tester","branch":"main","d":{"title":"Detail","parent":"task-003"}}
+{"v":1,"op":"create","id":"task-005","ts":"2024-01-15T10:04:00Z","by":"@tester","branch":"main","d":{"title":"Standalone"}}
+{"v":1,"op":"complete","id":"task-002","ts":"2024-01-15T11:00:00Z","by":"@tester","branch":"main","d":{}}
+"#,
+    );
+    let run = |args: &[&str]| spool_cmd().current_dir(temp_dir.path()).args(args).assert();
+
+    run(&["tree"]).success().stdout(
+        "[ ] task-001  Epic (1/3)
+├── [x] task-002  Part one
+└── [ ] task-003  Part two (0/1)
+    └── [ ] task-004  Detail
+",
+    );
+    run(&["tree", "task-003"])
+        .success()
+        .stdout("[ ] task-003  Part two (0/1)\n└── [ ] task-004  Detail\n");
+    run(&["show", "task-001"])
+        .success()
+        .stdout(predicate::str::contains(
+            "Subtasks: 1/3 complete\n  [x] task-002  Part one\n  [ ] task-003  Part two (0/1)\n",
+        ));
+
+    let output = spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["list", "-f", "json"])
+        .output()
+        .unwrap();
+    let tasks: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let epic = &tasks[0];
+    assert_eq!(
+        epic["children"],
+        serde_json::json!(["task-002", "task-003"])
+    );
+    assert_eq!(
+        epic["progress"],
+        serde_json::json!({"complete": 1, "total": 3})
+    );
+    assert!(tasks[3].get("children").is_none());
+
+    run(&["complete", "task-001"])
+        .failure()
+        .stderr(predicate::str::contains("2 open subtask(s)"));
+    run(&["complete", "task-001", "--force"]).success();
+}
+
+#[test]
+fn test_tag_and_tags() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"First","tags":["wip","ui"]}}
+{"v":1,"op":"create","id":"task-002","ts":"2024-01-15T10:01:00Z","by":"@tester","branch":"main","d":{"title":"Second","tags":["ui"]}}
+{"v":1,"op":"complete","id":"task-002","ts":"2024-01-15T11:00:00Z","by":"@tester","branch":"main","d":{}}
+"#,
+    );
+    let run = |args: &[&str]| spool_cmd().current_dir(temp_dir.path()).args(args).assert();
+
+    run(&["tag", "task-001", "+bug", "-wip"])
+        .success()
+        .stdout("Tagged task task-001: +bug -wip\n");
+    run(&["show", "task-001"])
+        .success()
+        .stdout(predicate::str::contains("Tags:     ui, bug"));
+    run(&["tag", "task-001", "+x", "-x"])
+        .failure()
+        .stderr(predicate::str::contains("both added and removed"));
+    run(&["tag", "task-404", "+bug"])
+        .failure()
+        .stderr(predicate::str::contains("Task not found"));
+
+    run(&["tags"]).success().stdout(
+        "TAG                    OPEN  TOTAL
+ui                        1      2
+bug                       1      1
+",
+    );
+    run(&["tags", "-f", "ids"]).success().stdout("ui\nbug\n");
+}
+
 #[test]
 fn test_complete_task_with_resolution() {
     let temp_dir = TempDir::new().unwrap();
@@ -348,6 +637,63 @@ fn test_update_task_title() {
         .stdout(predicate::str::contains("New title"));
 }
 
+#[test]
+fn test_update_if_version_rejects_stale_version() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Old title"}}"#,
+    );
+
+    let output = spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["show", "task-001"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Version:"))
+        .expect("show prints the task version")
+        .trim()
+        .to_string();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args([
+            "update",
+            "task-001",
+            "--title",
+            "Stale",
+            "--if-version",
+            "0000",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("modified concurrently"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args([
+            "update",
+            "task-001",
+            "--title",
+            "Fresh",
+            "--if-version",
+            &version,
+        ])
+        .assert()
+        .success();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["show", "task-001"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Fresh"));
+}
+
 #[test]
 fn test_update_task_not_found() {
     let temp_dir = TempDir::new().unwrap();
@@ -384,6 +730,31 @@ fn test_rebuild_regenerates_state() {
     assert!(state_path.exists());
 }
 
+#[test]
+fn test_list_sees_tasks_added_after_rebuild() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("rebuild")
+        .assert()
+        .success();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["add", "Added after rebuild"])
+        .assert()
+        .success();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added after rebuild"));
+}
+
 #[test]
 fn test_validate_valid_events() {
     let temp_dir = TempDir::new().unwrap();
@@ -945,3 +1316,469 @@ fn test_stream_list_json_format() {
         .stdout(predicate::str::contains("\"name\":"))
         .stdout(predicate::str::contains("JSON Stream"));
 }
+
+#[test]
+fn test_validate_dedupe_removes_duplicates() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    let line = r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Test task"}}"#;
+    write_test_events(&temp_dir, &format!("{}\n{}\n", line, line));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["validate", "--strict"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Duplicate of event"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["validate", "--dedupe", "--strict"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 1 duplicate event(s)"));
+}
+
+#[test]
+fn test_torn_line_is_skipped_then_repaired() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    let line = r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Test task"}}"#;
+    write_test_events(&temp_dir, &format!("{}\n{{\"v\":1,\"op\n", line));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Test task"))
+        .stderr(predicate::str::contains("spool repair"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["repair"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Quarantined 1 line(s)"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["validate", "--strict"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_archived_tasks_listed_on_request_and_unarchived() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        concat!(
+            r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Shipped long ago"}}"#,
+            "\n",
+            r#"{"v":1,"op":"complete","id":"task-001","ts":"2024-01-15T11:00:00Z","by":"@tester","branch":"main","d":{}}"#,
+            "\n",
+        ),
+    );
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["archive"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Archived 1 tasks."));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["list", "--status", "all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Shipped long ago").not());
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["list", "--archived"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Shipped long ago"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["list", "--status", "all", "--include-archived"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Shipped long ago"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["unarchive", "task-001"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 event(s) restored"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["list", "--status", "all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Shipped long ago"));
+}
+
+#[test]
+fn test_archive_by_resolution_includes_recently_completed_tasks() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Dropped today"}}"#,
+    );
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["complete", "task-001", "--resolution", "wontfix"])
+        .assert()
+        .success();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["archive"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No tasks to archive."));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["archive", "--resolution", "wontfix"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Archived 1 tasks."));
+}
+
+#[test]
+fn test_init_git_registers_merge_driver() {
+    let temp_dir = TempDir::new().unwrap();
+    std::process::Command::new("git")
+        .current_dir(temp_dir.path())
+        .args(["init", "-q"])
+        .status()
+        .unwrap();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["init", "--git"])
+        .assert()
+        .success();
+
+    let attributes = fs::read_to_string(temp_dir.path().join(".spool/.gitattributes")).unwrap();
+    assert!(attributes.contains("events/*.jsonl merge=spool"));
+
+    let driver = std::process::Command::new("git")
+        .current_dir(temp_dir.path())
+        .args(["config", "merge.spool.driver"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&driver.stdout).trim(),
+        "spool merge-driver %O %A %B"
+    );
+
+    // Re-running on an existing spool is a no-op for the attributes file
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["init", "--git"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join(".spool/.gitattributes")).unwrap(),
+        attributes
+    );
+}
+
+#[test]
+fn test_init_events_ref_moves_events_to_ref() {
+    let temp_dir = TempDir::new().unwrap();
+    std::process::Command::new("git")
+        .current_dir(temp_dir.path())
+        .args(["init", "-q"])
+        .status()
+        .unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Existing task"}}
+"#,
+    );
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["init", "--events-ref"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Copied 1 event file(s)"));
+
+    let config = fs::read_to_string(temp_dir.path().join(".spool/config.json")).unwrap();
+    assert!(config.contains("\"events_ref\": \"refs/spool/events\""));
+
+    // The ref is now the source of truth
+    fs::remove_file(temp_dir.path().join(".spool/events/2024-01-15.jsonl")).unwrap();
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["add", "Added later"])
+        .assert()
+        .success();
+    assert!(fs::read_dir(temp_dir.path().join(".spool/events"))
+        .unwrap()
+        .next()
+        .is_none());
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Existing task"))
+        .stdout(predicate::str::contains("Added later"));
+
+    // Without a remote there's nothing to sync with
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("sync")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_author_layout_shards_events() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Existing task"}}
+"#,
+    );
+    fs::write(
+        temp_dir.path().join(".spool/config.json"),
+        r#"{"layout": "author"}"#,
+    )
+    .unwrap();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .env("SPOOL_SHARD", "laptop")
+        .args(["add", "Sharded task"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Split 1 day file(s)"));
+
+    let events_dir = temp_dir.path().join(".spool/events");
+    assert!(!events_dir.join("2024-01-15.jsonl").exists());
+    assert!(events_dir.join("2024-01-15/@tester.jsonl").exists());
+    let shards: Vec<_> = fs::read_dir(&events_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().join("laptop.jsonl"))
+        .filter(|shard| shard.exists())
+        .collect();
+    assert_eq!(shards.len(), 1);
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Existing task"))
+        .stdout(predicate::str::contains("Sharded task"));
+}
+
+#[test]
+fn test_unlock_removes_lock_of_dead_process() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    let lock_path = temp_dir.path().join(".spool/.lock");
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["unlock"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No lock held"));
+
+    fs::write(&lock_path, "999999999:2024-01-15T10:00:00+00:00\n").unwrap();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["unlock"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No lock held"));
+    assert!(!lock_path.exists());
+}
+
+#[test]
+fn test_merge_driver_writes_union_to_ours() {
+    let temp_dir = TempDir::new().unwrap();
+    let create = r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Test task"}}"#;
+    let ours = r#"{"v":1,"op":"assign","id":"task-001","ts":"2024-01-15T11:00:00Z","by":"@tester","branch":"main","d":{"to":"@tester"}}"#;
+    let theirs = r#"{"v":1,"op":"complete","id":"task-001","ts":"2024-01-15T12:00:00Z","by":"@other","branch":"feature","d":{}}"#;
+    fs::write(temp_dir.path().join("base"), format!("{}\n", create)).unwrap();
+    fs::write(
+        temp_dir.path().join("ours"),
+        format!("{}\n{}\n", create, ours),
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("theirs"),
+        format!("{}\n{}\n", create, theirs),
+    )
+    .unwrap();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["merge-driver", "base", "ours", "theirs"])
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("ours")).unwrap(),
+        format!("{}\n{}\n{}\n", create, ours, theirs)
+    );
+}
+
+#[test]
+fn test_conflicts_lists_and_resolves_concurrent_edits() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+
+    let create_line = r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Original"}}"#;
+    let create = spool::Event::from_json(create_line).unwrap();
+    let ours = format!(
+        r#"{{"v":1,"op":"update","id":"task-001","ts":"2024-01-15T11:00:00Z","prev":["{}"],"by":"@tester","branch":"main","d":{{"title":"Ours"}}}}"#,
+        create.event_id()
+    );
+    let theirs = format!(
+        r#"{{"v":1,"op":"update","id":"task-001","ts":"2024-01-15T12:00:00Z","prev":["{}"],"by":"@other","branch":"feature","d":{{"title":"Theirs"}}}}"#,
+        create.event_id()
+    );
+    let ours_id = spool::Event::from_json(&ours).unwrap().event_id();
+    write_test_events(
+        &temp_dir,
+        &format!("{}\n{}\n{}\n", create_line, ours, theirs),
+    );
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("conflicts")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("task-001 title:"))
+        .stdout(predicate::str::contains("\"Theirs\" by @other on feature"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["show", "task-001"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Conflicts: title"))
+        .stderr(predicate::str::contains("1 conflicting edit(s)"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["conflicts", "task-001", "--resolve", "title", "--keep"])
+        .arg(&ours_id[..8])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Resolved title on task-001"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("conflicts")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No conflicts."));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["show", "task-001"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Title:    Ours"));
+}
+
+#[test]
+fn test_resolving_status_conflict_keeps_completion_resolution() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+
+    let create_line = r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Original"}}"#;
+    let create = spool::Event::from_json(create_line).unwrap();
+    let wontfix = format!(
+        r#"{{"v":1,"op":"complete","id":"task-001","ts":"2024-01-15T11:00:00Z","prev":["{}"],"by":"@tester","branch":"main","d":{{"resolution":"wontfix"}}}}"#,
+        create.event_id()
+    );
+    let reopen = format!(
+        r#"{{"v":1,"op":"reopen","id":"task-001","ts":"2024-01-15T12:00:00Z","prev":["{}"],"by":"@other","branch":"feature","d":{{}}}}"#,
+        create.event_id()
+    );
+    let wontfix_id = spool::Event::from_json(&wontfix).unwrap().event_id();
+    write_test_events(
+        &temp_dir,
+        &format!("{}\n{}\n{}\n", create_line, wontfix, reopen),
+    );
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["conflicts", "task-001", "--resolve", "status", "--keep"])
+        .arg(&wontfix_id[..8])
+        .assert()
+        .success();
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["show", "task-001"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(wontfix)"));
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_list_from_sqlite_index() {
+    let temp_dir = TempDir::new().unwrap();
+    setup_initialized_spool(&temp_dir);
+    fs::write(
+        temp_dir.path().join(".spool/config.json"),
+        r#"{"sqlite_index": true}"#,
+    )
+    .unwrap();
+    write_test_events(
+        &temp_dir,
+        r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Indexed task","tags":["bug"]}}
+{"v":1,"op":"create","id":"task-002","ts":"2024-01-15T11:00:00Z","by":"@tester","branch":"main","d":{"title":"Other task"}}
+"#,
+    );
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["list", "--tag", "bug", "-f", "ids"])
+        .assert()
+        .success()
+        .stdout("task-001\n");
+    assert!(temp_dir.path().join(".spool/.index.db").exists());
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .args(["add", "Added later"])
+        .assert()
+        .success();
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added later"));
+
+    spool_cmd()
+        .current_dir(temp_dir.path())
+        .arg("rebuild")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Wrote .index.db"));
+}
@@ -1,8 +1,10 @@
 use anyhow::Result;
 use ratatui::widgets::ListState;
 use spool::context::SpoolContext;
+use spool::deps::Hierarchy;
 use spool::event::Event;
-use spool::state::{load_or_materialize_state, Stream, Task, TaskStatus};
+use spool::query::{ArchivedFilter, TaskQuery, TaskSource};
+use spool::state::{Stream, Task, TaskStatus};
 use spool::writer::{self, CreateTaskParams};
 use spool::{archive, init, rebuild, validation};
 
@@ -173,13 +175,13 @@ impl App {
             Ok(ctx) => ctx,
             Err(_) => {
                 // Auto-initialize spool directory
-                init()?;
+                init(false, false)?;
                 SpoolContext::discover()?
             }
         };
-        let state = load_or_materialize_state(&ctx)?;
+        let source = TaskSource::open(&ctx)?;
 
-        let streams = state.streams.clone();
+        let streams = source.streams()?;
         let mut stream_ids: Vec<String> = streams.keys().cloned().collect();
         stream_ids.sort_by(|a, b| {
             let name_a = streams.get(a).map(|s| s.name.as_str()).unwrap_or(a);
@@ -187,13 +189,12 @@ impl App {
             name_a.to_lowercase().cmp(&name_b.to_lowercase())
         });
 
-        let all_tasks = state.tasks.clone();
+        let all_tasks = all_tasks(&source)?;
 
-        let mut tasks: Vec<Task> = state
-            .tasks
-            .into_values()
-            .filter(|t| t.status == TaskStatus::Open)
-            .collect();
+        let mut tasks = source.tasks(&TaskQuery {
+            status: Some(TaskStatus::Open),
+            ..Default::default()
+        })?;
 
         tasks.sort_by(|a, b| {
             let pa = a.priority.as_deref().unwrap_or("p3");
@@ -250,9 +251,9 @@ impl App {
     }
 
     pub fn reload_tasks(&mut self) -> Result<()> {
-        let state = load_or_materialize_state(&self.ctx)?;
-        self.streams = state.streams.clone();
-        self.all_tasks = state.tasks.clone();
+        let source = TaskSource::open(&self.ctx)?;
+        self.streams = source.streams()?;
+        self.all_tasks = all_tasks(&source)?;
 
         // Update stream_ids list
         self.stream_ids = self.streams.keys().cloned().collect();
@@ -269,33 +270,17 @@ impl App {
             }
         }
 
-        let query = self.search_query.to_lowercase();
-        let stream_filter = self.stream_filter.clone();
-        let mut tasks: Vec<Task> = state
-            .tasks
-            .into_values()
-            .filter(|t| match self.status_filter {
-                StatusFilter::Open => t.status == TaskStatus::Open,
-                StatusFilter::Complete => t.status == TaskStatus::Complete,
-                StatusFilter::All => true,
-            })
-            .filter(|t| match &stream_filter {
-                None => true,
-                Some(stream_id) => t.stream.as_ref() == Some(stream_id),
-            })
-            .filter(|t| {
-                if query.is_empty() {
-                    true
-                } else {
-                    t.title.to_lowercase().contains(&query)
-                        || t.description
-                            .as_ref()
-                            .map(|d| d.to_lowercase().contains(&query))
-                            .unwrap_or(false)
-                        || t.tags.iter().any(|tag| tag.to_lowercase().contains(&query))
-                }
-            })
-            .collect();
+        // Archived tasks are only listed on request (`spool list --archived`)
+        let mut tasks = source.tasks(&TaskQuery {
+            status: match self.status_filter {
+                StatusFilter::Open => Some(TaskStatus::Open),
+                StatusFilter::Complete => Some(TaskStatus::Complete),
+                StatusFilter::All => None,
+            },
+            stream: self.stream_filter.clone(),
+            search: Some(self.search_query.clone()).filter(|q| !q.is_empty()),
+            ..Default::default()
+        })?;
 
         self.sort_tasks(&mut tasks);
         self.tasks = tasks;
@@ -330,10 +315,10 @@ impl App {
                 });
             }
             SortBy::Created => {
-                tasks.sort_by(|a, b| b.created.cmp(&a.created));
+                tasks.sort_by_key(|t| std::cmp::Reverse(t.created));
             }
             SortBy::Title => {
-                tasks.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
+                tasks.sort_by_key(|t| t.title.to_lowercase());
             }
         }
     }
@@ -341,12 +326,7 @@ impl App {
     pub fn load_task_events(&mut self) -> Result<()> {
         if let Some(task) = self.selected_task() {
             let task_id = task.id.clone();
-            let events_by_task = spool::archive::collect_all_events(&self.ctx)?;
-            self.task_events = events_by_task
-                .into_values()
-                .flatten()
-                .filter(|e| e.id == task_id)
-                .collect();
+            self.task_events = spool::archive::collect_task_events(&self.ctx, &task_id)?;
         }
         Ok(())
     }
@@ -473,9 +453,16 @@ impl App {
             let by = writer::get_current_user().unwrap_or_else(|_| "unknown".to_string());
             let branch = writer::get_current_branch().unwrap_or_else(|_| "main".to_string());
 
+            // The TUI completes parents anyway, but says what's left
+            let open = Hierarchy::new(self.all_tasks.values()).progress(&id).open();
+
             match writer::complete_task(&self.ctx, &id, None, &by, &branch) {
                 Ok(()) => {
-                    self.message = Some(format!("Completed: {}", id));
+                    self.message = Some(if open > 0 {
+                        format!("Completed: {} ({} subtask(s) still open)", id, open)
+                    } else {
+                        format!("Completed: {}", id)
+                    });
                     let _ = self.reload_tasks();
                 }
                 Err(e) => {
@@ -1047,7 +1034,7 @@ impl App {
         let events_by_task = spool::archive::collect_all_events(&self.ctx)?;
         let mut all_events: Vec<Event> = events_by_task.into_values().flatten().collect();
         // Sort by timestamp descending (most recent first)
-        all_events.sort_by(|a, b| b.ts.cmp(&a.ts));
+        all_events.sort_by_key(|e| std::cmp::Reverse(e.ts));
         self.history_events = all_events;
         self.history_selected = 0;
         self.history_list_state.select(Some(0));
@@ -1121,6 +1108,15 @@ impl App {
     }
 }
 
+/// Every task, archived ones included, for lookups by ID
+fn all_tasks(source: &TaskSource) -> Result<std::collections::HashMap<String, Task>> {
+    let tasks = source.tasks(&TaskQuery {
+        archived: ArchivedFilter::Include,
+        ..Default::default()
+    })?;
+    Ok(tasks.into_iter().map(|t| (t.id.clone(), t)).collect())
+}
+
 /// Test-only constructor for `App` — bypasses filesystem discovery and uses
 /// an in-memory task list with a non-existent (but structurally valid)
 /// `SpoolContext`. Methods that perform I/O silently ignore errors (e.g.
@@ -1791,6 +1787,9 @@ mod tests {
             op: spool::Operation::Create,
             id: id.to_string(),
             ts: chrono::Utc::now(),
+            hlc: None,
+            prev: vec![],
+            txn: None,
             by: "test".to_string(),
             branch: "main".to_string(),
             d: serde_json::Value::Null,
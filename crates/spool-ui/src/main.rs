@@ -58,8 +58,8 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: Ap
         }
     })?;
 
-    // Watch the events directory
-    watcher.watch(app.events_dir(), RecursiveMode::NonRecursive)?;
+    // Watch the events directory, including each day's author shards
+    watcher.watch(app.events_dir(), RecursiveMode::Recursive)?;
 
     // Poll timeout - balance between responsiveness and CPU usage
     let poll_timeout = Duration::from_millis(100);
@@ -238,17 +238,9 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: Ap
                             KeyCode::Char('l') | KeyCode::Right => app.history_scroll_right(),
                             KeyCode::Left => app.history_scroll_left(),
                             KeyCode::Enter => app.toggle_history_detail(),
-                            KeyCode::Tab => {
-                                // Allow navigating list even when detail is open
-                                if app.history_show_detail {
-                                    app.history_next();
-                                }
-                            }
-                            KeyCode::BackTab => {
-                                if app.history_show_detail {
-                                    app.history_previous();
-                                }
-                            }
+                            // Allow navigating list even when detail is open
+                            KeyCode::Tab if app.history_show_detail => app.history_next(),
+                            KeyCode::BackTab if app.history_show_detail => app.history_previous(),
                             _ => {}
                         },
                         InputMode::Normal if app.view == View::Streams => match key.code {
@@ -27,6 +27,10 @@ fn operation_color(op: &str) -> Color {
         "update" => Color::Yellow,
         "assign" => Color::Cyan,
         "comment" => Color::Magenta,
+        "edit_comment" => Color::Magenta,
+        "delete_comment" => Color::Red,
+        "tag" => Color::Cyan,
+        "untag" => Color::Yellow,
         "set_stream" => Color::Blue,
         "create_stream" => Color::Green,
         "update_stream" => Color::Yellow,
@@ -5,7 +5,7 @@ use tempfile::TempDir;
 
 use spool::context::SpoolContext;
 use spool::event::{Event, Operation};
-use spool::state::{build_index, materialize, Task, TaskStatus};
+use spool::state::{build_index, materialize, replay_all, Task, TaskStatus};
 
 fn create_test_spool(num_tasks: usize) -> (TempDir, SpoolContext) {
     let temp_dir = TempDir::new().unwrap();
@@ -17,6 +17,7 @@ fn create_test_spool(num_tasks: usize) -> (TempDir, SpoolContext) {
         root: spool_dir.clone(),
         events_dir: spool_dir.join("events"),
         archive_dir: spool_dir.join("archive"),
+        git_ref: None,
     };
 
     // Generate test events
@@ -24,6 +25,7 @@ fn create_test_spool(num_tasks: usize) -> (TempDir, SpoolContext) {
     let file = OpenOptions::new()
         .create(true)
         .write(true)
+        .truncate(true)
         .open(&event_file)
         .unwrap();
     let mut writer = BufWriter::new(file);
@@ -83,6 +85,57 @@ fn create_test_spool(num_tasks: usize) -> (TempDir, SpoolContext) {
     (temp_dir, ctx)
 }
 
+/// A spool with `num_events` events spread over `num_days` day files, each
+/// task created on one day and updated on the following ones
+fn create_large_spool(num_events: usize, num_days: usize) -> (TempDir, SpoolContext) {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = temp_dir.path().join(".spool");
+    fs::create_dir_all(spool_dir.join("events")).unwrap();
+    fs::create_dir_all(spool_dir.join("archive")).unwrap();
+    let ctx = SpoolContext::new(spool_dir);
+
+    let per_day = num_events / num_days;
+    let base = chrono::NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+    for day in 0..num_days {
+        let date = base + chrono::Duration::days(day as i64);
+        let event_file = ctx.events_dir.join(format!("{}.jsonl", date));
+        let mut writer = BufWriter::new(fs::File::create(&event_file).unwrap());
+        for i in 0..per_day {
+            let ts = format!(
+                "{}T{:02}:{:02}:{:02}Z",
+                date,
+                i / 3600 % 24,
+                i / 60 % 60,
+                i % 60
+            );
+            // Half of each day's events create tasks, the rest update
+            // tasks from earlier days
+            let event = if i % 2 == 0 || day == 0 {
+                serde_json::json!({
+                    "v": 1, "op": "create", "id": format!("task-{}-{}", day, i),
+                    "ts": ts, "by": "@bench", "branch": "main",
+                    "d": {
+                        "title": format!("Test task {} {}", day, i),
+                        "description": "A test task for benchmarking",
+                        "priority": "medium",
+                        "tags": ["benchmark", "test"]
+                    }
+                })
+            } else {
+                serde_json::json!({
+                    "v": 1, "op": "update", "id": format!("task-{}-{}", day - 1, i - 1),
+                    "ts": ts, "by": "@bench", "branch": "main",
+                    "d": {"title": format!("Updated on day {}", day)}
+                })
+            };
+            writeln!(writer, "{}", event).unwrap();
+        }
+        writer.flush().unwrap();
+    }
+
+    (temp_dir, ctx)
+}
+
 fn bench_event_serialization(c: &mut Criterion) {
     let mut group = c.benchmark_group("serialization");
 
@@ -91,6 +144,9 @@ fn bench_event_serialization(c: &mut Criterion) {
         op: Operation::Create,
         id: "test-1234".to_string(),
         ts: chrono::Utc::now(),
+        hlc: None,
+        prev: vec![],
+        txn: None,
         by: "@bench".to_string(),
         branch: "main".to_string(),
         d: serde_json::json!({
@@ -248,6 +304,34 @@ fn bench_event_parsing(c: &mut Criterion) {
     group.finish();
 }
 
+/// 100k events over 100 day files: parsing every file on one thread, against
+/// full replays and index builds, which parse files in parallel
+fn bench_large_history(c: &mut Criterion) {
+    let mut group = c.benchmark_group("large_history");
+    group.sample_size(10);
+
+    let size = 100_000;
+    let (_temp_dir, ctx) = create_large_spool(size, 100);
+    let files = ctx.get_event_files().unwrap();
+    group.throughput(Throughput::Elements(size as u64));
+
+    group.bench_with_input(BenchmarkId::new("parse_sequential", size), &size, |b, _| {
+        b.iter(|| {
+            for file in &files {
+                black_box(ctx.read_events_lenient(file).unwrap());
+            }
+        })
+    });
+    group.bench_with_input(BenchmarkId::new("replay_all", size), &size, |b, _| {
+        b.iter(|| replay_all(black_box(&ctx)).unwrap())
+    });
+    group.bench_with_input(BenchmarkId::new("build_index", size), &size, |b, _| {
+        b.iter(|| build_index(black_box(&ctx)).unwrap())
+    });
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_event_serialization,
@@ -255,6 +339,7 @@ criterion_group!(
     bench_index_building,
     bench_queries,
     bench_event_parsing,
+    bench_large_history,
 );
 
 criterion_main!(benches);
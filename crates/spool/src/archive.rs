@@ -1,31 +1,107 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use chrono::Utc;
-use std::collections::{BTreeMap, HashMap};
-use std::fs::{self, OpenOptions};
-use std::io::{BufWriter, Write};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
+use crate::config::Config;
 use crate::context::SpoolContext;
-use crate::event::{Event, Operation};
-use crate::state::{materialize, Task, TaskStatus};
-use crate::writer::get_current_branch;
+use crate::event::{sort_events, Event, Operation};
+use crate::payload::{ArchivePayload, Payload};
+use crate::state::{materialize, State, Task, TaskStatus};
+use crate::store::{archive_segment, segment_name, EventStore, ARCHIVE, EVENTS};
+use crate::writer::{get_current_branch, stamp_events, todays_segment};
 
-pub fn archive_tasks(ctx: &SpoolContext, days: u32, dry_run: bool) -> Result<Vec<String>> {
-    let state = materialize(ctx)?;
-    let cutoff = Utc::now() - chrono::Duration::days(days as i64);
+/// Which completed tasks to archive
+///
+/// Every criterion given must match; an empty list matches anything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveRule {
+    /// Only tasks completed at least this many days ago
+    #[serde(default)]
+    pub days: u32,
+    /// Only tasks in one of these streams (ID or name)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub streams: Vec<String>,
+    /// Only tasks completed with one of these resolutions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolutions: Vec<String>,
+    /// Only tasks with at least one of these tags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl ArchiveRule {
+    /// Archive everything completed at least `days` ago
+    pub fn older_than(days: u32) -> Self {
+        Self {
+            days,
+            ..Default::default()
+        }
+    }
 
-    let mut to_archive: Vec<&Task> = state
+    fn matches(&self, task: &Task, state: &State) -> bool {
+        let cutoff = Utc::now() - chrono::Duration::days(self.days as i64);
+        let in_stream = |wanted: &String| match &task.stream {
+            Some(id) => {
+                id == wanted
+                    || state
+                        .streams
+                        .get(id)
+                        .is_some_and(|s| s.name.eq_ignore_ascii_case(wanted))
+            }
+            None => false,
+        };
+
+        task.status == TaskStatus::Complete
+            && task.archived.is_none()
+            && task.completed.is_some_and(|c| c <= cutoff)
+            && (self.streams.is_empty() || self.streams.iter().any(in_stream))
+            && (self.resolutions.is_empty()
+                || task
+                    .resolution
+                    .as_ref()
+                    .is_some_and(|r| self.resolutions.contains(r)))
+            && (self.tags.is_empty() || task.tags.iter().any(|t| self.tags.contains(t)))
+    }
+}
+
+/// Tasks matching any of `rules`, oldest completion first
+fn select<'a>(state: &'a State, rules: &[ArchiveRule]) -> Vec<&'a Task> {
+    let mut tasks: Vec<&Task> = state
         .tasks
         .values()
-        .filter(|t| {
-            t.status == TaskStatus::Complete
-                && t.completed.is_some_and(|c| c < cutoff)
-                && t.archived.is_none()
-        })
+        .filter(|t| rules.iter().any(|rule| rule.matches(t, state)))
         .collect();
+    tasks.sort_by_key(|t| t.completed);
+    tasks
+}
+
+pub fn archive_tasks<S: EventStore + ?Sized>(
+    store: &S,
+    days: u32,
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    archive_matching(store, &[ArchiveRule::older_than(days)], dry_run)
+}
 
-    to_archive.sort_by_key(|t| t.completed);
+/// Archive completed tasks matching any of `rules`, reporting what was done
+pub fn archive_matching<S: EventStore + ?Sized>(
+    store: &S,
+    rules: &[ArchiveRule],
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let state = materialize(store)?;
+    let to_archive = select(&state, rules);
 
     if to_archive.is_empty() {
+        if !dry_run {
+            // Finish moving events left behind by an interrupted archive
+            let _lock = store.lock()?;
+            let removed = prune_archived_events(store)?;
+            if removed > 0 {
+                println!("Removed {} archived event(s) from events/.", removed);
+            }
+        }
         println!("No tasks to archive.");
         return Ok(Vec::new());
     }
@@ -40,83 +116,267 @@ pub fn archive_tasks(ctx: &SpoolContext, days: u32, dry_run: bool) -> Result<Vec
         return Ok(archived_ids);
     }
 
+    let by_month = move_to_archive(store, &to_archive)?;
+
+    println!("Archived {} tasks.", to_archive.len());
+    for (month, count) in &by_month {
+        println!("  {} tasks to archive/{}.jsonl", count, month);
+    }
+
+    Ok(archived_ids)
+}
+
+/// Archive whatever the repo's retention policy selects
+///
+/// Does nothing when `.spool/config.json` sets no policy. Returns the IDs of
+/// the tasks archived.
+pub fn apply_retention(ctx: &SpoolContext) -> Result<Vec<String>> {
+    let config = Config::load(ctx)?;
+    if config.retention.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let state = materialize(ctx)?;
+    let to_archive = select(&state, &config.retention);
+    if to_archive.is_empty() {
+        return Ok(Vec::new());
+    }
+    move_to_archive(ctx, &to_archive)?;
+    Ok(to_archive.iter().map(|t| t.id.clone()).collect())
+}
+
+/// Move tasks' events into the monthly archive segments for their completion
+/// month; returns how many tasks went to each month
+fn move_to_archive<S: EventStore + ?Sized>(
+    store: &S,
+    tasks: &[&Task],
+) -> Result<BTreeMap<String, usize>> {
     // Group tasks by completion month
     let mut by_month: BTreeMap<String, Vec<&Task>> = BTreeMap::new();
-    for task in &to_archive {
+    for task in tasks {
         if let Some(completed) = task.completed {
             let month = completed.format("%Y-%m").to_string();
             by_month.entry(month).or_default().push(task);
         }
     }
 
-    // Create archive directory if needed
-    fs::create_dir_all(&ctx.archive_dir)?;
+    // Hold the write lock so no events are appended while they're moved
+    let _lock = store.lock()?;
 
-    // Collect all events for archived tasks and write to monthly files
-    let all_events = collect_all_events(ctx)?;
+    // Each task's history ends with a marker recording where it went
+    let branch = get_current_branch()?;
+    let markers: Vec<Event> = by_month
+        .iter()
+        .flat_map(|(month, tasks)| {
+            let branch = &branch;
+            tasks.iter().map(move |task| {
+                let payload = Payload::Archive(ArchivePayload {
+                    r#ref: Some(month.clone()),
+                    ..Default::default()
+                });
+                Event::new(&task.id, payload, "@spool", branch)
+            })
+        })
+        .collect();
+    let segment = todays_segment(store, "@spool")?;
+    let mut markers: HashMap<String, Event> = stamp_events(store, &segment, &markers)?
+        .into_iter()
+        .map(|event| (event.id.clone(), event))
+        .collect();
 
+    // Copy the live events to the monthly segments first, then remove them
+    // from events/. An interrupted archive leaves events in both places,
+    // which replay ignores and the next archive cleans up.
+    let live = live_events(store)?;
     for (month, tasks) in &by_month {
-        let archive_file = ctx.archive_dir.join(format!("{}.jsonl", month));
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&archive_file)?;
-        let mut writer = BufWriter::new(file);
-
+        let segment = archive_segment(month);
+        let present = event_hashes(store, &segment)?;
+        let mut lines = String::new();
         for task in tasks {
-            if let Some(events) = all_events.get(&task.id) {
-                for event in events {
-                    let json = serde_json::to_string(event)?;
-                    writeln!(writer, "{}", json)?;
+            let history = live.get(&task.id).into_iter().flatten();
+            for event in history.chain(markers.remove(&task.id).as_ref()) {
+                if !present.contains(&event.content_hash()) {
+                    lines.push_str(&serde_json::to_string(event)?);
+                    lines.push('\n');
                 }
             }
         }
-        writer.flush()?;
+        store.append(&segment, lines.as_bytes())?;
     }
+    prune_archived_events(store)?;
 
-    // Emit archive events to today's event file
-    let today = Utc::now().format("%Y-%m-%d").to_string();
-    let event_file = ctx.events_dir.join(format!("{}.jsonl", today));
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&event_file)?;
-    let mut writer = BufWriter::new(file);
+    Ok(by_month
+        .into_iter()
+        .map(|(month, tasks)| (month, tasks.len()))
+        .collect())
+}
 
-    let branch = get_current_branch()?;
+/// Move an archived task's events back into `events/`
+///
+/// Events return to the segment for their day (and author, with the
+/// `author` layout), and the task's archive markers are dropped, so it's
+/// live again as if never archived. Like archiving, events are copied before
+/// they're removed; an interrupted unarchive leaves the task archived and
+/// can be re-run. Returns the number of events restored.
+pub fn unarchive_task<S: EventStore + ?Sized>(store: &S, id: &str) -> Result<usize> {
+    let state = materialize(store)?;
+    let task = state
+        .tasks
+        .get(id)
+        .ok_or_else(|| anyhow!("Task not found: {}", id))?;
+    if task.archived.is_none() {
+        return Err(anyhow!("Task {} is not archived", id));
+    }
 
-    for task in &to_archive {
-        if let Some(completed) = task.completed {
-            let month = completed.format("%Y-%m").to_string();
-            let archive_event = Event {
-                v: 1,
-                op: Operation::Archive,
-                id: task.id.clone(),
-                ts: Utc::now(),
-                by: "@spool".to_string(),
-                branch: branch.clone(),
-                d: serde_json::json!({ "ref": month }),
-            };
-            let json = serde_json::to_string(&archive_event)?;
-            writeln!(writer, "{}", json)?;
+    let _lock = store.lock()?;
+
+    let layout = store.layout()?;
+    let is_marker = |event: &Event| event.id == id && event.op == Operation::Archive;
+    let mut by_segment: BTreeMap<String, Vec<Event>> = BTreeMap::new();
+    for segment in segments_in(store, ARCHIVE)? {
+        let (events, _) = store.read_events(&segment)?;
+        for event in events {
+            if event.id == id && !is_marker(&event) {
+                let day = event.ts.format("%Y-%m-%d").to_string();
+                let segment = layout.segment(&day, &event.by);
+                by_segment.entry(segment).or_default().push(event);
+            }
         }
     }
-    writer.flush()?;
 
-    println!("Archived {} tasks.", to_archive.len());
-    for (month, tasks) in &by_month {
-        println!("  {} tasks to archive/{}.jsonl", tasks.len(), month);
+    let mut restored = 0;
+    for (segment, events) in &by_segment {
+        let present = event_hashes(store, segment)?;
+        let mut lines = String::new();
+        for event in events {
+            if !present.contains(&event.content_hash()) {
+                lines.push_str(&serde_json::to_string(event)?);
+                lines.push('\n');
+                restored += 1;
+            }
+        }
+        store.append(segment, lines.as_bytes())?;
     }
 
-    Ok(archived_ids)
+    // Markers written to events/ by older versions go too
+    for segment in segments_in(store, EVENTS)? {
+        rewrite_without(store, &segment, is_marker)?;
+    }
+    for segment in segments_in(store, ARCHIVE)? {
+        rewrite_without(store, &segment, |event| event.id == id)?;
+    }
+
+    Ok(restored)
+}
+
+/// Remove events from `events/` that have been copied to `archive/`
+///
+/// Completes an archive that was interrupted after copying, and cleans up
+/// repos archived by older versions, which left the originals in place.
+/// Returns the number of events removed.
+pub fn prune_archived_events<S: EventStore + ?Sized>(store: &S) -> Result<usize> {
+    let mut archived = HashSet::new();
+    for segment in segments_in(store, ARCHIVE)? {
+        archived.extend(event_hashes(store, &segment)?);
+    }
+    if archived.is_empty() {
+        return Ok(0);
+    }
+
+    let mut removed = 0;
+    for segment in segments_in(store, EVENTS)? {
+        removed += rewrite_without(store, &segment, |event| {
+            archived.contains(&event.content_hash())
+        })?;
+    }
+    Ok(removed)
+}
+
+/// Keys of the segments under `prefix`, in replay order
+fn segments_in<S: EventStore + ?Sized>(store: &S, prefix: &str) -> Result<Vec<String>> {
+    Ok(store
+        .segments()?
+        .into_iter()
+        .filter(|key| segment_name(key, prefix).is_some())
+        .collect())
+}
+
+/// Events in `events/`, by task, in replay order
+fn live_events<S: EventStore + ?Sized>(store: &S) -> Result<HashMap<String, Vec<Event>>> {
+    let mut events_by_task: HashMap<String, Vec<Event>> = HashMap::new();
+    let mut seen = HashSet::new();
+    for segment in segments_in(store, EVENTS)? {
+        let (events, _) = store.read_events(&segment)?;
+        for event in events {
+            if seen.insert(event.content_hash()) {
+                events_by_task
+                    .entry(event.id.clone())
+                    .or_default()
+                    .push(event);
+            }
+        }
+    }
+    // A day's shards each hold part of a task's history
+    for events in events_by_task.values_mut() {
+        sort_events(events);
+    }
+    Ok(events_by_task)
+}
+
+fn event_hashes<S: EventStore + ?Sized>(store: &S, segment: &str) -> Result<HashSet<u64>> {
+    if !store.segments()?.iter().any(|key| key == segment) {
+        return Ok(HashSet::new());
+    }
+    let (events, _) = store.read_events(segment)?;
+    Ok(events.iter().map(Event::content_hash).collect())
+}
+
+/// Rewrite a segment without the events matching `remove`, deleting it if
+/// nothing is left
+///
+/// Lines that don't parse are kept for `spool repair`. Returns the number of
+/// events removed.
+fn rewrite_without<S: EventStore + ?Sized>(
+    store: &S,
+    segment: &str,
+    remove: impl Fn(&Event) -> bool,
+) -> Result<usize> {
+    let content = String::from_utf8(store.read(segment)?)
+        .with_context(|| format!("Failed to read {}", segment))?;
+    let mut kept = String::with_capacity(content.len());
+    let mut removed = 0;
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if Event::from_json(line).is_ok_and(|event| remove(&event)) {
+            removed += 1;
+        } else {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+
+    if removed > 0 {
+        store.replace(segment, kept.as_bytes())?;
+    }
+    Ok(removed)
 }
 
-pub fn collect_all_events(ctx: &SpoolContext) -> Result<HashMap<String, Vec<Event>>> {
+pub fn collect_all_events<S: EventStore + ?Sized>(
+    store: &S,
+) -> Result<HashMap<String, Vec<Event>>> {
     let mut events_by_task: HashMap<String, Vec<Event>> = HashMap::new();
+    let mut seen = HashSet::new();
 
-    for file in ctx.get_event_files()? {
-        let events = ctx.parse_events_from_file(&file)?;
+    // Archived tasks' histories live in archive/, replayed first
+    for segment in store.segments()? {
+        let (events, _) = store.read_events(&segment)?;
         for event in events {
+            // Skip lines duplicated by cherry-picks or merges
+            if !seen.insert(event.content_hash()) {
+                continue;
+            }
             events_by_task
                 .entry(event.id.clone())
                 .or_default()
@@ -126,3 +386,18 @@ pub fn collect_all_events(ctx: &SpoolContext) -> Result<HashMap<String, Vec<Even
 
     Ok(events_by_task)
 }
+
+/// One task's events, reading only the segments that hold them
+pub fn collect_task_events<S: EventStore + ?Sized>(store: &S, id: &str) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut seen = HashSet::new();
+    for segment in store.task_segments(id)? {
+        let (segment_events, _) = store.read_events(&segment)?;
+        for event in segment_events {
+            if event.id == id && seen.insert(event.content_hash()) {
+                events.push(event);
+            }
+        }
+    }
+    Ok(events)
+}
@@ -1,15 +1,29 @@
-use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
-
-use crate::archive::collect_all_events;
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::PathBuf;
+
+use crate::archive::collect_task_events;
+use crate::concurrency::{break_lock, get_task_version, Unlocked, Version};
+use crate::conflict::{resolution_payload, Conflict};
 use crate::context::SpoolContext;
-use crate::state::{load_or_materialize_state, Task, TaskStatus};
+use crate::deps::{ready_tasks, Hierarchy, LinkGraph, Progress, Relation};
+use crate::event::Event;
+use crate::payload::{
+    AssignPayload, CommentPayload, CompletePayload, DeleteCommentPayload, EditCommentPayload,
+    Payload, ReopenPayload, SetStreamPayload, UpdatePayload,
+};
+use crate::query::{ArchivedFilter, TaskQuery, TaskSource};
+use crate::state::{load_or_materialize_state, Comment, State, Task, TaskStatus};
 use crate::writer::{
-    assign_task as write_assign, complete_task as write_complete,
-    create_stream as write_create_stream, create_task as write_create,
-    delete_stream as write_delete_stream, get_current_branch, get_current_user,
-    reopen_task as write_reopen, set_stream as write_stream, update_stream as write_update_stream,
-    update_task as write_update, CreateTaskParams,
+    claim_next as write_claim_next, create_stream as write_create_stream,
+    create_task as write_create, delete_stream as write_delete_stream, get_current_branch,
+    get_current_user, link_tasks as write_link, set_stream as write_stream, tag_task as write_tag,
+    unlink_tasks as write_unlink, update_stream as write_update_stream, write_event_checked,
+    CreateTaskParams, Transaction,
 };
 
 #[derive(Parser)]
@@ -23,7 +37,14 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Initialize .spool/ directory structure
-    Init,
+    Init {
+        /// Register the event log merge driver in .gitattributes and git config
+        #[arg(long)]
+        git: bool,
+        /// Keep events on a git ref (refs/spool/events) instead of the working tree
+        #[arg(long)]
+        events_ref: bool,
+    },
     /// Create a new task
     Add {
         /// Task title
@@ -67,6 +88,12 @@ pub enum Commands {
         /// Show only tasks without a stream
         #[arg(long, conflicts_with_all = ["stream", "stream_name"])]
         no_stream: bool,
+        /// Show only archived tasks (of any status unless --status complete)
+        #[arg(long, conflicts_with = "include_archived")]
+        archived: bool,
+        /// Show archived tasks along with live ones
+        #[arg(long)]
+        include_archived: bool,
         /// Output format: table, json, or ids
         #[arg(short, long, default_value = "table")]
         format: String,
@@ -81,20 +108,84 @@ pub enum Commands {
     },
     /// Rebuild .index.json and .state.json from events
     Rebuild,
+    /// Snapshot the current state so replay can start from it
+    Compact,
+    /// Fetch, merge and push the events ref (set up by `spool init --events-ref`)
+    Sync {
+        /// Remote to sync with
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
     /// Archive completed tasks older than N days
     Archive {
-        /// Days after completion to archive (default: 30)
-        #[arg(short, long, default_value = "30")]
-        days: u32,
+        /// Days after completion to archive (default: 30, or 0 with --stream,
+        /// --resolution or --tag)
+        #[arg(short, long)]
+        days: Option<u32>,
+        /// Only tasks in this stream (ID or name, repeatable)
+        #[arg(long = "stream")]
+        streams: Vec<String>,
+        /// Only tasks completed with this resolution (repeatable)
+        #[arg(long = "resolution")]
+        resolutions: Vec<String>,
+        /// Only tasks with this tag (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Apply the retention policy from .spool/config.json instead
+        #[arg(long, conflicts_with_all = ["days", "streams", "resolutions", "tags"])]
+        policy: bool,
         /// Show what would be archived without doing it
         #[arg(long)]
         dry_run: bool,
     },
+    /// Move an archived task's events back into events/
+    Unarchive {
+        /// Task ID to restore
+        id: String,
+    },
     /// Validate event files for correctness
     Validate {
         /// Fail on warnings too
         #[arg(long)]
         strict: bool,
+        /// Rewrite event files without duplicate events
+        #[arg(long)]
+        dedupe: bool,
+    },
+    /// Move corrupt lines out of event files into .spool/rejected/
+    Repair {
+        /// Show what would be quarantined without doing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show concurrent edits to the same task field, or resolve one
+    Conflicts {
+        /// Only show conflicts for this task
+        id: Option<String>,
+        /// Resolve the conflict on this field by writing a superseding event
+        #[arg(long, requires = "id")]
+        resolve: Option<String>,
+        /// Event ID (or prefix) of the write to keep (default: the applied one)
+        #[arg(long, requires = "resolve")]
+        keep: Option<String>,
+        /// Output format: table or json
+        #[arg(short, long, default_value = "table")]
+        format: String,
+    },
+    /// Remove the write lock left by a hung or crashed process
+    Unlock {
+        /// Remove it even if the holder may still be running
+        #[arg(long)]
+        force: bool,
+    },
+    /// Git merge driver for event files (registered by `spool init --git`)
+    MergeDriver {
+        /// Common ancestor version (%O)
+        base: PathBuf,
+        /// Current branch version, overwritten with the result (%A)
+        ours: PathBuf,
+        /// Other branch version (%B)
+        theirs: PathBuf,
     },
     /// Mark a task as complete
     Complete {
@@ -103,11 +194,20 @@ pub enum Commands {
         /// Resolution: done, wontfix, duplicate, obsolete
         #[arg(short, long, default_value = "done")]
         resolution: String,
+        /// Complete it even if some of its subtasks are still open
+        #[arg(long)]
+        force: bool,
+        /// Only write if the task's version (shown by `spool show`) still matches
+        #[arg(long, value_name = "HASH")]
+        if_version: Option<String>,
     },
     /// Reopen a completed task
     Reopen {
         /// Task ID to reopen
         id: String,
+        /// Only write if the task's version (shown by `spool show`) still matches
+        #[arg(long, value_name = "HASH")]
+        if_version: Option<String>,
     },
     /// Update a task's fields
     Update {
@@ -125,6 +225,9 @@ pub enum Commands {
         /// Move to stream (use "" to remove from stream)
         #[arg(long)]
         stream: Option<String>,
+        /// Only write if the task's version (shown by `spool show`) still matches
+        #[arg(long, value_name = "HASH")]
+        if_version: Option<String>,
     },
     /// Assign a task to a user
     Assign {
@@ -132,11 +235,93 @@ pub enum Commands {
         id: String,
         /// Assignee (@username)
         assignee: String,
+        /// Only write if the task's version (shown by `spool show`) still matches
+        #[arg(long, value_name = "HASH")]
+        if_version: Option<String>,
     },
     /// Assign a task to yourself
     Claim {
         /// Task ID to claim
         id: String,
+        /// Only write if the task's version (shown by `spool show`) still matches
+        #[arg(long, value_name = "HASH")]
+        if_version: Option<String>,
+    },
+    /// Comment on a task, or edit or delete a comment
+    Comment {
+        /// Task ID to comment on
+        id: String,
+        /// Comment text; read from stdin or $EDITOR when omitted
+        body: Option<String>,
+        /// Commit SHA or URL the comment refers to
+        #[arg(long = "ref", value_name = "SHA|URL")]
+        reference: Option<String>,
+        /// Replace the text of a comment (ID prefix shown by `spool show`)
+        #[arg(long, value_name = "COMMENT", conflicts_with = "delete")]
+        edit: Option<String>,
+        /// Delete a comment (ID prefix shown by `spool show`)
+        #[arg(long, value_name = "COMMENT", conflicts_with_all = ["body", "reference"])]
+        delete: Option<String>,
+    },
+    /// Add (+TAG) or remove (-TAG) tags on a task
+    Tag {
+        /// Task ID to tag
+        id: String,
+        /// Tags to add (+bug, or just bug) and remove (-wip)
+        #[arg(required = true, allow_hyphen_values = true, value_name = "+TAG|-TAG")]
+        changes: Vec<String>,
+    },
+    /// List all tags with the number of tasks that have them
+    Tags {
+        /// Output format: table, json, or ids
+        #[arg(short, long, default_value = "table")]
+        format: String,
+    },
+    /// Link a task to another; the other task records the inverse link
+    Link {
+        /// Task ID to link
+        id: String,
+        #[command(flatten)]
+        link: LinkArgs,
+    },
+    /// Remove a link between two tasks
+    Unlink {
+        /// Task ID to unlink
+        id: String,
+        #[command(flatten)]
+        link: LinkArgs,
+    },
+    /// Show the tasks a task is waiting on, transitively
+    Deps {
+        /// Task ID
+        id: String,
+        /// Show the tasks waiting on this one instead
+        #[arg(long)]
+        reverse: bool,
+    },
+    /// Show tasks and their subtasks as a tree
+    Tree {
+        /// Task to show (default: every top-level task with subtasks)
+        id: Option<String>,
+    },
+    /// List open, unassigned tasks that nothing unfinished blocks, best first
+    Ready {
+        #[command(flatten)]
+        filter: QueueArgs,
+        /// Show at most this many tasks
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+        /// Output format: table, json, or ids
+        #[arg(short, long, default_value = "table")]
+        format: String,
+    },
+    /// Show the task to pick up next (the first one `spool ready` lists)
+    Next {
+        #[command(flatten)]
+        filter: QueueArgs,
+        /// Assign it to yourself
+        #[arg(long)]
+        claim: bool,
     },
     /// Manage streams (workstreams/projects)
     Stream {
@@ -147,9 +332,65 @@ pub enum Commands {
     Free {
         /// Task ID to free
         id: String,
+        /// Only write if the task's version (shown by `spool show`) still matches
+        #[arg(long, value_name = "HASH")]
+        if_version: Option<String>,
     },
 }
 
+/// The link named by `spool link` and `spool unlink`
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct LinkArgs {
+    /// This task has to be done before TASK
+    #[arg(long, value_name = "TASK")]
+    pub blocks: Option<String>,
+    /// TASK has to be done before this task
+    #[arg(long, value_name = "TASK")]
+    pub blocked_by: Option<String>,
+    /// TASK is this task's parent
+    #[arg(long, value_name = "TASK")]
+    pub parent: Option<String>,
+}
+
+impl LinkArgs {
+    /// The relation and target task given
+    pub fn link(&self) -> (Relation, &str) {
+        match (&self.blocks, &self.blocked_by, &self.parent) {
+            (Some(target), _, _) => (Relation::Blocks, target),
+            (_, Some(target), _) => (Relation::BlockedBy, target),
+            (_, _, Some(target)) => (Relation::Parent, target),
+            // clap requires exactly one of them
+            _ => unreachable!("no link given"),
+        }
+    }
+}
+
+/// Filters for `spool ready` and `spool next`
+#[derive(Args)]
+pub struct QueueArgs {
+    /// Only tasks with this tag
+    #[arg(short, long)]
+    pub tag: Option<String>,
+    /// Only tasks with this priority
+    #[arg(short, long)]
+    pub priority: Option<String>,
+    /// Only tasks in this stream (ID)
+    #[arg(long)]
+    pub stream: Option<String>,
+}
+
+impl QueueArgs {
+    pub fn query(&self) -> TaskQuery {
+        TaskQuery {
+            tag: self.tag.clone(),
+            priority: self.priority.clone(),
+            stream: self.stream.clone(),
+            ..Default::default()
+        }
+    }
+}
+
 /// Stream subcommands for managing workstreams/projects
 #[derive(Subcommand)]
 pub enum StreamCommands {
@@ -211,6 +452,25 @@ impl OutputFormat {
     }
 }
 
+/// Load state, surfacing any materialization warnings on stderr
+fn load_state(ctx: &SpoolContext) -> Result<State> {
+    let state = load_or_materialize_state(ctx)?;
+    print_warnings(&state.warnings, state.conflicts.len());
+    Ok(state)
+}
+
+fn print_warnings(warnings: &[String], conflicts: usize) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if conflicts > 0 {
+        eprintln!(
+            "Warning: {} conflicting edit(s) from concurrent branches (run 'spool conflicts' to review)",
+            conflicts
+        );
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn list_tasks(
     ctx: &SpoolContext,
@@ -221,71 +481,95 @@ pub fn list_tasks(
     stream: Option<&str>,
     stream_name: Option<&str>,
     no_stream: bool,
+    archived: bool,
+    include_archived: bool,
     format: OutputFormat,
 ) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+    let source = TaskSource::open(ctx)?;
+    print_warnings(&source.warnings()?, source.conflicts()?);
+
+    // Archived tasks are all complete, so the default open filter would hide
+    // every one of them
+    let status_filter = match status_filter {
+        Some("open") if archived => None,
+        other => other,
+    };
 
     // Resolve stream_name to stream ID if provided
-    let stream_id_from_name: Option<String> = stream_name.and_then(|name| {
-        state
-            .streams
-            .iter()
+    let stream_id_from_name: Option<String> = match stream_name {
+        Some(name) => source
+            .streams()?
+            .into_iter()
             .find(|(_, s)| s.name.eq_ignore_ascii_case(name))
-            .map(|(id, _)| id.clone())
-    });
-
-    // Use stream ID directly, or resolved from name
-    let effective_stream = stream.map(String::from).or(stream_id_from_name);
-
-    let mut tasks: Vec<&Task> = state
-        .tasks
-        .values()
-        .filter(|t| {
-            // Status filter
-            let status_match = match status_filter {
-                Some("open") => t.status == TaskStatus::Open,
-                Some("complete") => t.status == TaskStatus::Complete,
-                Some("all") | None => true,
-                _ => true,
-            };
-
-            // Assignee filter
-            let assignee_match = assignee
-                .map(|a| t.assignee.as_deref() == Some(a))
-                .unwrap_or(true);
-
-            // Tag filter
-            let tag_match = tag.map(|tg| t.tags.iter().any(|t| t == tg)).unwrap_or(true);
-
-            // Priority filter
-            let priority_match = priority
-                .map(|p| t.priority.as_deref() == Some(p))
-                .unwrap_or(true);
-
-            // Stream filter (--stream, --stream-name, and --no-stream are mutually exclusive)
-            let stream_match = if no_stream {
-                t.stream.is_none()
-            } else {
-                effective_stream
-                    .as_ref()
-                    .map(|s| t.stream.as_deref() == Some(s.as_str()))
-                    .unwrap_or(true)
-            };
+            .map(|(id, _)| id),
+        None => None,
+    };
 
-            status_match && assignee_match && tag_match && priority_match && stream_match
-        })
-        .collect();
+    let query = TaskQuery {
+        status: match status_filter {
+            Some("open") => Some(TaskStatus::Open),
+            Some("complete") => Some(TaskStatus::Complete),
+            _ => None,
+        },
+        assignee: assignee.map(String::from),
+        tag: tag.map(String::from),
+        priority: priority.map(String::from),
+        // Use stream ID directly, or resolved from name
+        stream: stream.map(String::from).or(stream_id_from_name),
+        no_stream,
+        archived: if archived {
+            ArchivedFilter::Only
+        } else if include_archived {
+            ArchivedFilter::Include
+        } else {
+            ArchivedFilter::Exclude
+        },
+        search: None,
+    };
+    let tasks = source.tasks(&query)?;
+    let hierarchy = match format {
+        OutputFormat::Json => Some(source.hierarchy()?),
+        _ => None,
+    };
+    print_tasks(&tasks, format, hierarchy.as_ref())
+}
 
-    // Sort by created date
-    tasks.sort_by_key(|t| t.created);
+/// A task in `-f json` output, with its place in the hierarchy
+#[derive(Serialize)]
+struct TaskJson<'a> {
+    #[serde(flatten)]
+    task: &'a Task,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    children: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<Progress>,
+}
 
+/// Print tasks as a table, JSON or IDs
+///
+/// With a `hierarchy`, each task in the JSON output also lists its
+/// `children` and their `progress`, if it has any.
+fn print_tasks(tasks: &[Task], format: OutputFormat, hierarchy: Option<&Hierarchy>) -> Result<()> {
     match format {
         OutputFormat::Json => {
-            let json = serde_json::to_string_pretty(&tasks)?;
+            let values: Vec<TaskJson> = tasks
+                .iter()
+                .map(|task| {
+                    let children = hierarchy.map_or(&[][..], |h| h.children(&task.id));
+                    TaskJson {
+                        task,
+                        children,
+                        progress: hierarchy
+                            .filter(|_| !children.is_empty())
+                            .map(|h| h.progress(&task.id)),
+                    }
+                })
+                .collect();
+            let json = serde_json::to_string_pretty(&values)?;
             println!("{}", json);
         }
         OutputFormat::Ids => {
-            for task in &tasks {
+            for task in tasks {
                 println!("{}", task.id);
             }
         }
@@ -296,7 +580,7 @@ pub fn list_tasks(
             }
 
             println!("{:<15} {:<10} {:<12} TITLE", "ID", "PRIORITY", "ASSIGNEE");
-            for task in &tasks {
+            for task in tasks {
                 let priority = task.priority.as_deref().unwrap_or("-");
                 let assignee = task.assignee.as_deref().unwrap_or("-");
                 let title = if task.title.len() > 50 {
@@ -316,7 +600,7 @@ pub fn list_tasks(
 }
 
 pub fn show_task(ctx: &SpoolContext, id: &str, show_events: bool) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+    let state = load_state(ctx)?;
 
     let task = state
         .tasks
@@ -356,6 +640,9 @@ pub fn show_task(ctx: &SpoolContext, id: &str, show_events: bool) -> Result<()>
     if let Some(a) = &task.archived {
         println!("Archived: {}", a);
     }
+    if let Some(version) = get_task_version(ctx, id)? {
+        println!("Version:  {}", version.last_event_hash);
+    }
     if let Some(p) = &task.parent {
         println!("Parent:   {}", p);
     }
@@ -365,11 +652,44 @@ pub fn show_task(ctx: &SpoolContext, id: &str, show_events: bool) -> Result<()>
     if !task.blocked_by.is_empty() {
         println!("Blocked by: {}", task.blocked_by.join(", "));
     }
+    let hierarchy = Hierarchy::new(state.tasks.values());
+    let children = hierarchy.children(&task.id);
+    if !children.is_empty() {
+        println!("Subtasks: {} complete", hierarchy.progress(&task.id));
+        for child in children {
+            println!("  {}", tree_label(&state, &hierarchy, child));
+        }
+    }
+
+    let conflicts: Vec<&str> = state
+        .conflicts
+        .iter()
+        .filter(|c| c.task == task.id)
+        .map(|c| c.field.as_str())
+        .collect();
+    if !conflicts.is_empty() {
+        println!(
+            "Conflicts: {} (run 'spool conflicts {}')",
+            conflicts.join(", "),
+            task.id
+        );
+    }
 
     if !task.comments.is_empty() {
         println!("\nComments:");
         for comment in &task.comments {
-            println!("  [{} - {}]", comment.ts, comment.by);
+            let edited = if comment.edited.is_some() {
+                " (edited)"
+            } else {
+                ""
+            };
+            println!(
+                "  [{} {} - {}]{}",
+                comment.id.get(..8).unwrap_or(&comment.id),
+                comment.ts,
+                comment.by,
+                edited
+            );
             println!("  {}", comment.body.replace('\n', "\n  "));
             if let Some(r) = &comment.r#ref {
                 println!("  ref: {}", r);
@@ -380,23 +700,154 @@ pub fn show_task(ctx: &SpoolContext, id: &str, show_events: bool) -> Result<()>
 
     if show_events {
         println!("\nEvent History:");
-        let all_events = collect_all_events(ctx)?;
-        if let Some(events) = all_events.get(id) {
-            for event in events {
-                println!(
-                    "  {} {} by {} on {}",
-                    event.ts, event.op, event.by, event.branch
-                );
+        for event in collect_task_events(ctx, id)? {
+            println!(
+                "  {} {} by {} on {}",
+                event.ts, event.op, event.by, event.branch
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Write an event for a task as the current user
+///
+/// With `if_version`, the write only happens if the task's latest event is
+/// still the one with that ID, so scripts can do compare-and-swap updates.
+fn write_task_event(
+    ctx: &SpoolContext,
+    id: &str,
+    payload: Payload,
+    if_version: Option<&str>,
+) -> Result<()> {
+    let user = get_current_user()?;
+    let branch = get_current_branch()?;
+    let expected = if_version.map(Version::from_hash);
+    write_event_checked(
+        ctx,
+        &Event::new(id, payload, &user, &branch),
+        expected.as_ref(),
+    )
+}
+
+pub fn unlock(ctx: &SpoolContext, force: bool) -> Result<()> {
+    match break_lock(ctx, force)? {
+        Unlocked::NotLocked => println!("No lock held"),
+        Unlocked::Removed(Some(holder)) => println!("Removed lock held by {}", holder),
+        Unlocked::Removed(None) => println!("Removed lock"),
+    }
+    Ok(())
+}
+
+pub fn list_conflicts(ctx: &SpoolContext, id: Option<&str>, format: OutputFormat) -> Result<()> {
+    let state = load_or_materialize_state(ctx)?;
+    for warning in &state.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    if let Some(id) = id {
+        if !state.tasks.contains_key(id) {
+            return Err(anyhow!("Task not found: {}", id));
+        }
+    }
+    let conflicts: Vec<&Conflict> = state
+        .conflicts
+        .iter()
+        .filter(|c| id.map_or(true, |id| c.task == id))
+        .collect();
+
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&conflicts)?);
+        }
+        OutputFormat::Ids => {
+            for conflict in &conflicts {
+                println!("{}", conflict.task);
+            }
+        }
+        OutputFormat::Table => {
+            if conflicts.is_empty() {
+                println!("No conflicts.");
+                return Ok(());
+            }
+            for conflict in &conflicts {
+                println!("{} {}:", conflict.task, conflict.field);
+                for (i, write) in conflict.writes.iter().enumerate() {
+                    let applied = if i + 1 == conflict.writes.len() {
+                        " (applied)"
+                    } else {
+                        ""
+                    };
+                    println!(
+                        "  {} {} by {} on {} at {}{}",
+                        write.event.get(..8).unwrap_or(&write.event),
+                        write.value,
+                        write.by,
+                        write.branch,
+                        write.ts,
+                        applied
+                    );
+                }
             }
+            println!("\nResolve with: spool conflicts <id> --resolve <field> [--keep <event>]");
         }
     }
 
     Ok(())
 }
 
-pub fn complete_task(ctx: &SpoolContext, id: &str, resolution: Option<&str>) -> Result<()> {
+pub fn resolve_conflict(
+    ctx: &SpoolContext,
+    id: &str,
+    field: &str,
+    keep: Option<&str>,
+) -> Result<()> {
     let state = load_or_materialize_state(ctx)?;
 
+    let conflict = state
+        .conflicts
+        .iter()
+        .find(|c| c.task == id && c.field == field)
+        .ok_or_else(|| anyhow!("No conflict on {} for task {}", field, id))?;
+
+    let write = match keep {
+        Some(prefix) => {
+            let matches: Vec<_> = conflict
+                .writes
+                .iter()
+                .filter(|w| w.event.starts_with(prefix))
+                .collect();
+            match matches.as_slice() {
+                [write] => *write,
+                [] => return Err(anyhow!("No write {} in conflict on {}", prefix, field)),
+                _ => return Err(anyhow!("Ambiguous event prefix: {}", prefix)),
+            }
+        }
+        None => conflict
+            .writes
+            .last()
+            .ok_or_else(|| anyhow!("Conflict on {} has no writes", field))?,
+    };
+
+    // The writer records every side of the conflict as seen, so this event
+    // supersedes them all
+    let payload = resolution_payload(field, &write.value)?;
+    write_task_event(ctx, id, payload, None)?;
+    println!("Resolved {} on {}: kept {}", field, id, write.value);
+
+    Ok(())
+}
+
+pub fn complete_task(
+    ctx: &SpoolContext,
+    id: &str,
+    resolution: Option<&str>,
+    force: bool,
+    if_version: Option<&str>,
+) -> Result<()> {
+    let state = load_state(ctx)?;
+
     // Verify task exists
     let task = state
         .tasks
@@ -408,17 +859,28 @@ pub fn complete_task(ctx: &SpoolContext, id: &str, resolution: Option<&str>) ->
         return Err(anyhow!("Task is already complete: {}", id));
     }
 
-    let user = get_current_user()?;
-    let branch = get_current_branch()?;
+    let progress = Hierarchy::new(state.tasks.values()).progress(id);
+    if progress.open() > 0 && !force {
+        return Err(anyhow!(
+            "Task {} has {} open subtask(s) (see 'spool tree {}'); use --force to complete it anyway",
+            id,
+            progress.open(),
+            id
+        ));
+    }
 
-    write_complete(ctx, id, resolution, &user, &branch)?;
+    let payload = Payload::Complete(CompletePayload {
+        resolution: Some(resolution.unwrap_or("done").to_string()),
+        ..Default::default()
+    });
+    write_task_event(ctx, id, payload, if_version)?;
     println!("Completed task: {} ({})", id, resolution.unwrap_or("done"));
 
     Ok(())
 }
 
-pub fn reopen_task(ctx: &SpoolContext, id: &str) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+pub fn reopen_task(ctx: &SpoolContext, id: &str, if_version: Option<&str>) -> Result<()> {
+    let state = load_state(ctx)?;
 
     // Verify task exists
     let task = state
@@ -431,10 +893,8 @@ pub fn reopen_task(ctx: &SpoolContext, id: &str) -> Result<()> {
         return Err(anyhow!("Task is already open: {}", id));
     }
 
-    let user = get_current_user()?;
-    let branch = get_current_branch()?;
-
-    write_reopen(ctx, id, &user, &branch)?;
+    let payload = Payload::Reopen(ReopenPayload::default());
+    write_task_event(ctx, id, payload, if_version)?;
     println!("Reopened task: {}", id);
 
     Ok(())
@@ -447,8 +907,9 @@ pub fn update_task(
     description: Option<&str>,
     priority: Option<&str>,
     stream: Option<&str>,
+    if_version: Option<&str>,
 ) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+    let state = load_state(ctx)?;
 
     // Verify task exists
     state
@@ -466,20 +927,47 @@ pub fn update_task(
         }
     }
 
+    // Field and stream changes are separate operations, written together
     let user = get_current_user()?;
     let branch = get_current_branch()?;
+    let expected = if_version.map(Version::from_hash);
+    let mut txn = Transaction::new(ctx);
 
     // Handle field updates
-    if title.is_some() || description.is_some() || priority.is_some() {
-        write_update(ctx, id, title, description, priority, &user, &branch)?;
+    let fields = UpdatePayload {
+        title: title.map(String::from),
+        description: description.map(String::from),
+        priority: priority.map(String::from),
+        ..Default::default()
+    };
+    if !fields.is_empty() {
+        txn.push_checked(
+            Event::new(id, Payload::Update(fields), &user, &branch),
+            expected.as_ref(),
+        );
     }
 
     // Handle stream change separately (different operation type)
     if let Some(s) = stream {
-        let stream_value = if s.is_empty() { None } else { Some(s) };
-        write_stream(ctx, id, stream_value, &user, &branch)?;
+        let payload = Payload::SetStream(SetStreamPayload {
+            stream: if s.is_empty() {
+                None
+            } else {
+                Some(s.to_string())
+            },
+            ..Default::default()
+        });
+        // The version check applies once, to the first event
+        let expected = if txn.is_empty() {
+            expected.as_ref()
+        } else {
+            None
+        };
+        txn.push_checked(Event::new(id, payload, &user, &branch), expected);
     }
 
+    txn.commit()?;
+
     let mut updates = Vec::new();
     if title.is_some() {
         updates.push("title");
@@ -509,7 +997,7 @@ pub fn add_task(
 ) -> Result<()> {
     // If setting a stream, verify it exists
     if let Some(s) = stream {
-        let state = load_or_materialize_state(ctx)?;
+        let state = load_state(ctx)?;
         if !state.streams.contains_key(s) {
             return Err(anyhow!(
                 "Stream not found: {}. Use 'spool stream add' to create it first.",
@@ -539,8 +1027,13 @@ pub fn add_task(
     Ok(())
 }
 
-pub fn assign_task(ctx: &SpoolContext, id: &str, assignee: &str) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+pub fn assign_task(
+    ctx: &SpoolContext,
+    id: &str,
+    assignee: &str,
+    if_version: Option<&str>,
+) -> Result<()> {
+    let state = load_state(ctx)?;
 
     // Verify task exists
     state
@@ -548,17 +1041,18 @@ pub fn assign_task(ctx: &SpoolContext, id: &str, assignee: &str) -> Result<()> {
         .get(id)
         .ok_or_else(|| anyhow!("Task not found: {}", id))?;
 
-    let user = get_current_user()?;
-    let branch = get_current_branch()?;
-
-    write_assign(ctx, id, Some(assignee), &user, &branch)?;
+    let payload = Payload::Assign(AssignPayload {
+        to: Some(assignee.to_string()),
+        ..Default::default()
+    });
+    write_task_event(ctx, id, payload, if_version)?;
     println!("Assigned task {} to {}", id, assignee);
 
     Ok(())
 }
 
-pub fn claim_task(ctx: &SpoolContext, id: &str) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+pub fn claim_task(ctx: &SpoolContext, id: &str, if_version: Option<&str>) -> Result<()> {
+    let state = load_state(ctx)?;
 
     // Verify task exists
     state
@@ -567,16 +1061,190 @@ pub fn claim_task(ctx: &SpoolContext, id: &str) -> Result<()> {
         .ok_or_else(|| anyhow!("Task not found: {}", id))?;
 
     let user = get_current_user()?;
-    let branch = get_current_branch()?;
-
-    write_assign(ctx, id, Some(&user), &user, &branch)?;
+    let payload = Payload::Assign(AssignPayload {
+        to: Some(user.clone()),
+        ..Default::default()
+    });
+    write_task_event(ctx, id, payload, if_version)?;
     println!("Claimed task {} (assigned to {})", id, user);
 
     Ok(())
 }
 
-pub fn free_task(ctx: &SpoolContext, id: &str) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+pub fn free_task(ctx: &SpoolContext, id: &str, if_version: Option<&str>) -> Result<()> {
+    let state = load_state(ctx)?;
+
+    // Verify task exists
+    state
+        .tasks
+        .get(id)
+        .ok_or_else(|| anyhow!("Task not found: {}", id))?;
+
+    let payload = Payload::Assign(AssignPayload::default());
+    write_task_event(ctx, id, payload, if_version)?;
+    println!("Freed task {} (unassigned)", id);
+
+    Ok(())
+}
+
+pub fn comment_task(
+    ctx: &SpoolContext,
+    id: &str,
+    body: Option<&str>,
+    reference: Option<&str>,
+) -> Result<()> {
+    let state = load_state(ctx)?;
+
+    // Verify task exists
+    state
+        .tasks
+        .get(id)
+        .ok_or_else(|| anyhow!("Task not found: {}", id))?;
+
+    let payload = Payload::Comment(CommentPayload {
+        body: read_comment(body, "")?,
+        r#ref: reference.map(String::from),
+        ..Default::default()
+    });
+    write_task_event(ctx, id, payload, None)?;
+    println!("Commented on task: {}", id);
+
+    Ok(())
+}
+
+pub fn edit_comment(
+    ctx: &SpoolContext,
+    id: &str,
+    comment: &str,
+    body: Option<&str>,
+    reference: Option<&str>,
+) -> Result<()> {
+    let state = load_state(ctx)?;
+    let task = state
+        .tasks
+        .get(id)
+        .ok_or_else(|| anyhow!("Task not found: {}", id))?;
+    let comment = find_comment(task, comment)?;
+
+    let payload = Payload::EditComment(EditCommentPayload {
+        comment: comment.id.clone(),
+        body: read_comment(body, &comment.body)?,
+        r#ref: reference.map(String::from),
+        ..Default::default()
+    });
+    write_task_event(ctx, id, payload, None)?;
+    println!(
+        "Edited comment {} on task: {}",
+        comment.id.get(..8).unwrap_or(&comment.id),
+        id
+    );
+
+    Ok(())
+}
+
+pub fn delete_comment(ctx: &SpoolContext, id: &str, comment: &str) -> Result<()> {
+    let state = load_state(ctx)?;
+    let task = state
+        .tasks
+        .get(id)
+        .ok_or_else(|| anyhow!("Task not found: {}", id))?;
+    let comment = find_comment(task, comment)?;
+
+    let payload = Payload::DeleteComment(DeleteCommentPayload {
+        comment: comment.id.clone(),
+        ..Default::default()
+    });
+    write_task_event(ctx, id, payload, None)?;
+    println!(
+        "Deleted comment {} on task: {}",
+        comment.id.get(..8).unwrap_or(&comment.id),
+        id
+    );
+
+    Ok(())
+}
+
+/// A task's comment by ID prefix
+fn find_comment<'a>(task: &'a Task, prefix: &str) -> Result<&'a Comment> {
+    let matches: Vec<&Comment> = task
+        .comments
+        .iter()
+        .filter(|c| c.id.len() >= 8 && c.id.starts_with(prefix))
+        .collect();
+    match matches.as_slice() {
+        [comment] => Ok(comment),
+        [] => Err(anyhow!("No comment {} on task {}", prefix, task.id)),
+        _ => Err(anyhow!("Ambiguous comment prefix: {}", prefix)),
+    }
+}
+
+/// Comment text from the command line, or else piped to stdin, or else
+/// written in `$EDITOR` (starting from `initial`)
+fn read_comment(given: Option<&str>, initial: &str) -> Result<String> {
+    let text = match given {
+        Some(text) => text.to_string(),
+        None if !io::stdin().is_terminal() => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+        None => edit_in_editor(initial)?,
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(anyhow!("Aborting: empty comment"));
+    }
+    Ok(text.to_string())
+}
+
+/// Marks the end of the comment in the file given to `$EDITOR`
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+fn edit_in_editor(initial: &str) -> Result<String> {
+    let editor = std::env::var("EDITOR")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .ok_or_else(|| anyhow!("No comment given and $EDITOR is not set"))?;
+    run_editor(&editor, initial)
+}
+
+/// Text above the scissors line after `editor` edits a file holding `initial`
+fn run_editor(editor: &str, initial: &str) -> Result<String> {
+    let file = tempfile::Builder::new()
+        .prefix("spool-comment-")
+        .suffix(".md")
+        .tempfile()
+        .context("Failed to create a file for the editor")?;
+    fs::write(
+        file.path(),
+        format!(
+            "{}\n{}\n# Write the comment above the scissors; everything below them is ignored.\n",
+            initial, SCISSORS
+        ),
+    )?;
+
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .with_context(|| format!("Failed to run editor: {}", editor))?;
+    if !status.success() {
+        return Err(anyhow!("Editor exited with {}", status));
+    }
+
+    let text = fs::read_to_string(file.path())?;
+    Ok(text
+        .lines()
+        .take_while(|line| *line != SCISSORS)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Add and remove tags on a task from `+tag`/`-tag` arguments
+pub fn tag_task(ctx: &SpoolContext, id: &str, changes: &[String]) -> Result<()> {
+    let state = load_state(ctx)?;
 
     // Verify task exists
     state
@@ -584,11 +1252,281 @@ pub fn free_task(ctx: &SpoolContext, id: &str) -> Result<()> {
         .get(id)
         .ok_or_else(|| anyhow!("Task not found: {}", id))?;
 
+    let (add, remove) = parse_tag_changes(changes)?;
     let user = get_current_user()?;
     let branch = get_current_branch()?;
+    write_tag(ctx, id, &add, &remove, &user, &branch)?;
+    println!("Tagged task {}: {}", id, changes.join(" "));
 
-    write_assign(ctx, id, None, &user, &branch)?;
-    println!("Freed task {} (unassigned)", id);
+    Ok(())
+}
+
+/// Split `+tag`/`tag` and `-tag` arguments into tags to add and to remove
+fn parse_tag_changes(changes: &[String]) -> Result<(Vec<String>, Vec<String>)> {
+    let mut add: Vec<String> = Vec::new();
+    let mut remove: Vec<String> = Vec::new();
+    for change in changes {
+        let (list, tag) = match change.strip_prefix('-') {
+            Some(tag) => (&mut remove, tag),
+            None => (&mut add, change.strip_prefix('+').unwrap_or(change)),
+        };
+        if tag.is_empty() {
+            return Err(anyhow!("Empty tag in '{}'", change));
+        }
+        if !list.iter().any(|t| t == tag) {
+            list.push(tag.to_string());
+        }
+    }
+    if let Some(tag) = add.iter().find(|tag| remove.contains(tag)) {
+        return Err(anyhow!("Tag '{}' is both added and removed", tag));
+    }
+    Ok((add, remove))
+}
+
+/// Number of live tasks with a tag
+#[derive(Serialize)]
+struct TagCount {
+    tag: String,
+    open: usize,
+    total: usize,
+}
+
+pub fn list_tags(ctx: &SpoolContext, format: OutputFormat) -> Result<()> {
+    let source = TaskSource::open(ctx)?;
+    print_warnings(&source.warnings()?, source.conflicts()?);
+
+    let mut counts: HashMap<String, TagCount> = HashMap::new();
+    for task in source.tasks(&TaskQuery::default())? {
+        for tag in task.tags {
+            let count = counts.entry(tag.clone()).or_insert(TagCount {
+                tag,
+                open: 0,
+                total: 0,
+            });
+            if task.status == TaskStatus::Open {
+                count.open += 1;
+            }
+            count.total += 1;
+        }
+    }
+    let mut tags: Vec<TagCount> = counts.into_values().collect();
+    tags.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.tag.cmp(&b.tag)));
+
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&tags)?);
+        }
+        OutputFormat::Ids => {
+            for count in &tags {
+                println!("{}", count.tag);
+            }
+        }
+        OutputFormat::Table => {
+            if tags.is_empty() {
+                println!("No tags found.");
+                return Ok(());
+            }
+
+            println!("{:<20} {:>6} {:>6}", "TAG", "OPEN", "TOTAL");
+            for count in &tags {
+                println!("{:<20} {:>6} {:>6}", count.tag, count.open, count.total);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn link_task(ctx: &SpoolContext, id: &str, rel: Relation, target: &str) -> Result<()> {
+    let user = get_current_user()?;
+    let branch = get_current_branch()?;
+    write_link(ctx, id, rel, target, &user, &branch)?;
+    println!("Linked task {}: {} {}", id, rel, target);
+
+    Ok(())
+}
+
+pub fn unlink_task(ctx: &SpoolContext, id: &str, rel: Relation, target: &str) -> Result<()> {
+    let user = get_current_user()?;
+    let branch = get_current_branch()?;
+    write_unlink(ctx, id, rel, target, &user, &branch)?;
+    println!("Unlinked task {}: {} {}", id, rel, target);
+
+    Ok(())
+}
+
+/// Print the tree of tasks `id` is waiting on (or, with `reverse`, that
+/// are waiting on it)
+pub fn show_deps(ctx: &SpoolContext, id: &str, reverse: bool) -> Result<()> {
+    let state = load_state(ctx)?;
+    let task = state
+        .tasks
+        .get(id)
+        .ok_or_else(|| anyhow!("Task not found: {}", id))?;
+    let graph = LinkGraph::new(&state);
+
+    println!("{}", dep_label(&state, &task.id));
+    let mut path = vec![task.id.clone()];
+    let mut shown = HashSet::new();
+    print_dep_tree(&state, &graph, reverse, "", &mut path, &mut shown);
+
+    Ok(())
+}
+
+fn print_dep_tree(
+    state: &State,
+    graph: &LinkGraph,
+    reverse: bool,
+    prefix: &str,
+    path: &mut Vec<String>,
+    shown: &mut HashSet<String>,
+) {
+    let id = path.last().cloned().unwrap_or_default();
+    let ids = if reverse {
+        graph.dependents(&id)
+    } else {
+        graph.blockers(&id)
+    };
+    for (i, next) in ids.iter().enumerate() {
+        let last = i + 1 == ids.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let label = dep_label(state, next);
+        if path.iter().any(|p| p == next) {
+            println!("{}{}{} (cycle)", prefix, branch, label);
+        } else if !shown.insert(next.to_string()) {
+            // Already printed with its own dependencies
+            println!("{}{}{} (see above)", prefix, branch, label);
+        } else {
+            println!("{}{}{}", prefix, branch, label);
+            path.push(next.to_string());
+            print_dep_tree(
+                state,
+                graph,
+                reverse,
+                &format!("{}{}", prefix, indent),
+                path,
+                shown,
+            );
+            path.pop();
+        }
+    }
+}
+
+fn dep_label(state: &State, id: &str) -> String {
+    match state.tasks.get(id) {
+        Some(task) if task.status == TaskStatus::Complete => {
+            format!("{}  {} (complete)", task.id, task.title)
+        }
+        Some(task) => format!("{}  {}", task.id, task.title),
+        None => format!("{}  (not found)", id),
+    }
+}
+
+/// Print `id` and its subtasks, or every top-level task with subtasks
+pub fn show_tree(ctx: &SpoolContext, id: Option<&str>) -> Result<()> {
+    let state = load_state(ctx)?;
+    let hierarchy = Hierarchy::new(state.tasks.values());
+    let roots = match id {
+        Some(id) => {
+            let task = state
+                .tasks
+                .get(id)
+                .ok_or_else(|| anyhow!("Task not found: {}", id))?;
+            vec![task.id.as_str()]
+        }
+        None => hierarchy
+            .roots()
+            .into_iter()
+            .filter(|id| state.tasks.get(*id).map_or(true, |t| t.archived.is_none()))
+            .collect(),
+    };
+
+    if roots.is_empty() {
+        println!("No subtasks found.");
+        return Ok(());
+    }
+    for root in roots {
+        println!("{}", tree_label(&state, &hierarchy, root));
+        let mut path = vec![root.to_string()];
+        print_subtasks(&state, &hierarchy, "", &mut path);
+    }
+
+    Ok(())
+}
+
+fn print_subtasks(state: &State, hierarchy: &Hierarchy, prefix: &str, path: &mut Vec<String>) {
+    let id = path.last().cloned().unwrap_or_default();
+    let children = hierarchy.children(&id);
+    for (i, child) in children.iter().enumerate() {
+        let (branch, indent) = if i + 1 == children.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let label = tree_label(state, hierarchy, child);
+        if path.contains(child) {
+            println!("{}{}{} (cycle)", prefix, branch, label);
+        } else {
+            println!("{}{}{}", prefix, branch, label);
+            path.push(child.clone());
+            print_subtasks(state, hierarchy, &format!("{}{}", prefix, indent), path);
+            path.pop();
+        }
+    }
+}
+
+/// `[x] ID  Title (complete/total)`, with the count only for tasks with subtasks
+fn tree_label(state: &State, hierarchy: &Hierarchy, id: &str) -> String {
+    let Some(task) = state.tasks.get(id) else {
+        return format!("[?] {}  (not found)", id);
+    };
+    let marker = match task.status {
+        TaskStatus::Complete => "[x]",
+        TaskStatus::Open => "[ ]",
+    };
+    let mut label = format!("{} {}  {}", marker, task.id, task.title);
+    if !hierarchy.children(id).is_empty() {
+        label.push_str(&format!(" ({})", hierarchy.progress(id)));
+    }
+    label
+}
+
+pub fn list_ready(
+    ctx: &SpoolContext,
+    query: &TaskQuery,
+    limit: Option<usize>,
+    format: OutputFormat,
+) -> Result<()> {
+    let state = load_state(ctx)?;
+    let tasks: Vec<Task> = ready_tasks(&state)
+        .into_iter()
+        .filter(|task| query.matches(task))
+        .take(limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect();
+    let hierarchy = Hierarchy::new(state.tasks.values());
+    print_tasks(&tasks, format, Some(&hierarchy))
+}
+
+pub fn next_task(ctx: &SpoolContext, query: &TaskQuery, claim: bool) -> Result<()> {
+    if claim {
+        let user = get_current_user()?;
+        let branch = get_current_branch()?;
+        let id = write_claim_next(ctx, query, &user, &branch)?
+            .ok_or_else(|| anyhow!("No tasks ready"))?;
+        println!("Claimed task {} (assigned to {})", id, user);
+    } else {
+        let state = load_state(ctx)?;
+        let task = ready_tasks(&state)
+            .into_iter()
+            .find(|task| query.matches(task))
+            .ok_or_else(|| anyhow!("No tasks ready"))?;
+        println!("{}  {}", task.id, task.title);
+    }
 
     Ok(())
 }
@@ -606,7 +1544,7 @@ pub fn add_stream(ctx: &SpoolContext, name: &str, description: Option<&str>) ->
 
 /// List all streams
 pub fn list_streams(ctx: &SpoolContext, format: OutputFormat) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+    let state = load_state(ctx)?;
 
     let mut streams: Vec<_> = state.streams.values().collect();
     streams.sort_by_key(|s| &s.created);
@@ -668,7 +1606,7 @@ pub fn list_streams(ctx: &SpoolContext, format: OutputFormat) -> Result<()> {
 
 /// Show details of a stream and its tasks
 pub fn show_stream(ctx: &SpoolContext, id: Option<&str>, name: Option<&str>) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+    let state = load_state(ctx)?;
 
     // Find stream by ID or name
     let stream = match (id, name) {
@@ -735,7 +1673,7 @@ pub fn update_stream_cmd(
     name: Option<&str>,
     description: Option<&str>,
 ) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+    let state = load_state(ctx)?;
 
     // Verify stream exists
     state
@@ -762,7 +1700,7 @@ pub fn update_stream_cmd(
 
 /// Delete a stream
 pub fn delete_stream(ctx: &SpoolContext, id: &str) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+    let state = load_state(ctx)?;
 
     // Verify stream exists
     let stream = state
@@ -796,7 +1734,7 @@ pub fn delete_stream(ctx: &SpoolContext, id: &str) -> Result<()> {
 
 /// Set a task's stream (used by update command)
 pub fn set_task_stream(ctx: &SpoolContext, task_id: &str, stream_id: Option<&str>) -> Result<()> {
-    let state = load_or_materialize_state(ctx)?;
+    let state = load_state(ctx)?;
 
     // Verify task exists
     state
@@ -824,3 +1762,15 @@ pub fn set_task_stream(ctx: &SpoolContext, task_id: &str, stream_id: Option<&str
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_editor_keeps_markdown_headings() {
+        let text = run_editor("sed -i s/Draft/Done/", "# Heading\n\nDraft").unwrap();
+        assert_eq!(text, "# Heading\n\nDone");
+    }
+}
@@ -0,0 +1,71 @@
+//! Hybrid logical clocks for ordering events across branches
+//!
+//! Event files are merged by git, so the order of lines in a file says nothing
+//! about the order events happened in. Each event instead carries a hybrid
+//! logical clock: the writer's wall time in milliseconds, plus a counter that
+//! breaks ties and keeps the clock monotonic when a peer's clock ran ahead.
+//! Materialization replays events sorted by clock, so every clone converges
+//! to the same state regardless of how the lines were merged.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(from = "(i64, u32)", into = "(i64, u32)")]
+pub struct Hlc {
+    /// Milliseconds since the Unix epoch
+    pub wall_ms: i64,
+    /// Logical counter for events sharing the same `wall_ms`
+    pub counter: u32,
+}
+
+impl Hlc {
+    /// Clock for an event written before clocks existed, derived from its timestamp
+    pub fn from_ts(ts: DateTime<Utc>) -> Self {
+        Self {
+            wall_ms: ts.timestamp_millis(),
+            counter: 0,
+        }
+    }
+
+    /// Next clock value after `last` (the newest clock seen so far) at physical time `now`
+    pub fn tick(last: Option<Hlc>, now: DateTime<Utc>) -> Self {
+        let physical = now.timestamp_millis();
+        match last {
+            Some(last) if last.wall_ms >= physical => match last.counter.checked_add(1) {
+                Some(counter) => Self {
+                    wall_ms: last.wall_ms,
+                    counter,
+                },
+                // Counters come from hand-editable files; move to the next
+                // millisecond rather than wrap
+                None => Self {
+                    wall_ms: last.wall_ms.saturating_add(1),
+                    counter: 0,
+                },
+            },
+            _ => Self {
+                wall_ms: physical,
+                counter: 0,
+            },
+        }
+    }
+}
+
+impl From<(i64, u32)> for Hlc {
+    fn from((wall_ms, counter): (i64, u32)) -> Self {
+        Self { wall_ms, counter }
+    }
+}
+
+impl From<Hlc> for (i64, u32) {
+    fn from(hlc: Hlc) -> Self {
+        (hlc.wall_ms, hlc.counter)
+    }
+}
+
+impl std::fmt::Display for Hlc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.wall_ms, self.counter)
+    }
+}
@@ -1,13 +1,17 @@
-use anyhow::{anyhow, Result};
-use chrono::Utc;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File, OpenOptions};
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::context::SpoolContext;
-use crate::event::{Event, Operation};
+use crate::event::{sort_events, Event, Operation};
+use crate::store::{segment_group, segment_name, EventStore, ARCHIVE, EVENTS};
+use crate::writer::append_events;
 
 /// Global sequence counter for optimistic locking
 static SEQUENCE: AtomicU64 = AtomicU64::new(0);
@@ -19,10 +23,21 @@ pub struct Version {
     pub seq: u64,
     /// Timestamp of last modification
     pub ts: String,
-    /// Hash of the last event for this task
+    /// Hash of the last event for this task (its `Event::event_id`)
     pub last_event_hash: String,
 }
 
+impl Version {
+    /// A version to compare against, known only by its hash (e.g. `--if-version`)
+    pub fn from_hash(hash: &str) -> Self {
+        Self {
+            seq: SEQUENCE.fetch_add(1, Ordering::SeqCst),
+            ts: String::new(),
+            last_event_hash: hash.to_string(),
+        }
+    }
+}
+
 /// Result of an optimistic write attempt
 #[derive(Debug)]
 pub enum WriteResult {
@@ -37,102 +52,364 @@ pub enum WriteResult {
     Error(String),
 }
 
-/// Lock file for coordinating writes
+/// How long writers wait for another process's lock by default
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long writers wait for the lock: `SPOOL_LOCK_TIMEOUT` seconds, or the default
+pub fn lock_timeout() -> Duration {
+    std::env::var("SPOOL_LOCK_TIMEOUT")
+        .ok()
+        .and_then(|s| s.trim().parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or(DEFAULT_LOCK_TIMEOUT)
+}
+
+fn lock_path(ctx: &SpoolContext) -> PathBuf {
+    ctx.root.join(".lock")
+}
+
+/// The process recorded in the lock file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl LockHolder {
+    fn read(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let (pid, since) = content.trim().split_once(':')?;
+        Some(Self {
+            pid: pid.parse().ok()?,
+            since: DateTime::parse_from_rfc3339(since)
+                .ok()
+                .map(|ts| ts.with_timezone(&Utc)),
+        })
+    }
+
+    /// Whether the holder is still running; `None` if that can't be determined
+    pub fn is_alive(&self) -> Option<bool> {
+        sys::process_alive(self.pid)
+    }
+}
+
+impl std::fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "process {}", self.pid)?;
+        if let Some(since) = self.since {
+            write!(f, " since {}", since.to_rfc3339())?;
+        }
+        Ok(())
+    }
+}
+
+fn describe_holder(path: &Path) -> String {
+    match LockHolder::read(path) {
+        Some(holder) => format!("held by {}", holder),
+        None => "held by another process".to_string(),
+    }
+}
+
+/// Exclusive write lock on the spool directory
+///
+/// On Unix this is an advisory `flock` on `.spool/.lock`, which the kernel
+/// releases however the holder exits, so a crashed writer never blocks
+/// anyone. The file records the holder's PID for diagnostics and is removed
+/// when the lock is released.
 pub struct FileLock {
     path: PathBuf,
-    _lock_file: Option<File>,
+    file: File,
 }
 
 impl FileLock {
-    /// Acquire an advisory lock on the events directory
+    /// Take the lock without waiting, failing if another process holds it
     pub fn acquire(ctx: &SpoolContext) -> Result<Self> {
-        let path = ctx.root.join(".lock");
+        let path = lock_path(ctx);
+        match Self::try_lock(&path)? {
+            Some(lock) => Ok(lock),
+            None => Err(anyhow!(
+                "Lock {} (run 'spool unlock' if it is stuck)",
+                describe_holder(&path)
+            )),
+        }
+    }
+
+    /// Take the lock, waiting up to `lock_timeout()` for the current holder
+    pub fn wait(ctx: &SpoolContext) -> Result<Self> {
+        Self::wait_timeout(ctx, lock_timeout())
+    }
 
-        // Try to create lock file exclusively
-        let lock_file = OpenOptions::new().write(true).create_new(true).open(&path);
-
-        match lock_file {
-            Ok(mut f) => {
-                // Write PID and timestamp
-                writeln!(f, "{}:{}", std::process::id(), Utc::now().to_rfc3339())?;
-                Ok(Self {
-                    path,
-                    _lock_file: Some(f),
-                })
+    /// Take the lock, waiting up to `timeout` for the current holder
+    pub fn wait_timeout(ctx: &SpoolContext, timeout: Duration) -> Result<Self> {
+        let path = lock_path(ctx);
+        let start = Instant::now();
+        loop {
+            if let Some(lock) = Self::try_lock(&path)? {
+                return Ok(lock);
             }
-            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-                // Check if lock is stale (older than 60 seconds)
-                if let Ok(content) = fs::read_to_string(&path) {
-                    if let Some(ts_str) = content.split(':').nth(1) {
-                        if let Ok(ts) = chrono::DateTime::parse_from_rfc3339(ts_str.trim()) {
-                            let age = Utc::now().signed_duration_since(ts);
-                            if age.num_seconds() > 60 {
-                                // Stale lock, remove and retry
-                                fs::remove_file(&path)?;
-                                return Self::acquire(ctx);
-                            }
-                        }
-                    }
-                }
-                Err(anyhow!("Lock held by another process"))
+            if start.elapsed() >= timeout {
+                return Err(anyhow!(
+                    "Timed out after {:.1}s waiting for the write lock, {} (run 'spool unlock' if it is stuck)",
+                    timeout.as_secs_f64(),
+                    describe_holder(&path)
+                ));
+            }
+            thread::sleep(LOCK_POLL_INTERVAL);
+        }
+    }
+
+    fn try_lock(path: &Path) -> Result<Option<Self>> {
+        loop {
+            if let Some(mut file) =
+                sys::try_lock(path).with_context(|| format!("Failed to lock {:?}", path))?
+            {
+                file.set_len(0)?;
+                writeln!(file, "{}:{}", std::process::id(), Utc::now().to_rfc3339())?;
+                return Ok(Some(Self {
+                    path: path.to_path_buf(),
+                    file,
+                }));
+            }
+
+            // An OS lock is only ever held by a live process, whatever PID the
+            // file shows: it may be in another PID namespace, or have just
+            // taken the lock and not yet written its own PID
+            if sys::OS_LOCKS {
+                return Ok(None);
+            }
+
+            // Without OS locks the file is the lock; if its holder is gone,
+            // clear it and retry
+            let dead = LockHolder::read(path).and_then(|holder| holder.is_alive()) == Some(false);
+            if !dead || fs::remove_file(path).is_err() {
+                return Ok(None);
             }
-            Err(e) => Err(e.into()),
         }
     }
 }
 
 impl Drop for FileLock {
     fn drop(&mut self) {
-        let _ = fs::remove_file(&self.path);
+        // Leave the file alone if `spool unlock --force` handed it to someone else
+        if sys::is_same_file(&self.file, &self.path) {
+            let _ = fs::remove_file(&self.path);
+        }
     }
 }
 
-/// Get the current version of a task by reading its last event
-pub fn get_task_version(ctx: &SpoolContext, task_id: &str) -> Result<Option<Version>> {
-    let mut last_event: Option<Event> = None;
+/// What `break_lock` found
+#[derive(Debug, PartialEq)]
+pub enum Unlocked {
+    /// Nobody held the lock
+    NotLocked,
+    /// Removed the lock of the recorded holder, if any
+    Removed(Option<LockHolder>),
+}
 
-    // Scan event files in reverse chronological order
-    let mut files = ctx.get_event_files()?;
-    files.reverse();
+/// Remove the write lock, e.g. after a writer hung or died on a filesystem
+/// without OS locks
+///
+/// Refuses while the holder is still running, or when that can't be told
+/// (always the case while an OS lock is held), unless `force` is set.
+/// Forcing lets new writers in while the old holder may still be writing.
+pub fn break_lock(ctx: &SpoolContext, force: bool) -> Result<Unlocked> {
+    let path = lock_path(ctx);
+    if !path.exists() {
+        return Ok(Unlocked::NotLocked);
+    }
+    if let Some(lock) = FileLock::try_lock(&path)? {
+        drop(lock);
+        return Ok(Unlocked::NotLocked);
+    }
+
+    // The OS lock is held, so the holder is running even if the recorded PID
+    // says otherwise
+    let holder = LockHolder::read(&path);
+    let alive = if sys::OS_LOCKS {
+        None
+    } else {
+        holder.as_ref().and_then(|h| h.is_alive())
+    };
+    if !force && alive != Some(false) {
+        let state = match alive {
+            Some(_) => "which is still running",
+            None => "which may still be running",
+        };
+        return Err(anyhow!(
+            "Lock {}, {} (use --force to remove it anyway)",
+            describe_holder(&path),
+            state
+        ));
+    }
+
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(Unlocked::Removed(holder)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Unlocked::NotLocked),
+        Err(e) => Err(anyhow!("Failed to remove {:?}: {}", path, e)),
+    }
+}
 
-    for file in files {
-        let events = ctx.parse_events_from_file(&file)?;
-        for event in events.into_iter().rev() {
-            if event.id == task_id {
-                last_event = Some(event);
-                break;
+#[cfg(unix)]
+mod sys {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+
+    /// Whether `try_lock` takes a lock the kernel releases when its holder exits
+    pub const OS_LOCKS: bool = true;
+
+    /// Open the lock file and `flock` it without blocking
+    pub fn try_lock(path: &Path) -> io::Result<Option<File>> {
+        loop {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            // SAFETY: the descriptor is open for the duration of the call
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(None);
+                }
+                return Err(err);
+            }
+            // The previous holder removes the file on release; if it did so
+            // between our open and flock, we locked an orphan and must retry
+            if is_same_file(&file, path) {
+                return Ok(Some(file));
             }
         }
-        if last_event.is_some() {
+    }
+
+    pub fn is_same_file(file: &File, path: &Path) -> bool {
+        match (file.metadata(), std::fs::metadata(path)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+
+    pub fn process_alive(pid: u32) -> Option<bool> {
+        let pid = match libc::pid_t::try_from(pid) {
+            Ok(pid) if pid > 0 => pid,
+            _ => return Some(false),
+        };
+        // SAFETY: signal 0 only checks that the process exists
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return Some(true);
+        }
+        // EPERM: it exists but belongs to another user
+        Some(io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::path::Path;
+
+    pub const OS_LOCKS: bool = false;
+
+    /// Create the lock file exclusively; it exists exactly while held
+    pub fn try_lock(path: &Path) -> io::Result<Option<File>> {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(file) => Ok(Some(file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn is_same_file(_file: &File, path: &Path) -> bool {
+        path.exists()
+    }
+
+    pub fn process_alive(_pid: u32) -> Option<bool> {
+        None
+    }
+}
+
+/// Get the current version of a task by reading its last event
+pub fn get_task_version<S: EventStore + ?Sized>(
+    store: &S,
+    task_id: &str,
+) -> Result<Option<Version>> {
+    let mut last_event: Option<Event> = None;
+
+    // Scan the task's segments in reverse replay order, a day (with all of
+    // its shards) at a time; archived tasks' events are only in the archive
+    let mut segments = store.task_segments(task_id)?;
+    segments.retain(|key| {
+        [EVENTS, ARCHIVE]
+            .iter()
+            .any(|p| segment_name(key, p).is_some())
+    });
+    segments.reverse();
+
+    let mut day: Vec<Event> = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let (events, _) = store.read_events(segment)?;
+        day.extend(events.into_iter().filter(|event| event.id == task_id));
+        let day_ends = segments
+            .get(i + 1)
+            .map_or(true, |next| segment_group(next) != segment_group(segment));
+        if day_ends && !day.is_empty() {
+            sort_events(&mut day);
+            last_event = day.pop();
             break;
         }
     }
 
     match last_event {
-        Some(event) => {
-            let event_json = serde_json::to_string(&event)?;
-            let hash = format!("{:x}", simple_hash(&event_json));
-            Ok(Some(Version {
-                seq: SEQUENCE.fetch_add(1, Ordering::SeqCst),
-                ts: event.ts.to_rfc3339(),
-                last_event_hash: hash,
-            }))
-        }
+        Some(event) => Ok(Some(Version {
+            seq: SEQUENCE.fetch_add(1, Ordering::SeqCst),
+            ts: event.ts.to_rfc3339(),
+            last_event_hash: event.event_id(),
+        })),
         None => Ok(None),
     }
 }
 
 /// Write an event with optimistic locking
-pub fn write_event_with_version(
-    ctx: &SpoolContext,
+///
+/// This is the append path for every single-event mutation. Without an
+/// expected version the event is written unconditionally, except that a
+/// create must not reuse an existing ID.
+pub fn write_event_with_version<S: EventStore + ?Sized>(
+    store: &S,
     event: &Event,
     expected_version: Option<&Version>,
 ) -> Result<WriteResult> {
-    // Acquire file lock
-    let _lock = FileLock::acquire(ctx)?;
+    // Acquire the store's write lock
+    let _lock = store.lock()?;
+
+    let result = check_version(store, event, expected_version)?;
+    if let WriteResult::Success = result {
+        append_events(store, std::slice::from_ref(event))?;
+    }
+    Ok(result)
+}
+
+/// Whether `event` may be written given the expected version of its task;
+/// callers hold the write lock
+pub(crate) fn check_version<S: EventStore + ?Sized>(
+    store: &S,
+    event: &Event,
+    expected_version: Option<&Version>,
+) -> Result<WriteResult> {
+    let creates = matches!(event.op, Operation::Create | Operation::CreateStream);
+    if expected_version.is_none() && !creates {
+        return Ok(WriteResult::Success);
+    }
 
     // Check current version
-    let current_version = get_task_version(ctx, &event.id)?;
+    let current_version = get_task_version(store, &event.id)?;
 
     // Version conflict detection
     match (expected_version, &current_version) {
@@ -146,58 +423,36 @@ pub fn write_event_with_version(
         }
         (Some(_expected), None) => {
             // Expected a version but task doesn't exist
-            if event.op != Operation::Create {
+            if !creates {
                 return Ok(WriteResult::Error(
                     "Task does not exist but expected version provided".to_string(),
                 ));
             }
         }
         (None, Some(_actual)) => {
-            // No expected version but task exists - allow for creates
-            if event.op == Operation::Create {
-                return Ok(WriteResult::Error("Task already exists".to_string()));
-            }
+            // Only creates get here; the ID is already taken
+            return Ok(WriteResult::Error("Task already exists".to_string()));
         }
         (None, None) => {
             // No version check needed for new tasks
-            if event.op != Operation::Create {
-                return Ok(WriteResult::Error("Task does not exist".to_string()));
-            }
         }
     }
 
-    // Write event
-    let today = Utc::now().format("%Y-%m-%d").to_string();
-    let event_file = ctx.events_dir.join(format!("{}.jsonl", today));
-
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&event_file)?;
-    let mut writer = BufWriter::new(file);
-
-    let json = serde_json::to_string(event)?;
-    writeln!(writer, "{}", json)?;
-    writer.flush()?;
-
     Ok(WriteResult::Success)
 }
 
 /// Retry a write operation with exponential backoff
-pub fn write_with_retry<F>(
-    ctx: &SpoolContext,
-    max_retries: u32,
-    mut operation: F,
-) -> Result<WriteResult>
+pub fn write_with_retry<S, F>(store: &S, max_retries: u32, mut operation: F) -> Result<WriteResult>
 where
-    F: FnMut(&SpoolContext) -> Result<(Event, Option<Version>)>,
+    S: EventStore + ?Sized,
+    F: FnMut(&S) -> Result<(Event, Option<Version>)>,
 {
     let mut retries = 0;
     let mut delay_ms = 10;
 
     loop {
-        let (event, version) = operation(ctx)?;
-        let result = write_event_with_version(ctx, &event, version.as_ref())?;
+        let (event, version) = operation(store)?;
+        let result = write_event_with_version(store, &event, version.as_ref())?;
 
         match result {
             WriteResult::Success => return Ok(WriteResult::Success),
@@ -211,16 +466,6 @@ where
     }
 }
 
-/// Simple rolling hash for version fingerprinting
-fn simple_hash(input: &str) -> u128 {
-    let mut hash: u128 = 0;
-    for (i, byte) in input.bytes().enumerate() {
-        hash = hash.wrapping_add((byte as u128).wrapping_mul((i as u128).wrapping_add(1)));
-        hash = hash.wrapping_mul(31);
-    }
-    hash
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,6 +481,7 @@ mod tests {
             root: spool_dir.clone(),
             events_dir: spool_dir.join("events"),
             archive_dir: spool_dir.join("archive"),
+            git_ref: None,
         };
 
         (temp_dir, ctx)
@@ -255,6 +501,67 @@ mod tests {
         let _lock = FileLock::acquire(&ctx).unwrap();
     }
 
+    #[test]
+    fn test_file_lock_wait_times_out() {
+        let (_temp, ctx) = setup_test_ctx();
+
+        let _lock = FileLock::acquire(&ctx).unwrap();
+        let err = FileLock::wait_timeout(&ctx, Duration::from_millis(50))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Timed out"));
+        assert!(err
+            .to_string()
+            .contains(&format!("process {}", std::process::id())));
+    }
+
+    #[test]
+    fn test_lock_left_by_dead_process_is_taken_over() {
+        let (_temp, ctx) = setup_test_ctx();
+        fs::write(
+            ctx.root.join(".lock"),
+            "999999999:2024-01-15T10:00:00+00:00\n",
+        )
+        .unwrap();
+
+        let _lock = FileLock::acquire(&ctx).unwrap();
+        let holder = LockHolder::read(&ctx.root.join(".lock")).unwrap();
+        assert_eq!(holder.pid, std::process::id());
+    }
+
+    #[test]
+    fn test_held_lock_is_kept_whatever_pid_it_records() {
+        let (_temp, ctx) = setup_test_ctx();
+        let path = ctx.root.join(".lock");
+
+        // The holder may be in another PID namespace, or not yet have
+        // written its own PID
+        let _lock = FileLock::acquire(&ctx).unwrap();
+        fs::write(&path, "999999999:2024-01-15T10:00:00+00:00\n").unwrap();
+
+        assert!(FileLock::acquire(&ctx).is_err());
+        assert!(break_lock(&ctx, false).is_err());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_break_lock_requires_force_for_live_holder() {
+        let (_temp, ctx) = setup_test_ctx();
+        assert_eq!(break_lock(&ctx, false).unwrap(), Unlocked::NotLocked);
+
+        let lock = FileLock::acquire(&ctx).unwrap();
+        assert!(break_lock(&ctx, false).is_err());
+
+        let removed = break_lock(&ctx, true).unwrap();
+        assert!(matches!(removed, Unlocked::Removed(Some(h)) if h.pid == std::process::id()));
+
+        // A new writer gets in, and the old holder's release leaves its lock alone
+        let _new = FileLock::acquire(&ctx).unwrap();
+        drop(lock);
+        assert!(ctx.root.join(".lock").exists());
+        assert!(FileLock::acquire(&ctx).is_err());
+    }
+
     #[test]
     fn test_version_tracking() {
         let (_temp, ctx) = setup_test_ctx();
@@ -269,6 +576,9 @@ mod tests {
             op: Operation::Create,
             id: "task-1".to_string(),
             ts: Utc::now(),
+            hlc: None,
+            prev: vec![],
+            txn: None,
             by: "@test".to_string(),
             branch: "main".to_string(),
             d: serde_json::json!({"title": "Test"}),
@@ -292,6 +602,9 @@ mod tests {
             op: Operation::Create,
             id: "task-1".to_string(),
             ts: Utc::now(),
+            hlc: None,
+            prev: vec![],
+            txn: None,
             by: "@test".to_string(),
             branch: "main".to_string(),
             d: serde_json::json!({"title": "Test"}),
@@ -307,6 +620,9 @@ mod tests {
             op: Operation::Update,
             id: "task-1".to_string(),
             ts: Utc::now(),
+            hlc: None,
+            prev: vec![],
+            txn: None,
             by: "@other".to_string(),
             branch: "main".to_string(),
             d: serde_json::json!({"title": "Updated by other"}),
@@ -325,6 +641,9 @@ mod tests {
             op: Operation::Update,
             id: "task-1".to_string(),
             ts: Utc::now(),
+            hlc: None,
+            prev: vec![],
+            txn: None,
             by: "@test".to_string(),
             branch: "main".to_string(),
             d: serde_json::json!({"title": "My update"}),
@@ -333,4 +652,26 @@ mod tests {
         let result = write_event_with_version(&ctx, &conflicting_event, Some(&version2)).unwrap();
         assert!(matches!(result, WriteResult::Conflict { .. }));
     }
+
+    #[test]
+    fn test_create_rejects_existing_id() {
+        let (_temp, ctx) = setup_test_ctx();
+
+        let event = Event {
+            v: 1,
+            op: Operation::Create,
+            id: "task-1".to_string(),
+            ts: Utc::now(),
+            hlc: None,
+            prev: vec![],
+            txn: None,
+            by: "@test".to_string(),
+            branch: "main".to_string(),
+            d: serde_json::json!({"title": "Test"}),
+        };
+        write_event_with_version(&ctx, &event, None).unwrap();
+
+        let result = write_event_with_version(&ctx, &event, None).unwrap();
+        assert!(matches!(result, WriteResult::Error(_)));
+    }
 }
@@ -0,0 +1,53 @@
+//! Repository settings, shared through git in `.spool/config.json`
+//!
+//! Every field is optional, so a repo without the file (or with only some
+//! settings) gets the defaults.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::archive::ArchiveRule;
+use crate::context::SpoolContext;
+use crate::store::Layout;
+use crate::writer::replace_durably;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    /// Retention policy: a task is archived by `spool rebuild` (and
+    /// `spool archive --policy`) when it matches any of these rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retention: Vec<ArchiveRule>,
+    /// Git ref holding the events instead of `.spool/events/` and
+    /// `.spool/archive/` (see `gitref`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub events_ref: Option<String>,
+    /// How new events are split into segments; switching to `"author"`
+    /// shards existing day files on the next command (see `migration`)
+    #[serde(default, skip_serializing_if = "Layout::is_daily")]
+    pub layout: Layout,
+    /// Answer `spool list` and the TUI from a local SQLite index in
+    /// `.spool/.index.db` instead of `.state.json` (see `sqlite`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sqlite_index: bool,
+}
+
+impl Config {
+    /// Read the repo's config, or the defaults if it has none
+    pub fn load(ctx: &SpoolContext) -> Result<Self> {
+        let path = ctx.config_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid config in {:?}", path))
+    }
+
+    /// Write the config back to `.spool/config.json`
+    pub fn save(&self, ctx: &SpoolContext) -> Result<()> {
+        let path = ctx.config_path();
+        let content = format!("{}\n", serde_json::to_string_pretty(self)?);
+        replace_durably(&path, content.as_bytes())
+    }
+}
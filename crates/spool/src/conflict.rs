@@ -0,0 +1,336 @@
+//! Detection of concurrent edits to the same task field
+//!
+//! Every task event written by spool lists the task's events its writer had
+//! already seen (`Event::prev`). Together these form a causal history per
+//! task: a write to a field supersedes the earlier writes it descends from,
+//! and is concurrent with any it doesn't. Concurrent writes, typically made
+//! on different branches and then merged, are reported as a `Conflict`
+//! instead of silently letting the later one win. A new write that has seen
+//! all sides of a conflict resolves it.
+//!
+//! Events without `prev` (written before ancestry was recorded) are assumed
+//! to have seen every event replayed before them, so they never conflict.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::event::Event;
+use crate::payload::{
+    AssignPayload, CompletePayload, Payload, ReopenPayload, SetStreamPayload, UpdatePayload,
+};
+
+/// Concurrent writes to one field of a task
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Conflict {
+    pub task: String,
+    /// Task field: `title`, `description`, `priority`, `tags`, `assignee`,
+    /// `stream` or `status`
+    pub field: String,
+    /// Competing writes in replay order; the last one is the applied value
+    pub writes: Vec<FieldWrite>,
+}
+
+/// A single write to a task field
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldWrite {
+    /// `Event::event_id` of the event that wrote the value
+    pub event: String,
+    pub by: String,
+    pub branch: String,
+    pub ts: DateTime<Utc>,
+    /// Value written; for `status`, an object with the status and, for a
+    /// completion, its resolution
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Node {
+    id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    prev: Vec<String>,
+}
+
+/// A task's events in replay order, indexed by event ID
+///
+/// Serialized as the plain list; the index is rebuilt when read back.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "Vec<Node>")]
+struct Nodes {
+    list: Vec<Node>,
+    index: HashMap<String, usize>,
+}
+
+impl Nodes {
+    fn push(&mut self, node: Node) -> usize {
+        let i = self.list.len();
+        self.index.entry(node.id.clone()).or_insert(i);
+        self.list.push(node);
+        i
+    }
+
+    fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Position of the first event with this ID
+    fn position(&self, id: &str) -> Option<usize> {
+        self.index.get(id).copied()
+    }
+}
+
+impl std::ops::Index<usize> for Nodes {
+    type Output = Node;
+
+    fn index(&self, i: usize) -> &Node {
+        &self.list[i]
+    }
+}
+
+impl From<Vec<Node>> for Nodes {
+    fn from(list: Vec<Node>) -> Self {
+        let mut nodes = Nodes::default();
+        for node in list {
+            nodes.push(node);
+        }
+        nodes
+    }
+}
+
+impl Serialize for Nodes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.list.serialize(serializer)
+    }
+}
+
+/// Causal history of a single task's events
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct TaskHistory {
+    /// Events in replay order
+    nodes: Nodes,
+    /// Events no later event has seen
+    frontier: Vec<usize>,
+    /// Writes to each field not superseded by a later write
+    fields: BTreeMap<String, Vec<FieldWrite>>,
+}
+
+impl TaskHistory {
+    /// Record an event and the fields it writes
+    pub(crate) fn record(&mut self, event: &Event, payload: &Payload) {
+        let index = self.nodes.push(Node {
+            id: event.event_id(),
+            prev: event.prev.clone(),
+        });
+
+        if event.prev.is_empty() {
+            self.frontier = vec![index];
+        } else {
+            let nodes = &self.nodes;
+            self.frontier
+                .retain(|&i| !event.prev.iter().any(|p| *p == nodes[i].id));
+            self.frontier.push(index);
+        }
+
+        for (field, value) in field_values(payload) {
+            let writes = self.fields.entry(field.to_string()).or_default();
+            writes.retain(|write| {
+                // Writes this event hasn't seen stay alongside it
+                match self.nodes.position(&write.event) {
+                    Some(j) => !descends(&self.nodes, index, j),
+                    None => false,
+                }
+            });
+            writes.push(FieldWrite {
+                event: self.nodes[index].id.clone(),
+                by: event.by.clone(),
+                branch: event.branch.clone(),
+                ts: event.ts,
+                value,
+            });
+        }
+    }
+
+    /// Whether an event with this ID has been recorded
+    pub(crate) fn knows(&self, id: &str) -> bool {
+        self.nodes.position(id).is_some()
+    }
+
+    /// Only the events that the frontier and unsuperseded writes refer to
+    ///
+    /// Each kept event lists the kept events it descends from as its `prev`,
+    /// so later events are related to them as before; one that descends from
+    /// none keeps its original, now unknown, `prev` rather than appearing to
+    /// have seen everything before it.
+    pub(crate) fn compacted(&self) -> TaskHistory {
+        let mut keep: BTreeSet<usize> = self.frontier.iter().copied().collect();
+        for writes in self.fields.values() {
+            keep.extend(
+                writes
+                    .iter()
+                    .filter_map(|write| self.nodes.position(&write.event)),
+            );
+        }
+
+        let mut nodes = Nodes::default();
+        let mut moved = HashMap::new();
+        for &i in &keep {
+            let node = &self.nodes[i];
+            let mut prev = Vec::new();
+            if !node.prev.is_empty() {
+                prev = keep
+                    .iter()
+                    .filter(|&&j| j != i && descends(&self.nodes, i, j))
+                    .map(|&j| self.nodes[j].id.clone())
+                    .collect();
+                if prev.is_empty() {
+                    prev = node.prev.clone();
+                }
+            }
+            moved.insert(
+                i,
+                nodes.push(Node {
+                    id: node.id.clone(),
+                    prev,
+                }),
+            );
+        }
+
+        TaskHistory {
+            nodes,
+            frontier: self.frontier.iter().map(|i| moved[i]).collect(),
+            fields: self.fields.clone(),
+        }
+    }
+
+    /// IDs of the events a new write to this task supersedes
+    pub(crate) fn frontier(&self) -> Vec<String> {
+        self.frontier
+            .iter()
+            .map(|&i| self.nodes[i].id.clone())
+            .collect()
+    }
+
+    /// Fields with more than one unsuperseded write
+    pub(crate) fn conflicts(&self, task: &str) -> Vec<Conflict> {
+        self.fields
+            .iter()
+            .filter(|(_, writes)| writes.len() > 1)
+            .map(|(field, writes)| Conflict {
+                task: task.to_string(),
+                field: field.clone(),
+                writes: writes.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Whether the event at `from` had seen the event at `target`
+fn descends(nodes: &Nodes, from: usize, target: usize) -> bool {
+    let mut visited = vec![false; nodes.len()];
+    let mut stack = vec![from];
+    while let Some(i) = stack.pop() {
+        if std::mem::replace(&mut visited[i], true) {
+            continue;
+        }
+        let node = &nodes[i];
+        if node.prev.is_empty() {
+            // No recorded ancestry: assume it saw everything replayed before it
+            if target < i {
+                return true;
+            }
+            continue;
+        }
+        for prev in &node.prev {
+            match nodes.position(prev) {
+                Some(j) if j == target => return true,
+                Some(j) => stack.push(j),
+                None => {}
+            }
+        }
+    }
+    false
+}
+
+/// Task fields written by an event, with the value written
+fn field_values(payload: &Payload) -> Vec<(&'static str, Value)> {
+    match payload {
+        Payload::Update(d) => {
+            let mut values = Vec::new();
+            if let Some(title) = &d.title {
+                values.push(("title", json!(title)));
+            }
+            if let Some(description) = &d.description {
+                values.push(("description", json!(description)));
+            }
+            if let Some(priority) = &d.priority {
+                values.push(("priority", json!(priority)));
+            }
+            if let Some(tags) = &d.tags {
+                values.push(("tags", json!(tags)));
+            }
+            values
+        }
+        Payload::Assign(d) => vec![("assignee", json!(d.to))],
+        Payload::SetStream(d) => vec![("stream", json!(d.stream))],
+        Payload::Complete(d) => {
+            let mut value = json!({"status": "complete"});
+            if let Some(resolution) = &d.resolution {
+                value["resolution"] = json!(resolution);
+            }
+            vec![("status", value)]
+        }
+        Payload::Reopen(_) => vec![("status", json!({"status": "open"}))],
+        _ => Vec::new(),
+    }
+}
+
+/// Payload of an event that sets `field` to `value`, used to resolve a conflict
+pub fn resolution_payload(field: &str, value: &Value) -> Result<Payload> {
+    let text = || {
+        value
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow!("Expected a string value for {}", field))
+    };
+    let payload = match field {
+        "title" => Payload::Update(UpdatePayload {
+            title: Some(text()?),
+            ..Default::default()
+        }),
+        "description" => Payload::Update(UpdatePayload {
+            description: Some(text()?),
+            ..Default::default()
+        }),
+        "priority" => Payload::Update(UpdatePayload {
+            priority: Some(text()?),
+            ..Default::default()
+        }),
+        "tags" => Payload::Update(UpdatePayload {
+            tags: Some(serde_json::from_value(value.clone())?),
+            ..Default::default()
+        }),
+        "assignee" => Payload::Assign(AssignPayload {
+            to: value.as_str().map(String::from),
+            ..Default::default()
+        }),
+        "stream" => Payload::SetStream(SetStreamPayload {
+            stream: value.as_str().map(String::from),
+            ..Default::default()
+        }),
+        // States cached before resolutions were recorded hold the bare status
+        "status" if value.get("status").unwrap_or(value) == "complete" => {
+            Payload::Complete(CompletePayload {
+                resolution: value
+                    .get("resolution")
+                    .and_then(Value::as_str)
+                    .map(String::from),
+                ..Default::default()
+            })
+        }
+        "status" => Payload::Reopen(ReopenPayload::default()),
+        other => return Err(anyhow!("Unknown task field: {}", other)),
+    };
+    Ok(payload)
+}
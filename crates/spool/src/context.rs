@@ -3,13 +3,29 @@ use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 
-use crate::event::Event;
+use crate::concurrency::FileLock;
+use crate::config::Config;
+use crate::event::{sort_events, Event};
+use crate::gitref::{self, GitRefStore};
+use crate::merge;
 use crate::migration;
 
+/// A line of an event file that couldn't be read as an event
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadLine {
+    /// 1-based line number in the file
+    pub line: usize,
+    pub text: String,
+    pub error: String,
+}
+
 pub struct SpoolContext {
     pub root: PathBuf,
     pub events_dir: PathBuf,
     pub archive_dir: PathBuf,
+    /// Where events are stored when the repo keeps them on a git ref (see
+    /// `gitref`) instead of in `events_dir` and `archive_dir`
+    pub git_ref: Option<GitRefStore>,
 }
 
 impl SpoolContext {
@@ -18,16 +34,27 @@ impl SpoolContext {
         Self {
             events_dir: root.join("events"),
             archive_dir: root.join("archive"),
+            git_ref: None,
             root,
         }
     }
 
+    /// Store events on `ref_name` in the git repository holding `root`
+    pub fn with_git_ref(mut self, ref_name: &str) -> Self {
+        let dir = self.root.parent().unwrap_or(&self.root).to_path_buf();
+        self.git_ref = Some(GitRefStore::new(dir, ref_name));
+        self
+    }
+
     pub fn discover() -> Result<Self> {
         let mut current = std::env::current_dir()?;
         loop {
             let spool_dir = current.join(".spool");
             if spool_dir.is_dir() {
-                let ctx = Self::new(spool_dir);
+                let mut ctx = Self::new(spool_dir);
+                if let Some(ref_name) = Config::load(&ctx)?.events_ref {
+                    ctx = ctx.with_git_ref(&ref_name);
+                }
                 // Check and run any needed migrations
                 migration::check_and_migrate(&ctx)?;
                 return Ok(ctx);
@@ -48,18 +75,40 @@ impl SpoolContext {
         self.root.join(".state.json")
     }
 
+    pub fn sqlite_path(&self) -> PathBuf {
+        self.root.join(".index.db")
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.root.join("config.json")
+    }
+
+    pub fn snapshots_dir(&self) -> PathBuf {
+        self.root.join("snapshots")
+    }
+
+    /// Event files, including each day's author shards (`events/DAY/*.jsonl`),
+    /// in replay order
     pub fn get_event_files(&self) -> Result<Vec<PathBuf>> {
         let mut files = Vec::new();
         if self.events_dir.is_dir() {
             for entry in fs::read_dir(&self.events_dir)? {
                 let entry = entry?;
                 let path = entry.path();
-                if path.extension().is_some_and(|ext| ext == "jsonl") {
+                if path.is_dir() {
+                    for shard in fs::read_dir(&path)? {
+                        let shard = shard?.path();
+                        if shard.extension().is_some_and(|ext| ext == "jsonl") {
+                            files.push(shard);
+                        }
+                    }
+                } else if path.extension().is_some_and(|ext| ext == "jsonl") {
                     files.push(path);
                 }
             }
         }
-        files.sort();
+        // By name, so a day's file sorts next to its shards
+        files.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
         Ok(files)
     }
 
@@ -78,6 +127,7 @@ impl SpoolContext {
         Ok(files)
     }
 
+    /// Parse all events in a file, returned in replay order (see `sort_events`)
     pub fn parse_events_from_file(&self, path: &Path) -> Result<Vec<Event>> {
         let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
         let reader = BufReader::new(file);
@@ -87,19 +137,121 @@ impl SpoolContext {
             if line.trim().is_empty() {
                 continue;
             }
-            let event: Event = serde_json::from_str(&line)
+            let event = Event::from_json(&line)
                 .with_context(|| format!("Failed to parse line {} in {:?}", line_num + 1, path))?;
             events.push(event);
         }
+        sort_events(&mut events);
         Ok(events)
     }
+
+    /// Parse events from a slice of an event file starting at line `first_line`
+    ///
+    /// Events are returned in replay order. `path` and `first_line` are only
+    /// used for error messages.
+    pub fn parse_events_from_bytes(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        first_line: usize,
+    ) -> Result<Vec<Event>> {
+        let mut events = Vec::new();
+        for (line_num, line) in bytes.split(|b| *b == b'\n').enumerate() {
+            let line = std::str::from_utf8(line).with_context(|| {
+                format!(
+                    "Invalid UTF-8 on line {} in {:?}",
+                    first_line + line_num,
+                    path
+                )
+            })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = Event::from_json(line).with_context(|| {
+                format!(
+                    "Failed to parse line {} in {:?}",
+                    first_line + line_num,
+                    path
+                )
+            })?;
+            events.push(event);
+        }
+        sort_events(&mut events);
+        Ok(events)
+    }
+
+    /// Read an event file, skipping lines that aren't valid events
+    ///
+    /// Unlike `parse_events_from_file`, one torn or hand-mangled line doesn't
+    /// make the rest of the file unreadable. Returns the events in replay
+    /// order and the skipped lines, for the caller to report.
+    pub fn read_events_lenient(&self, path: &Path) -> Result<(Vec<Event>, Vec<BadLine>)> {
+        let bytes = fs::read(path).with_context(|| format!("Failed to open {:?}", path))?;
+        Ok(self.parse_events_lenient(&bytes, 1))
+    }
+
+    /// Parse a slice of an event file starting at line `first_line`, skipping
+    /// lines that aren't valid events
+    pub fn parse_events_lenient(
+        &self,
+        bytes: &[u8],
+        first_line: usize,
+    ) -> (Vec<Event>, Vec<BadLine>) {
+        parse_events_lenient(bytes, first_line)
+    }
+}
+
+/// Parse event lines starting at line `first_line`, skipping lines that
+/// aren't valid events
+///
+/// Returns the events in replay order and the skipped lines.
+pub fn parse_events_lenient(bytes: &[u8], first_line: usize) -> (Vec<Event>, Vec<BadLine>) {
+    let mut events = Vec::new();
+    let mut bad = Vec::new();
+    for (line_num, line) in bytes.split(|b| *b == b'\n').enumerate() {
+        let parsed = std::str::from_utf8(line)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| {
+                if line.trim().is_empty() {
+                    Ok(None)
+                } else {
+                    Event::from_json(line).map(Some)
+                }
+            });
+        match parsed {
+            Ok(Some(event)) => events.push(event),
+            Ok(None) => {}
+            Err(e) => bad.push(BadLine {
+                line: first_line + line_num,
+                text: String::from_utf8_lossy(line).into_owned(),
+                error: format!("{:#}", e),
+            }),
+        }
+    }
+    sort_events(&mut events);
+    (events, bad)
 }
 
-pub fn init() -> Result<()> {
+/// Create `.spool/` in the current directory
+///
+/// With `git`, also register the event log merge driver. With `events_ref`,
+/// keep events on a git ref instead of in the working tree (see `gitref`).
+/// Running with either on an existing spool only adds that integration.
+pub fn init(git: bool, events_ref: bool) -> Result<()> {
     let spool_dir = PathBuf::from(".spool");
 
     if spool_dir.exists() {
-        return Err(anyhow!(".spool directory already exists"));
+        if !git && !events_ref {
+            return Err(anyhow!(".spool directory already exists"));
+        }
+        if git {
+            merge::install_merge_driver(&spool_dir)?;
+            println!("Registered spool merge driver for .spool/");
+        }
+        if events_ref {
+            move_events_to_ref(&spool_dir)?;
+        }
+        return Ok(());
     }
 
     fs::create_dir_all(spool_dir.join("events"))?;
@@ -114,6 +266,13 @@ pub fn init() -> Result<()> {
 # Materialized state: current snapshot of all tasks
 .state.json
 
+# Optional SQLite query index (config.json "sqlite_index")
+.index.db
+.index.db-*
+
+# Write lock, present only while a write is in progress
+.lock
+
 # Any temporary files from tooling
 *.tmp
 *.bak
@@ -131,5 +290,41 @@ pub fn init() -> Result<()> {
     println!("  .spool/.gitignore  - Ignores derived files");
     println!("  .spool/version.json - Format version tracking");
 
+    if git {
+        merge::install_merge_driver(&spool_dir)?;
+        println!("  .spool/.gitattributes - Merges event logs with `spool merge-driver`");
+    }
+
+    if events_ref {
+        move_events_to_ref(&spool_dir)?;
+    }
+
+    Ok(())
+}
+
+/// Configure the spool at `spool_dir` to keep events on a git ref, copying
+/// any event files in the working tree onto it
+fn move_events_to_ref(spool_dir: &Path) -> Result<()> {
+    let ctx = SpoolContext::new(fs::canonicalize(spool_dir)?);
+    let mut config = Config::load(&ctx)?;
+    let ref_name = config
+        .events_ref
+        .clone()
+        .unwrap_or_else(|| gitref::DEFAULT_REF.to_string());
+    let target = ctx.root.parent().unwrap_or(&ctx.root);
+    let target = GitRefStore::new(target, &ref_name);
+
+    let _lock = FileLock::wait(&ctx)?;
+    // `ctx` has no ref yet, so this reads the working tree
+    let imported = gitref::import(&target, &ctx)?;
+    config.events_ref = Some(ref_name.clone());
+    config.save(&ctx)?;
+
+    println!("Events are now stored on {}", ref_name);
+    if imported > 0 {
+        println!("  Copied {} event file(s) from the working tree", imported);
+        println!("  Remove them with: git rm -r .spool/events .spool/archive");
+    }
+    println!("  Share them with: spool sync");
     Ok(())
 }
@@ -0,0 +1,372 @@
+//! Relationships between tasks
+//!
+//! A task can block other tasks and have a parent. Blocking is recorded on
+//! both tasks (`blocks` on one, `blocked_by` on the other), and `link_tasks`
+//! and `unlink_tasks` in `writer` keep the two sides in step. Only the child
+//! records its parent; a task's children are found by looking it up.
+//!
+//! Links written by hand, or made on different branches and then merged,
+//! may still have only one side recorded or form a cycle, so the queries
+//! here read both sides and never loop.
+
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use crate::state::{State, Task, TaskStatus};
+
+/// Kind of link, named from the task the link is written on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    /// The task has to be done before the target
+    Blocks,
+    /// The target has to be done before the task
+    BlockedBy,
+    /// The target is the task's parent
+    Parent,
+}
+
+impl Relation {
+    /// Value of `LinkPayload::rel`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Relation::Blocks => "blocks",
+            Relation::BlockedBy => "blocked_by",
+            Relation::Parent => "parent",
+        }
+    }
+
+    /// The same link as recorded on the target, if it's recorded there
+    pub fn inverse(&self) -> Option<Relation> {
+        match self {
+            Relation::Blocks => Some(Relation::BlockedBy),
+            Relation::BlockedBy => Some(Relation::Blocks),
+            Relation::Parent => None,
+        }
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Whether `task` records a link of kind `rel` to `target`
+pub fn has_link(task: &Task, rel: Relation, target: &str) -> bool {
+    match rel {
+        Relation::Blocks => task.blocks.iter().any(|t| t == target),
+        Relation::BlockedBy => task.blocked_by.iter().any(|t| t == target),
+        Relation::Parent => task.parent.as_deref() == Some(target),
+    }
+}
+
+/// Blocking links and parents of every task, indexed in both directions
+pub struct LinkGraph {
+    blockers: HashMap<String, BTreeSet<String>>,
+    dependents: HashMap<String, BTreeSet<String>>,
+    parents: HashMap<String, String>,
+}
+
+impl LinkGraph {
+    pub fn new(state: &State) -> Self {
+        let mut graph = Self {
+            blockers: HashMap::new(),
+            dependents: HashMap::new(),
+            parents: HashMap::new(),
+        };
+        for task in state.tasks.values() {
+            for other in &task.blocks {
+                graph.add_block(&task.id, other);
+            }
+            for other in &task.blocked_by {
+                graph.add_block(other, &task.id);
+            }
+            if let Some(parent) = &task.parent {
+                graph.parents.insert(task.id.clone(), parent.clone());
+            }
+        }
+        graph
+    }
+
+    fn add_block(&mut self, blocker: &str, blocked: &str) {
+        self.dependents
+            .entry(blocker.to_string())
+            .or_default()
+            .insert(blocked.to_string());
+        self.blockers
+            .entry(blocked.to_string())
+            .or_default()
+            .insert(blocker.to_string());
+    }
+
+    /// Tasks that have to be done before `id`, from either side of the link
+    pub fn blockers(&self, id: &str) -> Vec<&str> {
+        Self::ids(self.blockers.get(id))
+    }
+
+    /// Tasks waiting on `id`, from either side of the link
+    pub fn dependents(&self, id: &str) -> Vec<&str> {
+        Self::ids(self.dependents.get(id))
+    }
+
+    fn ids(set: Option<&BTreeSet<String>>) -> Vec<&str> {
+        set.map_or_else(Vec::new, |set| set.iter().map(String::as_str).collect())
+    }
+
+    pub fn parent(&self, id: &str) -> Option<&str> {
+        self.parents.get(id).map(String::as_str)
+    }
+
+    /// The cycle that linking `id` to `target` would close, if any
+    ///
+    /// Returned as the path of task IDs around the cycle, starting and
+    /// ending with `id`.
+    pub fn link_cycle(&self, id: &str, rel: Relation, target: &str) -> Option<Vec<String>> {
+        if id == target {
+            return Some(vec![id.to_string(), id.to_string()]);
+        }
+        match rel {
+            // id blocks target: a cycle if target already leads to id
+            Relation::Blocks => {
+                let mut path = self.blocking_path(target, id)?;
+                path.insert(0, id.to_string());
+                Some(path)
+            }
+            // target blocks id: a cycle if id already leads to target
+            Relation::BlockedBy => {
+                let mut path = self.blocking_path(id, target)?;
+                path.push(id.to_string());
+                Some(path)
+            }
+            // A cycle if id is among target's ancestors
+            Relation::Parent => {
+                let mut path = vec![id.to_string(), target.to_string()];
+                let mut seen: HashSet<&str> = HashSet::from([target]);
+                let mut current = target;
+                while let Some(parent) = self.parent(current) {
+                    path.push(parent.to_string());
+                    if parent == id {
+                        return Some(path);
+                    }
+                    if !seen.insert(parent) {
+                        return None;
+                    }
+                    current = parent;
+                }
+                None
+            }
+        }
+    }
+
+    /// Shortest chain of blocking links from `from` to `to`, both included
+    fn blocking_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        // Breadth-first, remembering where each task was reached from
+        let mut queue: Vec<(&str, usize)> = vec![(from, usize::MAX)];
+        let mut seen: HashSet<&str> = HashSet::from([from]);
+        let mut i = 0;
+        while i < queue.len() {
+            if queue[i].0 == to {
+                let mut path = Vec::new();
+                let mut at = i;
+                while at != usize::MAX {
+                    path.push(queue[at].0.to_string());
+                    at = queue[at].1;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for id in self.dependents(queue[i].0) {
+                if seen.insert(id) {
+                    queue.push((id, i));
+                }
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// Every cycle among blocking links and among parents
+    ///
+    /// Each cycle is listed once, as a path starting and ending with its
+    /// smallest task ID.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut found: BTreeSet<Vec<String>> = BTreeSet::new();
+        for (blocker, dependents) in &self.dependents {
+            // A cycle through this link leads back from the dependent
+            for dependent in dependents {
+                if let Some(mut path) = self.blocking_path(dependent, blocker) {
+                    path.insert(0, blocker.clone());
+                    found.insert(normalize(path));
+                }
+            }
+        }
+        for (id, parent) in &self.parents {
+            if let Some(path) = self.link_cycle(id, Relation::Parent, parent) {
+                found.insert(normalize(path));
+            }
+        }
+        found.into_iter().collect()
+    }
+}
+
+/// Rotate a closed path so it starts at its smallest ID
+fn normalize(mut path: Vec<String>) -> Vec<String> {
+    path.pop();
+    let start = (0..path.len()).min_by_key(|&i| &path[i]).unwrap_or(0);
+    path.rotate_left(start);
+    if let Some(first) = path.first().cloned() {
+        path.push(first);
+    }
+    path
+}
+
+/// Completed and total number of a task's subtasks, at any depth
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    pub complete: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn open(&self) -> usize {
+        self.total - self.complete
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.complete, self.total)
+    }
+}
+
+/// Parent/child links between tasks, with which tasks are complete
+#[derive(Default)]
+pub struct Hierarchy {
+    /// Every task added, in order, and whether it's complete
+    tasks: Vec<(String, bool)>,
+    index: HashMap<String, usize>,
+    parents: HashMap<String, String>,
+    children: HashMap<String, Vec<String>>,
+}
+
+impl Hierarchy {
+    /// Hierarchy of `tasks`, with children listed oldest first
+    pub fn new<'a>(tasks: impl IntoIterator<Item = &'a Task>) -> Self {
+        let mut tasks: Vec<&Task> = tasks.into_iter().collect();
+        tasks.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+        let mut hierarchy = Self::default();
+        for task in tasks {
+            hierarchy.add(
+                &task.id,
+                task.parent.as_deref(),
+                task.status == TaskStatus::Complete,
+            );
+        }
+        hierarchy
+    }
+
+    /// Record a task; tasks and children are listed in the order they're added
+    pub fn add(&mut self, id: &str, parent: Option<&str>, complete: bool) {
+        self.index.insert(id.to_string(), self.tasks.len());
+        self.tasks.push((id.to_string(), complete));
+        if let Some(parent) = parent {
+            self.parents.insert(id.to_string(), parent.to_string());
+            self.children
+                .entry(parent.to_string())
+                .or_default()
+                .push(id.to_string());
+        }
+    }
+
+    fn is_complete(&self, id: &str) -> bool {
+        self.index.get(id).is_some_and(|&i| self.tasks[i].1)
+    }
+
+    /// Tasks whose parent is `id`
+    pub fn children(&self, id: &str) -> &[String] {
+        self.children.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Tasks with subtasks but no parent among the tasks added
+    pub fn roots(&self) -> Vec<&str> {
+        self.tasks
+            .iter()
+            .map(|(id, _)| id.as_str())
+            .filter(|id| {
+                self.children.contains_key(*id)
+                    && self
+                        .parents
+                        .get(*id)
+                        .map_or(true, |parent| !self.index.contains_key(parent))
+            })
+            .collect()
+    }
+
+    /// Every task below `id`
+    pub fn descendants(&self, id: &str) -> Vec<&str> {
+        let mut found: Vec<&str> = Vec::new();
+        let mut seen: HashSet<&str> = HashSet::from([id]);
+        let mut i = 0;
+        let mut current = id;
+        loop {
+            for child in self.children(current) {
+                if seen.insert(child) {
+                    found.push(child);
+                }
+            }
+            let Some(next) = found.get(i) else {
+                return found;
+            };
+            current = next;
+            i += 1;
+        }
+    }
+
+    pub fn progress(&self, id: &str) -> Progress {
+        let descendants = self.descendants(id);
+        Progress {
+            complete: descendants.iter().filter(|id| self.is_complete(id)).count(),
+            total: descendants.len(),
+        }
+    }
+}
+
+/// Sort key for a priority string: `p0` first, then `p1` and so on, with
+/// other values and no priority last
+pub fn priority_rank(priority: Option<&str>) -> u32 {
+    priority
+        .and_then(|p| p.strip_prefix('p'))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(u32::MAX)
+}
+
+/// Open, unassigned tasks whose blockers are all complete, best first
+///
+/// Tasks are ranked by priority, then oldest first. A blocker that doesn't
+/// exist (see `validate`) doesn't hold a task up.
+pub fn ready_tasks(state: &State) -> Vec<&Task> {
+    let graph = LinkGraph::new(state);
+    let mut tasks: Vec<&Task> = state
+        .tasks
+        .values()
+        .filter(|task| {
+            task.status == TaskStatus::Open
+                && task.assignee.is_none()
+                && task.archived.is_none()
+                && graph.blockers(&task.id).iter().all(|id| {
+                    state
+                        .tasks
+                        .get(*id)
+                        .map_or(true, |blocker| blocker.status == TaskStatus::Complete)
+                })
+        })
+        .collect();
+    tasks.sort_by(|a, b| {
+        priority_rank(a.priority.as_deref())
+            .cmp(&priority_rank(b.priority.as_deref()))
+            .then_with(|| a.created.cmp(&b.created))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    tasks
+}
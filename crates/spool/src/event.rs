@@ -1,5 +1,21 @@
+use anyhow::{anyhow, Context, Result};
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use crate::clock::Hlc;
+use crate::payload::Payload;
+
+/// Newest event schema version this build reads and writes
+pub const CURRENT_VERSION: u32 = 1;
+
+/// Upcasters from each older schema version to the next one
+///
+/// `UPCASTERS[n]` rewrites a raw version `n + 1` event into version `n + 2`.
+/// When the schema changes, bump `CURRENT_VERSION` and append a step here;
+/// older events are then upgraded on read and never rewritten on disk.
+const UPCASTERS: &[fn(Value) -> Result<Value>] = &[];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
@@ -7,19 +23,150 @@ pub struct Event {
     pub op: Operation,
     pub id: String,
     pub ts: DateTime<Utc>,
+    /// Hybrid logical clock, stamped by the writer; absent on older events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hlc: Option<Hlc>,
+    /// IDs of the same task's events the writer had seen (see `conflict`);
+    /// empty on older events and on stream events
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prev: Vec<String>,
+    /// Transaction this event was written in, if written as part of a group
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub txn: Option<Txn>,
     pub by: String,
     pub branch: String,
     pub d: serde_json::Value,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "snake_case")]
+/// Marks an event as one of a group written all-or-nothing (see
+/// `writer::Transaction`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Txn {
+    /// Shared by every event in the group
+    pub id: String,
+    /// Number of events in the group
+    pub n: u32,
+}
+
+impl Event {
+    /// Build a new event for `id` from a typed payload, stamped with the current time
+    pub fn new(id: &str, payload: Payload, by: &str, branch: &str) -> Self {
+        Self {
+            v: CURRENT_VERSION,
+            op: payload.operation(),
+            id: id.to_string(),
+            ts: Utc::now(),
+            hlc: None,
+            prev: Vec::new(),
+            txn: None,
+            by: by.to_string(),
+            branch: branch.to_string(),
+            d: payload.to_value(),
+        }
+    }
+
+    /// Parse a JSONL line, upcasting events written with an older schema version
+    pub fn from_json(line: &str) -> Result<Self> {
+        let raw: Value = serde_json::from_str(line)?;
+        let raw = upcast(raw)?;
+        Ok(serde_json::from_value(raw)?)
+    }
+
+    /// Decode the typed payload for this event's operation
+    pub fn payload(&self) -> Result<Payload> {
+        Payload::decode(&self.op, &self.d)
+    }
+
+    /// Position of this event in replay order, falling back to the timestamp
+    /// for events written before clocks existed
+    pub fn clock(&self) -> Hlc {
+        self.hlc.unwrap_or_else(|| Hlc::from_ts(self.ts))
+    }
+
+    /// Stable identity of this event, derived from its content
+    ///
+    /// Cherry-picks, rebases and "keep both sides" merges copy event lines
+    /// verbatim, so two events with the same hash are the same event.
+    pub fn content_hash(&self) -> u64 {
+        // Object keys serialize in sorted order, so this is canonical
+        let json = serde_json::to_string(self).expect("event serializes to JSON");
+        fnv1a(json.as_bytes())
+    }
+
+    /// Stable identifier of this event: its content hash in hex
+    pub fn event_id(&self) -> String {
+        format!("{:016x}", self.content_hash())
+    }
+
+    /// Whether this build understands the event's schema version and operation
+    pub fn is_supported(&self) -> bool {
+        self.v <= CURRENT_VERSION && !matches!(self.op, Operation::Unknown(_))
+    }
+}
+
+/// Sort events into replay order (see `replay_cmp`)
+pub fn sort_events(events: &mut [Event]) {
+    events.sort_by(replay_cmp);
+}
+
+/// Replay order: by clock, timestamp, author, then branch
+///
+/// The timestamp keeps sub-millisecond order for events without a clock.
+/// Events that tie on all of these came from one writer at one instant, so
+/// callers sort stably to keep them in the order they were written.
+pub fn replay_cmp(a: &Event, b: &Event) -> Ordering {
+    a.clock()
+        .cmp(&b.clock())
+        .then_with(|| a.ts.cmp(&b.ts))
+        .then_with(|| a.by.cmp(&b.by))
+        .then_with(|| a.branch.cmp(&b.branch))
+}
+
+/// 64-bit FNV-1a, stable across builds so stored hashes stay comparable
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Bring a raw event up to `CURRENT_VERSION`
+///
+/// Events from a newer schema version are returned untouched; readers keep
+/// them so they round-trip, but materialization skips them.
+pub fn upcast(mut raw: Value) -> Result<Value> {
+    let mut version = raw
+        .get("v")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| anyhow!("Missing or invalid schema version"))?;
+
+    if version == 0 {
+        return Err(anyhow!("Unsupported schema version 0"));
+    }
+
+    while version < CURRENT_VERSION as u64 {
+        let step = UPCASTERS[(version - 1) as usize];
+        raw = step(raw).with_context(|| format!("Failed to upcast event from v{}", version))?;
+        version += 1;
+        raw["v"] = Value::from(version);
+    }
+
+    Ok(raw)
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Operation {
     // Task operations
     Create,
     Update,
     Assign,
     Comment,
+    EditComment,
+    DeleteComment,
+    Tag,
+    Untag,
     Link,
     Unlink,
     Complete,
@@ -30,24 +177,75 @@ pub enum Operation {
     CreateStream,
     UpdateStream,
     DeleteStream,
+    /// An operation this build doesn't know, most likely written by a newer spool
+    Unknown(String),
 }
 
-impl std::fmt::Display for Operation {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Operation {
+    pub fn as_str(&self) -> &str {
         match self {
-            Operation::Create => write!(f, "create"),
-            Operation::Update => write!(f, "update"),
-            Operation::Assign => write!(f, "assign"),
-            Operation::Comment => write!(f, "comment"),
-            Operation::Link => write!(f, "link"),
-            Operation::Unlink => write!(f, "unlink"),
-            Operation::Complete => write!(f, "complete"),
-            Operation::Reopen => write!(f, "reopen"),
-            Operation::Archive => write!(f, "archive"),
-            Operation::SetStream => write!(f, "set_stream"),
-            Operation::CreateStream => write!(f, "create_stream"),
-            Operation::UpdateStream => write!(f, "update_stream"),
-            Operation::DeleteStream => write!(f, "delete_stream"),
+            Operation::Create => "create",
+            Operation::Update => "update",
+            Operation::Assign => "assign",
+            Operation::Comment => "comment",
+            Operation::EditComment => "edit_comment",
+            Operation::DeleteComment => "delete_comment",
+            Operation::Tag => "tag",
+            Operation::Untag => "untag",
+            Operation::Link => "link",
+            Operation::Unlink => "unlink",
+            Operation::Complete => "complete",
+            Operation::Reopen => "reopen",
+            Operation::Archive => "archive",
+            Operation::SetStream => "set_stream",
+            Operation::CreateStream => "create_stream",
+            Operation::UpdateStream => "update_stream",
+            Operation::DeleteStream => "delete_stream",
+            Operation::Unknown(op) => op,
+        }
+    }
+}
+
+impl From<&str> for Operation {
+    fn from(s: &str) -> Self {
+        match s {
+            "create" => Operation::Create,
+            "update" => Operation::Update,
+            "assign" => Operation::Assign,
+            "comment" => Operation::Comment,
+            "edit_comment" => Operation::EditComment,
+            "delete_comment" => Operation::DeleteComment,
+            "tag" => Operation::Tag,
+            "untag" => Operation::Untag,
+            "link" => Operation::Link,
+            "unlink" => Operation::Unlink,
+            "complete" => Operation::Complete,
+            "reopen" => Operation::Reopen,
+            "archive" => Operation::Archive,
+            "set_stream" => Operation::SetStream,
+            "create_stream" => Operation::CreateStream,
+            "update_stream" => Operation::UpdateStream,
+            "delete_stream" => Operation::DeleteStream,
+            other => Operation::Unknown(other.to_string()),
         }
     }
 }
+
+impl Serialize for Operation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Operation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(Operation::from(s.as_str()))
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
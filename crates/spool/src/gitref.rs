@@ -0,0 +1,516 @@
+//! Event storage on a dedicated git ref
+//!
+//! By default events live in `.spool/events/`, so every branch that touches
+//! a task carries the event lines in its diff. With `"events_ref"` set in
+//! `.spool/config.json`, segments are instead kept as files in commits on
+//! that ref (`refs/spool/events` by default), written with plumbing commands
+//! and never checked out. Task state is then the same whichever branch is
+//! checked out, and `spool sync` shares it with a remote: it fetches the
+//! remote's ref, merges it with the local one (see `merge`), and pushes the
+//! result.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::concurrency::FileLock;
+use crate::context::SpoolContext;
+use crate::merge::merge_event_logs;
+use crate::store::{segment_name, EventStore, ARCHIVE, EVENTS};
+
+/// Ref used by `spool init --events-ref`
+pub const DEFAULT_REF: &str = "refs/spool/events";
+
+/// Author and committer of commits on the events ref; events record who
+/// made each change themselves
+const IDENTITY: [(&str, &str); 4] = [
+    ("GIT_AUTHOR_NAME", "spool"),
+    ("GIT_AUTHOR_EMAIL", "spool@localhost"),
+    ("GIT_COMMITTER_NAME", "spool"),
+    ("GIT_COMMITTER_EMAIL", "spool@localhost"),
+];
+
+/// A commit's segments: path to blob ID
+type Tree = BTreeMap<String, String>;
+
+/// Segments stored in commits on a git ref
+#[derive(Debug)]
+pub struct GitRefStore {
+    /// Any directory inside the repository
+    dir: PathBuf,
+    ref_name: String,
+    cache: Mutex<Cache>,
+    lock: Mutex<()>,
+}
+
+/// The last commit read from the ref, and the contents of blobs seen so far
+#[derive(Debug, Default)]
+struct Cache {
+    commit: Option<String>,
+    tree: Tree,
+    blobs: HashMap<String, Vec<u8>>,
+}
+
+/// What `sync` did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+    /// Remote events were new to this clone
+    pub pulled: bool,
+    /// Both sides had new events, so a merge commit was made
+    pub merged: bool,
+    /// Local events were pushed to the remote
+    pub pushed: bool,
+}
+
+impl GitRefStore {
+    pub fn new(dir: impl Into<PathBuf>, ref_name: &str) -> Self {
+        Self {
+            dir: dir.into(),
+            ref_name: ref_name.to_string(),
+            cache: Mutex::default(),
+            lock: Mutex::default(),
+        }
+    }
+
+    pub fn ref_name(&self) -> &str {
+        &self.ref_name
+    }
+
+    /// Fetch the ref from `remote`, merge it into the local one, and push
+    /// the result back
+    ///
+    /// Callers hold the write lock, so no local events are written while the
+    /// ref moves.
+    pub fn sync(&self, remote: &str) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+        let tracking = format!(
+            "refs/spool-remotes/{}/{}",
+            remote,
+            self.ref_name.trim_start_matches("refs/")
+        );
+
+        let listed = self.git(&["ls-remote", remote, &self.ref_name], None)?;
+        let theirs = if listed.is_empty() {
+            None
+        } else {
+            let refspec = format!("+{}:{}", self.ref_name, tracking);
+            self.git(&["fetch", "--quiet", remote, &refspec], None)?;
+            self.resolve(&tracking)?
+        };
+        let ours = self.resolve(&self.ref_name)?;
+
+        let head = match (ours, theirs.clone()) {
+            (ours, None) => ours,
+            (None, Some(theirs)) => {
+                self.update_ref(&theirs, None)?;
+                report.pulled = true;
+                Some(theirs)
+            }
+            (Some(ours), Some(theirs)) if ours == theirs => Some(ours),
+            (Some(ours), Some(theirs)) => {
+                if self.is_ancestor(&theirs, &ours)? {
+                    Some(ours)
+                } else if self.is_ancestor(&ours, &theirs)? {
+                    self.update_ref(&theirs, Some(&ours))?;
+                    report.pulled = true;
+                    Some(theirs)
+                } else {
+                    let merged = self.merge(&ours, &theirs)?;
+                    report.pulled = true;
+                    report.merged = true;
+                    Some(merged)
+                }
+            }
+        };
+
+        if let Some(head) = head {
+            if Some(&head) != theirs.as_ref() {
+                let refspec = format!("{}:{}", self.ref_name, self.ref_name);
+                self.git(&["push", "--quiet", remote, &refspec], None)
+                    .context("Push rejected (run 'spool sync' again to merge)")?;
+                self.update_tracking(&tracking, &head)?;
+                report.pushed = true;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Commit the union of two diverged histories
+    fn merge(&self, ours: &str, theirs: &str) -> Result<String> {
+        let base = match self.git(&["merge-base", ours, theirs], None) {
+            Ok(out) => Some(text(out)),
+            Err(_) => None,
+        };
+        let base_tree = match &base {
+            Some(base) => self.read_tree(base)?,
+            None => Tree::new(),
+        };
+        let our_tree = self.read_tree(ours)?;
+        let their_tree = self.read_tree(theirs)?;
+
+        let paths: BTreeSet<&String> = base_tree
+            .keys()
+            .chain(our_tree.keys())
+            .chain(their_tree.keys())
+            .collect();
+        let mut tree = Tree::new();
+        for path in paths {
+            let content = |tree: &Tree| -> Result<String> {
+                match tree.get(path) {
+                    Some(blob) => Ok(String::from_utf8_lossy(&self.read_blob(blob)?).into_owned()),
+                    None => Ok(String::new()),
+                }
+            };
+            let merged = merge_event_logs(
+                &content(&base_tree)?,
+                &content(&our_tree)?,
+                &content(&their_tree)?,
+            );
+            if !merged.is_empty() {
+                tree.insert(path.clone(), self.write_blob(merged.as_bytes())?);
+            }
+        }
+
+        let message = format!("spool: merge {}", theirs);
+        let commit = self.commit(&tree, &[ours, theirs], &message)?;
+        self.update_ref(&commit, Some(ours))?;
+        Ok(commit)
+    }
+
+    /// Apply changes to the current commit's segments and commit the result;
+    /// `None` (or empty content) removes a segment
+    fn change(&self, changes: &[(&str, Option<&[u8]>)], message: &str) -> Result<()> {
+        let mut cache = self.refresh()?;
+        let mut tree = cache.tree.clone();
+        for (segment, content) in changes {
+            match content {
+                Some(content) if !content.is_empty() => {
+                    let blob = self.write_blob(content)?;
+                    cache.blobs.insert(blob.clone(), content.to_vec());
+                    tree.insert(segment.to_string(), blob);
+                }
+                _ => {
+                    tree.remove(*segment);
+                }
+            }
+        }
+
+        let parent = cache.commit.clone();
+        let parents: Vec<&str> = parent.iter().map(String::as_str).collect();
+        let commit = self.commit(&tree, &parents, message)?;
+        self.update_ref(&commit, parent.as_deref())?;
+        cache.commit = Some(commit);
+        cache.tree = tree;
+        Ok(())
+    }
+
+    /// A segment's contents at the ref's current commit, if it has one
+    fn current(&self, segment: &str) -> Result<Option<Vec<u8>>> {
+        let cache = self.refresh()?;
+        Ok(cache
+            .tree
+            .get(segment)
+            .and_then(|blob| cache.blobs.get(blob))
+            .cloned())
+    }
+
+    /// Bring the cache up to the ref's current commit
+    fn refresh(&self) -> Result<MutexGuard<'_, Cache>> {
+        let mut cache = self
+            .cache
+            .lock()
+            .map_err(|_| anyhow!("Event ref cache poisoned by a panicked writer"))?;
+        let commit = self.resolve(&self.ref_name)?;
+        if commit == cache.commit {
+            return Ok(cache);
+        }
+
+        let tree = match &commit {
+            Some(commit) => self.read_tree(commit)?,
+            None => Tree::new(),
+        };
+        let missing: Vec<&String> = tree
+            .values()
+            .filter(|blob| !cache.blobs.contains_key(*blob))
+            .collect();
+        if !missing.is_empty() {
+            let blobs = self.read_blobs(&missing)?;
+            cache.blobs.extend(blobs);
+        }
+        cache.commit = commit;
+        cache.tree = tree;
+        Ok(cache)
+    }
+
+    /// Segments in a commit
+    fn read_tree(&self, commit: &str) -> Result<Tree> {
+        let out = self.git(&["ls-tree", "-r", "-z", commit], None)?;
+        let mut tree = Tree::new();
+        for entry in out.split(|b| *b == 0).filter(|e| !e.is_empty()) {
+            let entry = String::from_utf8_lossy(entry);
+            let Some((meta, path)) = entry.split_once('\t') else {
+                continue;
+            };
+            // Day files and their author shards, and monthly archives
+            let depth = |prefix| segment_name(path, prefix).map(|name| name.matches('/').count());
+            let is_segment = path.ends_with(".jsonl")
+                && (depth(EVENTS).is_some_and(|d| d <= 1) || depth(ARCHIVE) == Some(0));
+            if let (true, Some(blob)) = (is_segment, meta.split(' ').nth(2)) {
+                tree.insert(path.to_string(), blob.to_string());
+            }
+        }
+        Ok(tree)
+    }
+
+    fn read_blob(&self, blob: &str) -> Result<Vec<u8>> {
+        if let Some(content) = self
+            .cache
+            .lock()
+            .ok()
+            .and_then(|c| c.blobs.get(blob).cloned())
+        {
+            return Ok(content);
+        }
+        self.git(&["cat-file", "blob", blob], None)
+    }
+
+    /// Read many blobs with a single `git cat-file --batch`
+    fn read_blobs(&self, blobs: &[&String]) -> Result<HashMap<String, Vec<u8>>> {
+        let mut input = String::new();
+        for blob in blobs {
+            input.push_str(blob);
+            input.push('\n');
+        }
+        let out = self.git(&["cat-file", "--batch"], Some(input.as_bytes()))?;
+
+        // Each object is "<id> <type> <size>\n<content>\n"
+        let mut contents = HashMap::new();
+        let mut rest = &out[..];
+        while !rest.is_empty() {
+            let header_end = rest
+                .iter()
+                .position(|b| *b == b'\n')
+                .ok_or_else(|| anyhow!("Truncated output from git cat-file"))?;
+            let header = String::from_utf8_lossy(&rest[..header_end]).into_owned();
+            let mut fields = header.split(' ');
+            let (Some(id), Some(_), Some(size)) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(anyhow!("Unexpected output from git cat-file: {}", header));
+            };
+            let size: usize = size.parse()?;
+            let start = header_end + 1;
+            if rest.len() < start + size {
+                return Err(anyhow!("Truncated output from git cat-file"));
+            }
+            contents.insert(id.to_string(), rest[start..start + size].to_vec());
+            rest = rest.get(start + size + 1..).unwrap_or_default();
+        }
+        Ok(contents)
+    }
+
+    fn write_blob(&self, content: &[u8]) -> Result<String> {
+        Ok(text(
+            self.git(&["hash-object", "-w", "--stdin"], Some(content))?,
+        ))
+    }
+
+    /// Write the tree for a set of segments and commit it
+    fn commit(&self, tree: &Tree, parents: &[&str], message: &str) -> Result<String> {
+        let entries: Vec<(&str, &str)> = tree
+            .iter()
+            .map(|(path, blob)| (path.as_str(), blob.as_str()))
+            .collect();
+        let root = self.write_tree(&entries)?;
+
+        let mut args = vec!["commit-tree", root.as_str(), "-m", message];
+        for parent in parents {
+            args.extend(["-p", parent]);
+        }
+        Ok(text(self.git(&args, None)?))
+    }
+
+    /// Write a tree holding blobs at the given paths (relative to it)
+    fn write_tree(&self, entries: &[(&str, &str)]) -> Result<String> {
+        let mut listing = String::new();
+        let mut dirs: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+        for (path, blob) in entries {
+            match path.split_once('/') {
+                Some((dir, rest)) => dirs.entry(dir).or_default().push((rest, blob)),
+                None => listing.push_str(&format!("100644 blob {}\t{}\n", blob, path)),
+            }
+        }
+        for (dir, entries) in &dirs {
+            let subtree = self.write_tree(entries)?;
+            listing.push_str(&format!("040000 tree {}\t{}\n", subtree, dir));
+        }
+        Ok(text(self.git(&["mktree"], Some(listing.as_bytes()))?))
+    }
+
+    /// Move the ref to `commit` if it's still at `old` (`None`: doesn't exist)
+    fn update_ref(&self, commit: &str, old: Option<&str>) -> Result<()> {
+        self.git(
+            &["update-ref", &self.ref_name, commit, old.unwrap_or("")],
+            None,
+        )
+        .with_context(|| format!("{} moved during the update; try again", self.ref_name))?;
+        Ok(())
+    }
+
+    fn update_tracking(&self, tracking: &str, commit: &str) -> Result<()> {
+        self.git(&["update-ref", tracking, commit], None)?;
+        Ok(())
+    }
+
+    fn resolve(&self, name: &str) -> Result<Option<String>> {
+        let spec = format!("{}^{{commit}}", name);
+        let output = self.command(&["rev-parse", "--verify", "--quiet", &spec])?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+    }
+
+    fn is_ancestor(&self, ancestor: &str, commit: &str) -> Result<bool> {
+        let output = self.command(&["merge-base", "--is-ancestor", ancestor, commit])?;
+        Ok(output.status.success())
+    }
+
+    fn command(&self, args: &[&str]) -> Result<std::process::Output> {
+        Command::new("git")
+            .current_dir(&self.dir)
+            .args(args)
+            .output()
+            .context("Failed to run git")
+    }
+
+    /// Run git, returning its stdout or failing with its stderr
+    fn git(&self, args: &[&str], stdin: Option<&[u8]>) -> Result<Vec<u8>> {
+        run_git(&self.dir, args, stdin)
+    }
+}
+
+fn run_git(dir: &Path, args: &[&str], stdin: Option<&[u8]>) -> Result<Vec<u8>> {
+    let mut child = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .envs(IDENTITY)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git")?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn text(out: Vec<u8>) -> String {
+    String::from_utf8_lossy(&out).trim().to_string()
+}
+
+impl EventStore for GitRefStore {
+    /// Only excludes writers in this process; `SpoolContext` adds its lock file
+    type Lock<'a> = MutexGuard<'a, ()>;
+
+    fn segments(&self) -> Result<Vec<String>> {
+        Ok(self.refresh()?.tree.keys().cloned().collect())
+    }
+
+    fn read(&self, segment: &str) -> Result<Vec<u8>> {
+        let cache = self
+            .cache
+            .lock()
+            .map_err(|_| anyhow!("Event ref cache poisoned by a panicked writer"))?;
+        // Reads follow a call to `segments`, so they see the same commit
+        let cache = if cache.tree.contains_key(segment) {
+            cache
+        } else {
+            drop(cache);
+            self.refresh()?
+        };
+        cache
+            .tree
+            .get(segment)
+            .and_then(|blob| cache.blobs.get(blob))
+            .cloned()
+            .ok_or_else(|| anyhow!("No segment {} on {}", segment, self.ref_name))
+    }
+
+    fn append(&self, segment: &str, lines: &[u8]) -> Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let mut content = self.current(segment)?.unwrap_or_default();
+        if content.last().is_some_and(|b| *b != b'\n') {
+            content.push(b'\n');
+        }
+        content.extend_from_slice(lines);
+        self.change(
+            &[(segment, Some(&content))],
+            &format!("spool: append to {}", segment),
+        )
+    }
+
+    fn replace(&self, segment: &str, content: &[u8]) -> Result<()> {
+        self.change(
+            &[(segment, Some(content))],
+            &format!("spool: rewrite {}", segment),
+        )
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, ()>> {
+        self.lock
+            .lock()
+            .map_err(|_| anyhow!("Event ref lock poisoned by a panicked writer"))
+    }
+}
+
+/// Sync the repo's events ref with `remote`
+pub fn sync(ctx: &SpoolContext, remote: &str) -> Result<SyncReport> {
+    let git_ref = ctx.git_ref.as_ref().ok_or_else(|| {
+        anyhow!(
+            "Events are stored in the working tree; run 'spool init --events-ref' to use a git ref"
+        )
+    })?;
+    let _lock = FileLock::wait(ctx)?;
+    git_ref.sync(remote)
+}
+
+/// Copy segments from another store onto the ref in one commit, merging
+/// with any already there; returns the number of segments copied
+pub fn import<S: EventStore + ?Sized>(target: &GitRefStore, source: &S) -> Result<usize> {
+    let segments = source.segments()?;
+    let mut merged = Vec::new();
+    for segment in &segments {
+        let incoming = String::from_utf8_lossy(&source.read(segment)?).into_owned();
+        let existing = target.current(segment)?.unwrap_or_default();
+        let existing = String::from_utf8_lossy(&existing).into_owned();
+        merged.push((segment.as_str(), merge_event_logs("", &existing, &incoming)));
+    }
+    if merged.is_empty() {
+        return Ok(0);
+    }
+    let changes: Vec<(&str, Option<&[u8]>)> = merged
+        .iter()
+        .map(|(segment, content)| (*segment, Some(content.as_bytes())))
+        .collect();
+    target.change(&changes, "spool: import events from the working tree")?;
+    Ok(segments.len())
+}
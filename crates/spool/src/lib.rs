@@ -1,15 +1,29 @@
 pub mod archive;
 pub mod cli;
+pub mod clock;
 pub mod concurrency;
+pub mod config;
+pub mod conflict;
 pub mod context;
+pub mod deps;
 pub mod event;
+pub mod gitref;
 pub mod id;
+pub mod merge;
 pub mod migration;
+pub mod payload;
+pub mod query;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 pub mod state;
+pub mod store;
 pub mod validation;
 pub mod writer;
 
 // Re-export commonly used types
 pub use context::{init, SpoolContext};
 pub use event::{Event, Operation};
+pub use payload::Payload;
 pub use state::{rebuild, Stream, Task, TaskStatus};
+pub use store::{EventStore, Layout, MemoryStore};
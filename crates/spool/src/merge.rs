@@ -0,0 +1,165 @@
+//! Git merge driver for event files
+//!
+//! Event files are append-only, so two branches that both add events to the
+//! same day's file produce a textual conflict even though nothing actually
+//! conflicts. The driver resolves such merges by taking the union of both
+//! sides, dropping duplicates and anything either side removed, and writing
+//! the result in replay order.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::event::{replay_cmp, Event};
+
+/// Name of the merge driver in git config and `.gitattributes`
+pub const DRIVER_NAME: &str = "spool";
+
+const GITATTRIBUTES: &str = "# Merge append-only event logs with `spool merge-driver`
+events/*.jsonl merge=spool
+events/*/*.jsonl merge=spool
+archive/*.jsonl merge=spool
+";
+
+/// Identity of a line: events by content, anything unparseable by its text
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LineKey {
+    Event(u64),
+    Raw(String),
+}
+
+struct Line<'a> {
+    key: LineKey,
+    event: Option<Event>,
+    text: &'a str,
+}
+
+fn parse_lines(content: &str) -> Vec<Line<'_>> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|text| match Event::from_json(text) {
+            Ok(event) => Line {
+                key: LineKey::Event(event.content_hash()),
+                event: Some(event),
+                text,
+            },
+            Err(_) => Line {
+                key: LineKey::Raw(text.to_string()),
+                event: None,
+                text,
+            },
+        })
+        .collect()
+}
+
+/// Three-way merge of event file contents
+///
+/// Keeps every line present on either side, except lines from `base` that
+/// one side removed (e.g. by archiving or de-duplicating). Events are written
+/// once each, in replay order; lines that don't parse as events are kept
+/// verbatim at the end so `spool validate` can report them.
+pub fn merge_event_logs(base: &str, ours: &str, theirs: &str) -> String {
+    let base = parse_lines(base);
+    let ours = parse_lines(ours);
+    let theirs = parse_lines(theirs);
+
+    let ours_keys: HashSet<&LineKey> = ours.iter().map(|l| &l.key).collect();
+    let theirs_keys: HashSet<&LineKey> = theirs.iter().map(|l| &l.key).collect();
+    let removed: HashSet<&LineKey> = base
+        .iter()
+        .map(|l| &l.key)
+        .filter(|key| !ours_keys.contains(key) || !theirs_keys.contains(key))
+        .collect();
+
+    let mut seen: HashSet<&LineKey> = HashSet::new();
+    let mut events: Vec<(&Event, &str)> = Vec::new();
+    let mut raw: Vec<&str> = Vec::new();
+    for line in ours.iter().chain(theirs.iter()) {
+        if removed.contains(&line.key) || !seen.insert(&line.key) {
+            continue;
+        }
+        match &line.event {
+            Some(event) => events.push((event, line.text)),
+            None => raw.push(line.text),
+        }
+    }
+
+    // Stable, so events that tie keep ours-before-theirs order
+    events.sort_by(|a, b| replay_cmp(a.0, b.0));
+
+    let mut merged = String::new();
+    for text in events.into_iter().map(|(_, text)| text).chain(raw) {
+        merged.push_str(text);
+        merged.push('\n');
+    }
+    merged
+}
+
+/// Entry point for `spool merge-driver %O %A %B`
+///
+/// Git expects the merged result to be written over the `ours` file.
+pub fn merge_driver(base: &Path, ours: &Path, theirs: &Path) -> Result<()> {
+    let read = |path: &Path| {
+        fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))
+    };
+    let merged = merge_event_logs(&read(base)?, &read(ours)?, &read(theirs)?);
+    fs::write(ours, merged).with_context(|| format!("Failed to write {:?}", ours))?;
+    Ok(())
+}
+
+/// Register the merge driver for the spool directory at `spool_dir`
+///
+/// Writes `.gitattributes` inside the spool directory and configures the
+/// driver in the local git config. Safe to run repeatedly.
+pub fn install_merge_driver(spool_dir: &Path) -> Result<()> {
+    let attributes_path = spool_dir.join(".gitattributes");
+    let existing = fs::read_to_string(&attributes_path).unwrap_or_default();
+    let missing: Vec<&str> = GITATTRIBUTES
+        .lines()
+        .filter(|line| !existing.lines().any(|l| l.trim() == line.trim()))
+        .collect();
+    if !missing.is_empty() {
+        let mut content = existing;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        for line in missing {
+            content.push_str(line);
+            content.push('\n');
+        }
+        fs::write(&attributes_path, content)
+            .with_context(|| format!("Failed to write {:?}", attributes_path))?;
+    }
+
+    let section = format!("merge.{}", DRIVER_NAME);
+    git_config(
+        spool_dir,
+        &format!("{}.name", section),
+        "spool event log merge",
+    )?;
+    git_config(
+        spool_dir,
+        &format!("{}.driver", section),
+        "spool merge-driver %O %A %B",
+    )?;
+    Ok(())
+}
+
+fn git_config(dir: &Path, key: &str, value: &str) -> Result<()> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["config", "--local", key, value])
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to set git config {}: {}",
+            key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
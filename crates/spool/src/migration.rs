@@ -6,14 +6,17 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::PathBuf;
 
 use crate::context::SpoolContext;
-use crate::event::{Event, Operation};
+use crate::event::Event;
 use crate::id::generate_id;
-use crate::writer::write_event;
+use crate::merge::merge_event_logs;
+use crate::payload::{CreateStreamPayload, Payload, SetStreamPayload};
+use crate::store::{author_segment, segment_name, EventStore, Layout, EVENTS};
+use crate::writer::Transaction;
 
 /// Current format version
 pub const CURRENT_FORMAT_VERSION: &str = "0.4.0";
@@ -66,12 +69,10 @@ pub fn check_and_migrate(ctx: &SpoolContext) -> Result<()> {
     match version {
         Some(v) if v.format_version == CURRENT_FORMAT_VERSION => {
             // Already at current version, nothing to do
-            Ok(())
         }
         Some(v) => {
             // Old version detected, need migration
             migrate_from_version(ctx, &v.format_version)?;
-            Ok(())
         }
         None => {
             // No version file - either fresh install or pre-0.4.0
@@ -90,9 +91,84 @@ pub fn check_and_migrate(ctx: &SpoolContext) -> Result<()> {
                 // Pre-0.4.0 spool, need migration
                 migrate_from_version(ctx, "0.3.1")?;
             }
-            Ok(())
         }
     }
+
+    // Day files reappear when branches from before the switch are merged
+    if ctx.layout()? == Layout::Author {
+        let sharded = shard_day_files(ctx)?;
+        if sharded > 0 {
+            eprintln!("Split {} day file(s) into author shards.", sharded);
+        }
+    }
+    Ok(())
+}
+
+/// Split each unsharded day file (`events/YYYY-MM-DD.jsonl`) into a shard
+/// per author (`events/YYYY-MM-DD/@alice.jsonl`)
+///
+/// Lines that aren't valid events stay in the day file for `spool repair`.
+/// Shards are written before the day file is removed, and merged with any
+/// already there, so an interrupted split is finished by running it again.
+/// Returns the number of day files split.
+pub fn shard_day_files<S: EventStore + ?Sized>(store: &S) -> Result<usize> {
+    let unsharded = |segments: Vec<String>| -> Vec<String> {
+        segments
+            .into_iter()
+            .filter(|key| segment_name(key, EVENTS).is_some_and(|name| !name.contains('/')))
+            .collect()
+    };
+    if unsharded(store.segments()?).is_empty() {
+        return Ok(0);
+    }
+
+    let _lock = store.lock()?;
+    let segments = store.segments()?;
+    let mut split = 0;
+    for key in unsharded(segments.clone()) {
+        let day = segment_name(&key, EVENTS)
+            .and_then(|name| name.strip_suffix(".jsonl"))
+            .unwrap_or_default();
+        let bytes = store.read(&key)?;
+
+        let mut shards: BTreeMap<String, String> = BTreeMap::new();
+        let mut kept = Vec::new();
+        for line in bytes.split(|b| *b == b'\n') {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let parsed = std::str::from_utf8(line)
+                .ok()
+                .and_then(|text| Some((text, Event::from_json(text).ok()?)));
+            match parsed {
+                Some((text, event)) => {
+                    let shard = shards.entry(author_segment(day, &event.by)).or_default();
+                    shard.push_str(text);
+                    shard.push('\n');
+                }
+                None => {
+                    kept.extend_from_slice(line);
+                    kept.push(b'\n');
+                }
+            }
+        }
+
+        if shards.is_empty() {
+            continue;
+        }
+        for (shard, lines) in &shards {
+            let existing = if segments.contains(shard) {
+                String::from_utf8_lossy(&store.read(shard)?).into_owned()
+            } else {
+                String::new()
+            };
+            let merged = merge_event_logs("", &existing, lines);
+            store.replace(shard, merged.as_bytes())?;
+        }
+        store.replace(&key, &kept)?;
+        split += 1;
+    }
+    Ok(split)
 }
 
 /// Migrate from a specific version to current
@@ -145,13 +221,13 @@ fn migrate_0_3_1_to_0_4_0(ctx: &SpoolContext) -> Result<()> {
 
     // Process archive files first
     for file in ctx.get_archive_files()? {
-        let events = ctx.parse_events_from_file(&file)?;
+        let (events, _) = ctx.read_events_lenient(&file)?;
         collect_stream_info(&events, &mut stream_names, &mut task_streams);
     }
 
     // Then process event files
     for file in ctx.get_event_files()? {
-        let events = ctx.parse_events_from_file(&file)?;
+        let (events, _) = ctx.read_events_lenient(&file)?;
         collect_stream_info(&events, &mut stream_names, &mut task_streams);
     }
 
@@ -166,6 +242,10 @@ fn migrate_0_3_1_to_0_4_0(ctx: &SpoolContext) -> Result<()> {
     let user = get_migration_user();
     let branch = get_migration_branch();
 
+    // Every stream and task reference is written in one transaction, so an
+    // interrupted migration leaves the repo as it was
+    let mut txn = Transaction::new(ctx);
+
     // Create a mapping of stream name -> new stream ID
     let mut stream_id_map: HashMap<String, String> = HashMap::new();
 
@@ -174,42 +254,30 @@ fn migrate_0_3_1_to_0_4_0(ctx: &SpoolContext) -> Result<()> {
         let stream_id = generate_id();
         stream_id_map.insert(stream_name.clone(), stream_id.clone());
 
-        let event = Event {
-            v: 1,
-            op: Operation::CreateStream,
-            id: stream_id.clone(),
-            ts: Utc::now(),
-            by: user.clone(),
-            branch: branch.clone(),
-            d: serde_json::json!({
-                "name": stream_name,
-                "description": format!("Migrated from stream name '{}'", stream_name),
-            }),
-        };
+        let payload = Payload::CreateStream(CreateStreamPayload {
+            name: stream_name.clone(),
+            description: Some(format!("Migrated from stream name '{}'", stream_name)),
+            ..Default::default()
+        });
 
-        write_event(ctx, &event)?;
+        txn.push(Event::new(&stream_id, payload, &user, &branch));
         eprintln!("  Created stream: {} ({})", stream_name, stream_id);
     }
 
     // Update tasks to use stream IDs instead of names
     for (task_id, stream_name) in &task_streams {
         if let Some(stream_id) = stream_id_map.get(stream_name) {
-            let event = Event {
-                v: 1,
-                op: Operation::SetStream,
-                id: task_id.clone(),
-                ts: Utc::now(),
-                by: user.clone(),
-                branch: branch.clone(),
-                d: serde_json::json!({
-                    "stream": stream_id,
-                }),
-            };
+            let payload = Payload::SetStream(SetStreamPayload {
+                stream: Some(stream_id.clone()),
+                ..Default::default()
+            });
 
-            write_event(ctx, &event)?;
+            txn.push(Event::new(task_id, payload, &user, &branch));
         }
     }
 
+    txn.commit()?;
+
     eprintln!(
         "  Updated {} tasks with stream references",
         task_streams.len()
@@ -225,34 +293,37 @@ fn collect_stream_info(
     task_streams: &mut HashMap<String, String>,
 ) {
     for event in events {
-        match &event.op {
-            Operation::Create => {
+        // Undecodable payloads carry no usable stream information
+        let Ok(payload) = event.payload() else {
+            continue;
+        };
+        match payload {
+            Payload::Create(d) => {
                 // Check if task was created with a stream
-                if let Some(stream) = event.d.get("stream").and_then(|v| v.as_str()) {
-                    if !stream.is_empty() {
-                        stream_names
-                            .entry(stream.to_string())
-                            .or_default()
-                            .push(event.id.clone());
-                        task_streams.insert(event.id.clone(), stream.to_string());
-                    }
+                if let Some(stream) = d.stream.filter(|s| !s.is_empty()) {
+                    stream_names
+                        .entry(stream.clone())
+                        .or_default()
+                        .push(event.id.clone());
+                    task_streams.insert(event.id.clone(), stream);
                 }
             }
-            Operation::SetStream => {
+            Payload::SetStream(d) => match d.stream {
                 // Check if stream was set on a task
-                if let Some(stream) = event.d.get("stream").and_then(|v| v.as_str()) {
+                Some(stream) => {
                     if !stream.is_empty() {
                         stream_names
-                            .entry(stream.to_string())
+                            .entry(stream.clone())
                             .or_default()
                             .push(event.id.clone());
-                        task_streams.insert(event.id.clone(), stream.to_string());
+                        task_streams.insert(event.id.clone(), stream);
                     }
-                } else {
+                }
+                None => {
                     // Stream was removed
                     task_streams.remove(&event.id);
                 }
-            }
+            },
             _ => {}
         }
     }
@@ -338,4 +409,44 @@ mod tests {
         assert_eq!(version.format_version, CURRENT_FORMAT_VERSION);
         assert!(version.migrated_at.is_none()); // Fresh install, not migrated
     }
+
+    #[test]
+    fn test_author_layout_splits_day_files() {
+        let (_temp_dir, ctx) = create_test_context();
+        write_version(&ctx, &VersionInfo::default()).unwrap();
+        fs::write(ctx.config_path(), r#"{"layout": "author"}"#).unwrap();
+        let event = |by: &str, title: &str| {
+            format!(
+                r#"{{"v":1,"op":"create","id":"{}","ts":"2024-01-15T10:00:00Z","by":"{}","branch":"main","d":{{"title":"x"}}}}"#,
+                title, by
+            )
+        };
+        fs::write(
+            ctx.events_dir.join("2024-01-15.jsonl"),
+            format!(
+                "{}\n{}\nnot json\n{}\n",
+                event("@alice", "a1"),
+                event("@bob", "b1"),
+                event("@alice", "a2")
+            ),
+        )
+        .unwrap();
+
+        check_and_migrate(&ctx).unwrap();
+
+        let day = ctx.events_dir.join("2024-01-15");
+        let alice = fs::read_to_string(day.join("@alice.jsonl")).unwrap();
+        assert_eq!(alice.lines().count(), 2);
+        let bob = fs::read_to_string(day.join("@bob.jsonl")).unwrap();
+        assert_eq!(bob.lines().count(), 1);
+        // The unreadable line stays behind for `spool repair`
+        assert_eq!(
+            fs::read_to_string(ctx.events_dir.join("2024-01-15.jsonl")).unwrap(),
+            "not json\n"
+        );
+
+        // Nothing left to split
+        assert_eq!(shard_day_files(&ctx).unwrap(), 0);
+        assert_eq!(fs::read_to_string(day.join("@alice.jsonl")).unwrap(), alice);
+    }
 }
@@ -0,0 +1,345 @@
+//! Typed event payloads
+//!
+//! Every event carries its operation-specific data in `Event::d`. This module
+//! gives each operation a payload struct so readers and writers share a single
+//! definition of the fields instead of picking values out of raw JSON.
+//!
+//! Each payload keeps any fields it does not recognise in `extra`, so decoding
+//! and re-encoding an event written by a newer spool never loses data.
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+use crate::event::Operation;
+
+/// Fields present in a payload that this version of spool does not know about
+pub type Extra = Map<String, Value>;
+
+/// Payload of a task's first event
+///
+/// A null title or list, and non-string entries in a list, are read as
+/// absent rather than losing the whole task, since event files are edited
+/// by hand.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CreatePayload {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "strings_only",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "strings_only",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub blocks: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "strings_only",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub blocked_by: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// A list of strings, skipping any other entries
+fn strings_only<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let items: Option<Vec<Value>> = Option::deserialize(deserializer)?;
+    Ok(items
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|item| match item {
+            Value::String(s) => Some(s),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Partial update of task fields; absent fields are left unchanged
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpdatePayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    /// Replaces the whole tag list when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl UpdatePayload {
+    /// Whether this update would change any known field
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.priority.is_none()
+            && self.tags.is_none()
+    }
+}
+
+/// Assignment change; `to: null` unassigns the task
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssignPayload {
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommentPayload {
+    #[serde(default)]
+    pub body: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#ref: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Correction of an earlier comment on the same task
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EditCommentPayload {
+    /// ID of the comment, i.e. of the event that added it (see `Event::event_id`)
+    pub comment: String,
+    #[serde(default)]
+    pub body: String,
+    /// Replaces the comment's ref when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#ref: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Removal of an earlier comment on the same task
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeleteCommentPayload {
+    /// ID of the comment, i.e. of the event that added it
+    pub comment: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Tags added or removed, shared by `tag` and `untag`
+///
+/// Unlike `UpdatePayload::tags`, which replaces the whole list, these only
+/// touch the tags named, so tags added on different branches all survive a
+/// merge.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TagPayload {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Relationship between two tasks, shared by `link` and `unlink`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinkPayload {
+    /// One of `blocks`, `blocked_by` or `parent`
+    pub rel: String,
+    pub target: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompletePayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReopenPayload {
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArchivePayload {
+    /// Archive month (`YYYY-MM`) the task's events were written to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#ref: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Stream membership change; `stream: null` removes the task from its stream
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SetStreamPayload {
+    #[serde(default)]
+    pub stream: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CreateStreamPayload {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpdateStreamPayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeleteStreamPayload {
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// The decoded `d` field of an event, one variant per `Operation`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    Create(CreatePayload),
+    Update(UpdatePayload),
+    Assign(AssignPayload),
+    Comment(CommentPayload),
+    EditComment(EditCommentPayload),
+    DeleteComment(DeleteCommentPayload),
+    Tag(TagPayload),
+    Untag(TagPayload),
+    Link(LinkPayload),
+    Unlink(LinkPayload),
+    Complete(CompletePayload),
+    Reopen(ReopenPayload),
+    Archive(ArchivePayload),
+    SetStream(SetStreamPayload),
+    CreateStream(CreateStreamPayload),
+    UpdateStream(UpdateStreamPayload),
+    DeleteStream(DeleteStreamPayload),
+    /// Payload of an operation this build doesn't know, kept verbatim
+    Unknown {
+        op: String,
+        d: Value,
+    },
+}
+
+impl Payload {
+    /// Decode the raw `d` value of an event with the given operation
+    pub fn decode(op: &Operation, d: &Value) -> Result<Self> {
+        let payload = match op {
+            Operation::Create => Payload::Create(decode_as(op, d)?),
+            Operation::Update => Payload::Update(decode_as(op, d)?),
+            Operation::Assign => Payload::Assign(decode_as(op, d)?),
+            Operation::Comment => Payload::Comment(decode_as(op, d)?),
+            Operation::EditComment => Payload::EditComment(decode_as(op, d)?),
+            Operation::DeleteComment => Payload::DeleteComment(decode_as(op, d)?),
+            Operation::Tag => Payload::Tag(decode_as(op, d)?),
+            Operation::Untag => Payload::Untag(decode_as(op, d)?),
+            Operation::Link => Payload::Link(decode_as(op, d)?),
+            Operation::Unlink => Payload::Unlink(decode_as(op, d)?),
+            Operation::Complete => Payload::Complete(decode_as(op, d)?),
+            Operation::Reopen => Payload::Reopen(decode_as(op, d)?),
+            Operation::Archive => Payload::Archive(decode_as(op, d)?),
+            Operation::SetStream => Payload::SetStream(decode_as(op, d)?),
+            Operation::CreateStream => Payload::CreateStream(decode_as(op, d)?),
+            Operation::UpdateStream => Payload::UpdateStream(decode_as(op, d)?),
+            Operation::DeleteStream => Payload::DeleteStream(decode_as(op, d)?),
+            Operation::Unknown(name) => Payload::Unknown {
+                op: name.clone(),
+                d: d.clone(),
+            },
+        };
+        Ok(payload)
+    }
+
+    /// The operation this payload belongs to
+    pub fn operation(&self) -> Operation {
+        match self {
+            Payload::Create(_) => Operation::Create,
+            Payload::Update(_) => Operation::Update,
+            Payload::Assign(_) => Operation::Assign,
+            Payload::Comment(_) => Operation::Comment,
+            Payload::EditComment(_) => Operation::EditComment,
+            Payload::DeleteComment(_) => Operation::DeleteComment,
+            Payload::Tag(_) => Operation::Tag,
+            Payload::Untag(_) => Operation::Untag,
+            Payload::Link(_) => Operation::Link,
+            Payload::Unlink(_) => Operation::Unlink,
+            Payload::Complete(_) => Operation::Complete,
+            Payload::Reopen(_) => Operation::Reopen,
+            Payload::Archive(_) => Operation::Archive,
+            Payload::SetStream(_) => Operation::SetStream,
+            Payload::CreateStream(_) => Operation::CreateStream,
+            Payload::UpdateStream(_) => Operation::UpdateStream,
+            Payload::DeleteStream(_) => Operation::DeleteStream,
+            Payload::Unknown { op, .. } => Operation::Unknown(op.clone()),
+        }
+    }
+
+    /// Encode the payload as the raw `d` value of an event
+    pub fn to_value(&self) -> Value {
+        let value = match self {
+            Payload::Create(p) => serde_json::to_value(p),
+            Payload::Update(p) => serde_json::to_value(p),
+            Payload::Assign(p) => serde_json::to_value(p),
+            Payload::Comment(p) => serde_json::to_value(p),
+            Payload::EditComment(p) => serde_json::to_value(p),
+            Payload::DeleteComment(p) => serde_json::to_value(p),
+            Payload::Tag(p) | Payload::Untag(p) => serde_json::to_value(p),
+            Payload::Link(p) | Payload::Unlink(p) => serde_json::to_value(p),
+            Payload::Complete(p) => serde_json::to_value(p),
+            Payload::Reopen(p) => serde_json::to_value(p),
+            Payload::Archive(p) => serde_json::to_value(p),
+            Payload::SetStream(p) => serde_json::to_value(p),
+            Payload::CreateStream(p) => serde_json::to_value(p),
+            Payload::UpdateStream(p) => serde_json::to_value(p),
+            Payload::DeleteStream(p) => serde_json::to_value(p),
+            Payload::Unknown { d, .. } => Ok(d.clone()),
+        };
+        // Payloads are plain structs with string keys, so this cannot fail
+        value.expect("event payload serializes to JSON")
+    }
+}
+
+fn decode_as<T: DeserializeOwned>(op: &Operation, d: &Value) -> Result<T> {
+    // Payload-less operations have historically been written with `d: {}`,
+    // but tolerate a missing/null `d` as well
+    let d = if d.is_null() {
+        Value::Object(Map::new())
+    } else {
+        d.clone()
+    };
+    serde_json::from_value(d).with_context(|| format!("Invalid payload for '{}' event", op))
+}
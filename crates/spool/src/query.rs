@@ -0,0 +1,161 @@
+//! Task queries for listing
+//!
+//! `spool list` and the TUI describe the tasks they want with a `TaskQuery`
+//! and run it against a `TaskSource`: the materialized state by default, or
+//! the SQLite index (see `sqlite`) when it's enabled in `.spool/config.json`
+//! and spool was built with the `sqlite` feature.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+#[cfg(feature = "sqlite")]
+use crate::config::Config;
+use crate::context::SpoolContext;
+use crate::deps::Hierarchy;
+use crate::state::{load_or_materialize_state, State, Stream, Task, TaskStatus};
+
+/// Whether archived tasks are listed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArchivedFilter {
+    /// Only live tasks
+    #[default]
+    Exclude,
+    /// Live and archived tasks
+    Include,
+    /// Only archived tasks
+    Only,
+}
+
+/// Filters for listing tasks; every filter that's set must match
+#[derive(Debug, Clone, Default)]
+pub struct TaskQuery {
+    pub status: Option<TaskStatus>,
+    pub assignee: Option<String>,
+    pub tag: Option<String>,
+    pub priority: Option<String>,
+    /// Stream ID
+    pub stream: Option<String>,
+    /// Only tasks without a stream
+    pub no_stream: bool,
+    pub archived: ArchivedFilter,
+    /// Text to find (case-insensitively) in the title, description or tags
+    pub search: Option<String>,
+}
+
+impl TaskQuery {
+    pub fn matches(&self, task: &Task) -> bool {
+        let status_match = self.status.as_ref().map_or(true, |s| task.status == *s);
+        let assignee_match = self
+            .assignee
+            .as_deref()
+            .map_or(true, |a| task.assignee.as_deref() == Some(a));
+        let tag_match = self
+            .tag
+            .as_ref()
+            .map_or(true, |tag| task.tags.contains(tag));
+        let priority_match = self
+            .priority
+            .as_deref()
+            .map_or(true, |p| task.priority.as_deref() == Some(p));
+        let stream_match = if self.no_stream {
+            task.stream.is_none()
+        } else {
+            self.stream
+                .as_deref()
+                .map_or(true, |s| task.stream.as_deref() == Some(s))
+        };
+        let archived_match = match self.archived {
+            ArchivedFilter::Exclude => task.archived.is_none(),
+            ArchivedFilter::Include => true,
+            ArchivedFilter::Only => task.archived.is_some(),
+        };
+        let search_match = self.search.as_ref().map_or(true, |text| {
+            let text = text.to_lowercase();
+            task.title.to_lowercase().contains(&text)
+                || task
+                    .description
+                    .as_ref()
+                    .is_some_and(|d| d.to_lowercase().contains(&text))
+                || task.tags.iter().any(|t| t.to_lowercase().contains(&text))
+        });
+
+        status_match
+            && assignee_match
+            && tag_match
+            && priority_match
+            && stream_match
+            && archived_match
+            && search_match
+    }
+}
+
+/// Where task queries are answered from
+pub enum TaskSource {
+    State(Box<State>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(crate::sqlite::SqliteIndex),
+}
+
+impl TaskSource {
+    /// The SQLite index if it's enabled and built in, otherwise the state
+    pub fn open(ctx: &SpoolContext) -> Result<Self> {
+        #[cfg(feature = "sqlite")]
+        if Config::load(ctx)?.sqlite_index {
+            return Ok(Self::Sqlite(crate::sqlite::SqliteIndex::open(ctx)?));
+        }
+        Ok(Self::State(Box::new(load_or_materialize_state(ctx)?)))
+    }
+
+    /// Tasks matching `query`, oldest first
+    pub fn tasks(&self, query: &TaskQuery) -> Result<Vec<Task>> {
+        match self {
+            Self::State(state) => {
+                let mut tasks: Vec<Task> = state
+                    .tasks
+                    .values()
+                    .filter(|task| query.matches(task))
+                    .cloned()
+                    .collect();
+                tasks.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+                Ok(tasks)
+            }
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(index) => index.tasks(query),
+        }
+    }
+
+    /// Parent/child links of every task, archived ones included
+    pub fn hierarchy(&self) -> Result<Hierarchy> {
+        match self {
+            Self::State(state) => Ok(Hierarchy::new(state.tasks.values())),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(index) => index.hierarchy(),
+        }
+    }
+
+    pub fn streams(&self) -> Result<HashMap<String, Stream>> {
+        match self {
+            Self::State(state) => Ok(state.streams.clone()),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(index) => index.streams(),
+        }
+    }
+
+    /// Problems found while materializing (see `State::warnings`)
+    pub fn warnings(&self) -> Result<Vec<String>> {
+        match self {
+            Self::State(state) => Ok(state.warnings.clone()),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(index) => index.warnings(),
+        }
+    }
+
+    /// Number of unresolved conflicting edits
+    pub fn conflicts(&self) -> Result<usize> {
+        match self {
+            Self::State(state) => Ok(state.conflicts.len()),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(index) => index.conflicts(),
+        }
+    }
+}
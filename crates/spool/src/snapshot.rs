@@ -0,0 +1,167 @@
+//! Committed snapshots of materialized state
+//!
+//! Replaying every event since the repo was created gets slower as history
+//! grows. `spool compact` writes the fully replayed `State`, including the
+//! watermark of exactly which bytes of each event file it covers, to
+//! `.spool/snapshots/`. Snapshots are committed, so every clone can start
+//! from one and only replay the events appended since.
+//!
+//! A snapshot is only used while the event files it covers are unchanged
+//! (same files, same leading bytes) and later events sort after it. Merges
+//! that insert older events, archiving, and repairs all rewrite history, so
+//! replay falls back to an older snapshot or to the beginning until the next
+//! `spool compact`.
+//!
+//! A snapshot leaves out what grows with every event (see `State::compacted`),
+//! so its size depends on the tasks, not on how much history they have.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::concurrency::FileLock;
+use crate::context::SpoolContext;
+use crate::event::CURRENT_VERSION;
+use crate::state::{catch_up, replay_all, CatchUp, State};
+use crate::store::EventStore;
+use crate::writer::replace_durably;
+
+/// A materialized state and the build that wrote it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Event schema version of the spool that replayed the events
+    pub v: u32,
+    pub created: DateTime<Utc>,
+    pub state: State,
+}
+
+impl Snapshot {
+    /// Whether this build would have replayed the covered events the same way
+    fn is_usable(&self) -> bool {
+        self.v == CURRENT_VERSION
+            && self.state.is_current_format()
+            && !self.state.skipped_unsupported()
+    }
+}
+
+/// Write a snapshot of the full replay and remove the snapshots it replaces
+///
+/// Returns the path of the new snapshot.
+pub fn compact(ctx: &SpoolContext) -> Result<PathBuf> {
+    // Keep writers out so the snapshot covers everything written so far
+    let _lock = FileLock::wait(ctx)?;
+
+    let state = replay_all(ctx)?;
+    if state.skipped_unsupported() {
+        return Err(anyhow!(
+            "Some events were skipped as unsupported; upgrade spool before compacting"
+        ));
+    }
+
+    let created = Utc::now();
+    let snapshot = Snapshot {
+        v: CURRENT_VERSION,
+        created,
+        state: state.compacted(),
+    };
+
+    let dir = ctx.snapshots_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.json", created.format("%Y-%m-%dT%H%M%S%.3fZ")));
+    let older = ctx.snapshots()?;
+    replace_durably(&path, serde_json::to_string(&snapshot)?.as_bytes())?;
+
+    // Git history keeps the old ones
+    for old in older.iter().map(|name| dir.join(name)) {
+        if old != path {
+            fs::remove_file(&old).with_context(|| format!("Failed to remove {:?}", old))?;
+        }
+    }
+
+    Ok(path)
+}
+
+/// State caught up from the newest snapshot that still matches the event
+/// files, or `None` if none does
+pub(crate) fn resume<S: EventStore + ?Sized>(store: &S) -> Result<Option<State>> {
+    for name in store.snapshots()?.into_iter().rev() {
+        // An unreadable snapshot is only a missed shortcut
+        let Ok(snapshot) = read_snapshot(store, &name) else {
+            continue;
+        };
+        if !snapshot.is_usable() {
+            continue;
+        }
+        let mut state = snapshot.state;
+        match catch_up(store, &mut state)? {
+            CatchUp::Fresh | CatchUp::Advanced => {
+                state.rebuilt = Utc::now();
+                return Ok(Some(state));
+            }
+            CatchUp::Stale => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Check every snapshot against `full`, a replay of all events
+///
+/// Snapshots that disagree with the replay are errors; snapshots that no
+/// longer apply to the event history are only warnings, since replay
+/// ignores them.
+pub(crate) fn verify<S: EventStore + ?Sized>(
+    store: &S,
+    full: &State,
+    errors: &mut Vec<String>,
+    warnings: &mut Vec<String>,
+) -> Result<()> {
+    for name in store.snapshots()? {
+        let snapshot = match read_snapshot(store, &name) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                errors.push(format!("snapshots/{}: {:#}", name, e));
+                continue;
+            }
+        };
+        if !snapshot.is_usable() {
+            warnings.push(format!(
+                "Snapshot {} was written by a different version of spool and is ignored",
+                name
+            ));
+            continue;
+        }
+        let mut state = snapshot.state;
+        match catch_up(store, &mut state)? {
+            CatchUp::Fresh | CatchUp::Advanced => {
+                if summary(&state) != summary(full) {
+                    errors.push(format!(
+                        "Snapshot {} does not match a full replay of the events (run 'spool compact')",
+                        name
+                    ));
+                }
+            }
+            CatchUp::Stale => warnings.push(format!(
+                "Snapshot {} no longer matches the event files and is ignored (run 'spool compact')",
+                name
+            )),
+        }
+    }
+    Ok(())
+}
+
+fn read_snapshot<S: EventStore + ?Sized>(store: &S, name: &str) -> Result<Snapshot> {
+    serde_json::from_slice(&store.read_snapshot(name)?).context("Invalid snapshot")
+}
+
+/// The parts of a state visible to users, in a comparable form
+fn summary(state: &State) -> Value {
+    serde_json::json!({
+        "tasks": state.tasks,
+        "streams": state.streams,
+        "warnings": state.warnings,
+        "conflicts": state.conflicts,
+    })
+}
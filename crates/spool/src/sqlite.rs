@@ -0,0 +1,480 @@
+//! Local SQLite query index
+//!
+//! `.state.json` is one JSON document that every command parses in full.
+//! With `"sqlite_index": true` in `.spool/config.json` (and spool built with
+//! the `sqlite` feature), tasks, tags, comments, links, streams and events
+//! are also kept in `.spool/.index.db`, which `spool list` and the TUI query
+//! instead. Like the other caches it's local and rebuilt from the events
+//! whenever needed.
+//!
+//! The database records how much of each event file it covers. While the
+//! files are unchanged, queries don't load the state at all. Otherwise the
+//! state is caught up as usual (see `state::load_or_materialize_state`),
+//! only the tasks that changed are rewritten, and only events from new or
+//! appended bytes are inserted.
+
+use anyhow::{Context, Result};
+use chrono::SecondsFormat;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::Duration;
+
+use crate::context::SpoolContext;
+use crate::deps::Hierarchy;
+use crate::event::{fnv1a, sort_events, Event};
+use crate::query::{ArchivedFilter, TaskQuery};
+use crate::state::{
+    load_or_materialize_state, FileMark, Stream, Task, TaskStatus, Watermark, STATE_FORMAT,
+};
+use crate::store::EventStore;
+
+/// Bumped whenever the tables change; older databases are rebuilt
+const SCHEMA_VERSION: &str = "2";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS tasks (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    description TEXT,
+    status TEXT NOT NULL,
+    priority TEXT,
+    assignee TEXT,
+    stream TEXT,
+    parent TEXT,
+    created TEXT NOT NULL,
+    updated TEXT NOT NULL,
+    completed TEXT,
+    resolution TEXT,
+    archived TEXT,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tasks_by_status ON tasks (status, archived);
+CREATE TABLE IF NOT EXISTS tags (
+    task_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (task_id, tag)
+);
+CREATE INDEX IF NOT EXISTS tags_by_tag ON tags (tag);
+CREATE TABLE IF NOT EXISTS comments (
+    task_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    id TEXT NOT NULL,
+    ts TEXT NOT NULL,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    reference TEXT,
+    edited TEXT,
+    PRIMARY KEY (task_id, seq)
+);
+CREATE TABLE IF NOT EXISTS links (
+    task_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    other TEXT NOT NULL,
+    PRIMARY KEY (task_id, kind, other)
+);
+CREATE TABLE IF NOT EXISTS streams (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    segment TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    task_id TEXT NOT NULL,
+    op TEXT NOT NULL,
+    ts TEXT NOT NULL,
+    author TEXT NOT NULL,
+    json TEXT NOT NULL,
+    PRIMARY KEY (segment, line)
+);
+CREATE INDEX IF NOT EXISTS events_by_task ON events (task_id);
+";
+
+/// The query index in `.spool/.index.db`
+pub struct SqliteIndex {
+    conn: Connection,
+}
+
+impl SqliteIndex {
+    /// Open the index, creating it or bringing it up to date as needed
+    pub fn open(ctx: &SpoolContext) -> Result<Self> {
+        let path = ctx.sqlite_path();
+        let mut index = Self::connect(ctx)?;
+        if index.meta("schema")?.as_deref() != Some(SCHEMA_VERSION) {
+            // A cache, so an old layout is simply rebuilt
+            drop(index);
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+            index = Self::connect(ctx)?;
+            index.set_meta("schema", SCHEMA_VERSION)?;
+        }
+        index.sync(ctx)?;
+        Ok(index)
+    }
+
+    fn connect(ctx: &SpoolContext) -> Result<Self> {
+        let path = ctx.sqlite_path();
+        let conn = Connection::open(&path).with_context(|| format!("Failed to open {:?}", path))?;
+        // Other spool processes may be syncing at the same time
+        conn.busy_timeout(Duration::from_secs(10))?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Tasks matching `query`, oldest first
+    pub fn tasks(&self, query: &TaskQuery) -> Result<Vec<Task>> {
+        let mut clauses: Vec<&str> = Vec::new();
+        let mut values: Vec<String> = Vec::new();
+        if let Some(status) = &query.status {
+            clauses.push("status = ?");
+            values.push(status_name(status).to_string());
+        }
+        if let Some(assignee) = &query.assignee {
+            clauses.push("assignee = ?");
+            values.push(assignee.clone());
+        }
+        if let Some(tag) = &query.tag {
+            clauses.push("id IN (SELECT task_id FROM tags WHERE tag = ?)");
+            values.push(tag.clone());
+        }
+        if let Some(priority) = &query.priority {
+            clauses.push("priority = ?");
+            values.push(priority.clone());
+        }
+        if query.no_stream {
+            clauses.push("stream IS NULL");
+        } else if let Some(stream) = &query.stream {
+            clauses.push("stream = ?");
+            values.push(stream.clone());
+        }
+        match query.archived {
+            ArchivedFilter::Exclude => clauses.push("archived IS NULL"),
+            ArchivedFilter::Include => {}
+            ArchivedFilter::Only => clauses.push("archived IS NOT NULL"),
+        }
+
+        let mut sql = "SELECT json FROM tasks".to_string();
+        if !clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY created, id");
+
+        let mut statement = self.conn.prepare(&sql)?;
+        let rows = statement.query_map(params_from_iter(values.iter()), |row| {
+            row.get::<_, String>(0)
+        })?;
+        let mut tasks = Vec::new();
+        for json in rows {
+            let task: Task = serde_json::from_str(&json?)?;
+            // SQLite only folds ASCII case, so text search is done here
+            if query.search.is_none() || query.matches(&task) {
+                tasks.push(task);
+            }
+        }
+        Ok(tasks)
+    }
+
+    pub fn task(&self, id: &str) -> Result<Option<Task>> {
+        let json: Option<String> = self
+            .conn
+            .query_row("SELECT json FROM tasks WHERE id = ?", [id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(json.map(|json| serde_json::from_str(&json)).transpose()?)
+    }
+
+    /// Parent/child links of every task, archived ones included
+    pub fn hierarchy(&self) -> Result<Hierarchy> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT id, parent, status FROM tasks ORDER BY created, id")?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut hierarchy = Hierarchy::default();
+        for row in rows {
+            let (id, parent, status) = row?;
+            let complete = status == status_name(&TaskStatus::Complete);
+            hierarchy.add(&id, parent.as_deref(), complete);
+        }
+        Ok(hierarchy)
+    }
+
+    pub fn streams(&self) -> Result<HashMap<String, Stream>> {
+        let mut statement = self.conn.prepare("SELECT id, json FROM streams")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut streams = HashMap::new();
+        for row in rows {
+            let (id, json) = row?;
+            streams.insert(id, serde_json::from_str(&json)?);
+        }
+        Ok(streams)
+    }
+
+    /// A task's events, in replay order
+    pub fn events(&self, task_id: &str) -> Result<Vec<Event>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT json FROM events WHERE task_id = ? ORDER BY segment, line")?;
+        let rows = statement.query_map([task_id], |row| row.get::<_, String>(0))?;
+        let mut events = Vec::new();
+        for json in rows {
+            events.push(Event::from_json(&json?)?);
+        }
+        sort_events(&mut events);
+        Ok(events)
+    }
+
+    /// Warnings from the last materialization (see `State::warnings`)
+    pub fn warnings(&self) -> Result<Vec<String>> {
+        match self.meta("warnings")? {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Number of conflicting edits in the last materialization
+    pub fn conflicts(&self) -> Result<usize> {
+        Ok(self
+            .meta("conflicts")?
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(0))
+    }
+
+    /// Bring the tables up to date with the event files
+    fn sync(&mut self, ctx: &SpoolContext) -> Result<()> {
+        // Rows derived by an older replay are all rewritten, as if never synced
+        let format = STATE_FORMAT.to_string();
+        let stale = self.meta("state_format")?.as_deref() != Some(format.as_str());
+        let synced: Option<Watermark> = if stale {
+            None
+        } else {
+            self.meta("watermark")?
+                .and_then(|json| serde_json::from_str(&json).ok())
+        };
+
+        let mut files = Vec::new();
+        for key in ctx.segments()? {
+            let bytes = ctx.read(&key)?;
+            files.push((key, bytes));
+        }
+        let mut watermark = Watermark::new();
+        for (key, bytes) in &files {
+            watermark.insert(
+                key.clone(),
+                FileMark {
+                    offset: bytes.len() as u64,
+                    hash: fnv1a(bytes),
+                    max_clock: None,
+                },
+            );
+        }
+        if synced.as_ref() == Some(&watermark) {
+            return Ok(());
+        }
+        let synced = synced.unwrap_or_default();
+
+        let state = load_or_materialize_state(ctx)?;
+        let tx = self.conn.transaction()?;
+        if stale {
+            for table in ["tasks", "tags", "comments", "links"] {
+                tx.execute(&format!("DELETE FROM {}", table), [])?;
+            }
+        }
+        sync_tasks(&tx, &state.tasks)?;
+
+        tx.execute("DELETE FROM streams", [])?;
+        for (id, stream) in &state.streams {
+            tx.execute(
+                "INSERT INTO streams (id, name, json) VALUES (?, ?, ?)",
+                params![id, stream.name, serde_json::to_string(stream)?],
+            )?;
+        }
+
+        for (key, bytes) in &files {
+            let from = match synced.get(key) {
+                Some(mark)
+                    if bytes.len() as u64 >= mark.offset
+                        && fnv1a(&bytes[..mark.offset as usize]) == mark.hash =>
+                {
+                    mark.offset as usize
+                }
+                _ => {
+                    tx.execute("DELETE FROM events WHERE segment = ?", [key])?;
+                    0
+                }
+            };
+            insert_events(&tx, key, bytes, from)?;
+        }
+        for key in synced.keys().filter(|key| !watermark.contains_key(*key)) {
+            tx.execute("DELETE FROM events WHERE segment = ?", [key])?;
+        }
+
+        set_meta(&tx, "watermark", &serde_json::to_string(&watermark)?)?;
+        set_meta(&tx, "state_format", &format)?;
+        set_meta(&tx, "warnings", &serde_json::to_string(&state.warnings)?)?;
+        set_meta(&tx, "conflicts", &state.conflicts.len().to_string())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = ?", [key], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)",
+            [key, value],
+        )?;
+        Ok(())
+    }
+}
+
+fn set_meta(tx: &Transaction, key: &str, value: &str) -> Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)",
+        [key, value],
+    )?;
+    Ok(())
+}
+
+/// Rewrite the rows of tasks that changed, and drop tasks that are gone
+fn sync_tasks(tx: &Transaction, tasks: &HashMap<String, Task>) -> Result<()> {
+    let mut stored: HashMap<String, String> = HashMap::new();
+    {
+        let mut statement = tx.prepare("SELECT id, json FROM tasks")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (id, json) = row?;
+            stored.insert(id, json);
+        }
+    }
+
+    for task in tasks.values() {
+        let json = serde_json::to_string(task)?;
+        if stored.get(&task.id) == Some(&json) {
+            continue;
+        }
+        delete_task(tx, &task.id)?;
+        tx.execute(
+            "INSERT INTO tasks (id, title, description, status, priority, assignee, stream,
+                parent, created, updated, completed, resolution, archived, json)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                task.id,
+                task.title,
+                task.description,
+                status_name(&task.status),
+                task.priority,
+                task.assignee,
+                task.stream,
+                task.parent,
+                timestamp(&task.created),
+                timestamp(&task.updated),
+                task.completed.as_ref().map(timestamp),
+                task.resolution,
+                task.archived,
+                json,
+            ],
+        )?;
+        for tag in &task.tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (task_id, tag) VALUES (?, ?)",
+                params![task.id, tag],
+            )?;
+        }
+        for (seq, comment) in task.comments.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO comments (task_id, seq, id, ts, author, body, reference, edited)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    task.id,
+                    seq as i64,
+                    comment.id,
+                    timestamp(&comment.ts),
+                    comment.by,
+                    comment.body,
+                    comment.r#ref,
+                    comment.edited.as_ref().map(timestamp),
+                ],
+            )?;
+        }
+        for (kind, others) in [("blocks", &task.blocks), ("blocked_by", &task.blocked_by)] {
+            for other in others {
+                tx.execute(
+                    "INSERT OR IGNORE INTO links (task_id, kind, other) VALUES (?, ?, ?)",
+                    params![task.id, kind, other],
+                )?;
+            }
+        }
+    }
+
+    let live: HashSet<&String> = tasks.keys().collect();
+    for id in stored.keys().filter(|id| !live.contains(id)) {
+        delete_task(tx, id)?;
+    }
+    Ok(())
+}
+
+fn delete_task(tx: &Transaction, id: &str) -> Result<()> {
+    tx.execute("DELETE FROM tasks WHERE id = ?", [id])?;
+    tx.execute("DELETE FROM tags WHERE task_id = ?", [id])?;
+    tx.execute("DELETE FROM comments WHERE task_id = ?", [id])?;
+    tx.execute("DELETE FROM links WHERE task_id = ?", [id])?;
+    Ok(())
+}
+
+/// Insert the events in `bytes` from byte `from` on, keyed by line number
+fn insert_events(tx: &Transaction, segment: &str, bytes: &[u8], from: usize) -> Result<()> {
+    let first_line = bytes[..from].iter().filter(|b| **b == b'\n').count() + 1;
+    let mut statement = tx.prepare(
+        "INSERT OR REPLACE INTO events (segment, line, task_id, op, ts, author, json)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )?;
+    for (i, line) in bytes[from..].split(|b| *b == b'\n').enumerate() {
+        // Unreadable lines are reported by `spool validate`, not indexed
+        let Some((text, event)) = std::str::from_utf8(line)
+            .ok()
+            .and_then(|text| Some((text, Event::from_json(text).ok()?)))
+        else {
+            continue;
+        };
+        statement.execute(params![
+            segment,
+            (first_line + i) as i64,
+            event.id,
+            event.op.to_string(),
+            timestamp(&event.ts),
+            event.by,
+            text,
+        ])?;
+    }
+    Ok(())
+}
+
+fn status_name(status: &TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Open => "open",
+        TaskStatus::Complete => "complete",
+    }
+}
+
+/// Fixed-width UTC timestamps, so they sort as text
+fn timestamp(ts: &chrono::DateTime<chrono::Utc>) -> String {
+    ts.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
@@ -1,11 +1,18 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
+use std::sync::{mpsc, Mutex};
+use std::thread;
 
-use crate::context::SpoolContext;
-use crate::event::{Event, Operation};
+use crate::clock::Hlc;
+use crate::conflict::{Conflict, TaskHistory};
+use crate::context::{parse_events_lenient, BadLine, SpoolContext};
+use crate::event::{fnv1a, replay_cmp, sort_events, Event, Operation, CURRENT_VERSION};
+use crate::payload::Payload;
+use crate::snapshot;
+use crate::store::{segment_group, EventStore};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Task {
@@ -52,11 +59,17 @@ pub enum TaskStatus {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Comment {
+    /// ID of the event that added the comment, used to edit or delete it
+    #[serde(default)]
+    pub id: String,
     pub ts: DateTime<Utc>,
     pub by: String,
     pub body: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub r#ref: Option<String>,
+    /// When the comment was last edited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited: Option<DateTime<Utc>>,
 }
 
 /// A stream is a collection of tasks representing a project or workstream
@@ -70,10 +83,17 @@ pub struct Stream {
     pub created_by: String,
 }
 
+/// Where each task's events are, so reads of one task only parse its files
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Index {
     pub tasks: HashMap<String, TaskIndex>,
+    /// Segments holding events for IDs without a create event, e.g. streams
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub untracked: HashMap<String, Vec<String>>,
     pub rebuilt: DateTime<Utc>,
+    /// How far each event file has been indexed (see `State::watermark`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,6 +103,7 @@ pub struct TaskIndex {
     pub updated: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub completed: Option<String>,
+    /// Segments holding the task's events, e.g. `events/2026-01-13.jsonl`
     pub files: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub archived: Option<String>,
@@ -94,38 +115,582 @@ pub struct State {
     #[serde(default)]
     pub streams: HashMap<String, Stream>,
     pub rebuilt: DateTime<Utc>,
+    /// Problems encountered while materializing, e.g. events that were skipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Concurrent edits to the same task field, e.g. from two merged branches
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<Conflict>,
+    /// How far each event file has been replayed; `None` for states that
+    /// can't be caught up incrementally
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
+    #[serde(default, skip_serializing_if = "Skipped::is_empty")]
+    skipped: Skipped,
+    /// Content hashes of every replayed event, so duplicated lines apply once
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    seen: BTreeSet<u64>,
+    /// Causal history of each task, used to detect conflicts
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    history: HashMap<String, TaskHistory>,
+    /// `STATE_FORMAT` of the build that replayed the events
+    #[serde(default)]
+    format: u32,
+    /// Whether `seen` and `history` were trimmed for a snapshot (see
+    /// `compacted`), so appended events can't always be checked against them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    compacted: bool,
+}
+
+/// Bumped when replay starts deriving something that cached states and
+/// snapshots written by older builds lack (1: comment IDs, 2: tag and untag,
+/// 3: resolutions in status conflicts)
+pub(crate) const STATE_FORMAT: u32 = 3;
+
+impl State {
+    /// IDs of the latest events of a task, which a new event for it supersedes
+    pub fn frontier(&self, task_id: &str) -> Vec<String> {
+        self.history
+            .get(task_id)
+            .map(TaskHistory::frontier)
+            .unwrap_or_default()
+    }
+
+    /// Whether this build's replay would produce the same kind of state
+    pub(crate) fn is_current_format(&self) -> bool {
+        self.format == STATE_FORMAT
+    }
+
+    /// The state without the parts that grow with every event replayed
+    ///
+    /// Drops the content hashes of replayed events and keeps only the events
+    /// of each task's history that conflicts and new writes refer to. Catching
+    /// up falls back to a full replay when an appended event might duplicate
+    /// a dropped one, or names an ancestor that was dropped.
+    pub(crate) fn compacted(mut self) -> State {
+        self.seen.clear();
+        for history in self.history.values_mut() {
+            *history = history.compacted();
+        }
+        self.compacted = true;
+        self
+    }
+
+    /// Whether the task histories hold every ancestor `events` name, besides
+    /// the ones among `events`
+    fn knows_ancestry(&self, events: &[&Event]) -> bool {
+        let ids: HashSet<String> = events.iter().map(|event| event.event_id()).collect();
+        events.iter().all(|event| {
+            event.prev.iter().all(|prev| {
+                ids.contains(prev)
+                    || self
+                        .history
+                        .get(&event.id)
+                        .is_some_and(|history| history.knows(prev))
+            })
+        })
+    }
+
+    /// Whether events were skipped because this build doesn't understand them
+    pub(crate) fn skipped_unsupported(&self) -> bool {
+        !self.skipped.unknown_ops.is_empty() || !self.skipped.newer_versions.is_empty()
+    }
+
+    /// Recompute the derived `warnings` and `conflicts` after replaying events
+    fn summarize(&mut self) {
+        self.warnings = self.skipped.warnings();
+        let mut conflicts: Vec<Conflict> = self
+            .history
+            .iter()
+            .flat_map(|(task, history)| history.conflicts(task))
+            .collect();
+        conflicts.sort_by(|a, b| a.task.cmp(&b.task).then_with(|| a.field.cmp(&b.field)));
+        self.conflicts = conflicts;
+    }
+}
+
+/// Replay position of each event file, keyed by path relative to `.spool/`
+///
+/// Keys sort in replay order: `archive/*` before `events/*`, then by date.
+pub type Watermark = BTreeMap<String, FileMark>;
+
+/// How much of a single event file has been applied to a cached state
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FileMark {
+    /// Number of bytes already replayed
+    pub offset: u64,
+    /// FNV-1a hash of those bytes, to detect rewritten history
+    pub hash: u64,
+    /// Newest clock among the replayed events; appended events must sort after it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_clock: Option<Hlc>,
+}
+
+/// Events materialization could not apply, summarized into `State::warnings`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Skipped {
+    #[serde(default)]
+    unknown_ops: BTreeMap<String, usize>,
+    #[serde(default)]
+    newer_versions: BTreeMap<u32, usize>,
+    #[serde(default)]
+    invalid_payloads: usize,
+    #[serde(default)]
+    duplicates: usize,
+    #[serde(default)]
+    incomplete_txns: usize,
+    #[serde(default)]
+    bad_lines: usize,
 }
 
-pub fn materialize(ctx: &SpoolContext) -> Result<State> {
-    let mut tasks: HashMap<String, Task> = HashMap::new();
-    let mut streams: HashMap<String, Stream> = HashMap::new();
+impl Skipped {
+    fn is_empty(&self) -> bool {
+        self.unknown_ops.is_empty()
+            && self.newer_versions.is_empty()
+            && self.invalid_payloads == 0
+            && self.duplicates == 0
+            && self.incomplete_txns == 0
+            && self.bad_lines == 0
+    }
 
-    // First process archive files
-    for file in ctx.get_archive_files()? {
-        let events = ctx.parse_events_from_file(&file)?;
-        apply_events(&mut tasks, &mut streams, events);
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (op, count) in &self.unknown_ops {
+            warnings.push(format!(
+                "Skipped {} event(s) with unknown operation '{}' (written by a newer spool?)",
+                count, op
+            ));
+        }
+        for (v, count) in &self.newer_versions {
+            warnings.push(format!(
+                "Skipped {} event(s) with schema version {} (this spool supports up to {})",
+                count, v, CURRENT_VERSION
+            ));
+        }
+        if self.invalid_payloads > 0 {
+            warnings.push(format!(
+                "Skipped {} event(s) with invalid payloads (run 'spool validate' for details)",
+                self.invalid_payloads
+            ));
+        }
+        if self.duplicates > 0 {
+            warnings.push(format!(
+                "Ignored {} duplicate event(s) (run 'spool validate --dedupe' to remove them)",
+                self.duplicates
+            ));
+        }
+        if self.incomplete_txns > 0 {
+            warnings.push(format!(
+                "Ignored {} event(s) from incomplete transactions (run 'spool validate' for details)",
+                self.incomplete_txns
+            ));
+        }
+        if self.bad_lines > 0 {
+            warnings.push(format!(
+                "Skipped {} unreadable line(s) in event files (run 'spool repair' to quarantine them)",
+                self.bad_lines
+            ));
+        }
+        warnings
     }
+}
 
-    // Then process event files (in chronological order)
-    for file in ctx.get_event_files()? {
-        let events = ctx.parse_events_from_file(&file)?;
-        apply_events(&mut tasks, &mut streams, events);
+/// Outcome of bringing a cached state up to date
+pub(crate) enum CatchUp {
+    /// Nothing was appended since the watermark
+    Fresh,
+    /// New events were replayed on top of the cached state
+    Advanced,
+    /// History changed underneath the cache; a full replay is needed
+    Stale,
+}
+
+/// Current state of the repo, starting from the newest usable snapshot
+///
+/// Falls back to replaying every event when no snapshot matches the event
+/// history (see `snapshot`).
+pub fn materialize<S: EventStore + ?Sized>(store: &S) -> Result<State> {
+    if let Some(state) = snapshot::resume(store)? {
+        return Ok(state);
     }
+    replay_all(store)
+}
 
-    Ok(State {
-        tasks,
-        streams,
+/// Materialize state by replaying every event from the beginning
+///
+/// Segments are parsed in parallel (see `parse_segments`) and each group is
+/// applied as soon as it and every group before it are parsed.
+pub fn replay_all<S: EventStore + ?Sized>(store: &S) -> Result<State> {
+    let mut state = State {
         rebuilt: Utc::now(),
+        watermark: Some(Watermark::new()),
+        format: STATE_FORMAT,
+        ..Default::default()
+    };
+
+    // Archiving moves a task's events to another file, so a transaction is
+    // complete if all of its events are anywhere in the repo. A group holding
+    // part of one whose other events haven't been parsed yet waits, with every
+    // group after it, until all segments are parsed.
+    let mut txns = TxnTally::default();
+    let mut group: Vec<SegmentEvents> = Vec::new();
+    let mut waiting: Vec<Vec<SegmentEvents>> = Vec::new();
+    let mut ready = |state: &mut State, group: Vec<SegmentEvents>, txns: &TxnTally| {
+        let complete = group
+            .iter()
+            .flat_map(|(_, _, events)| events)
+            .filter_map(|event| event.txn.as_ref())
+            .all(|txn| txns.is_complete(&txn.id));
+        if waiting.is_empty() && complete {
+            replay_files(state, group, &HashSet::new());
+        } else {
+            waiting.push(group);
+        }
+    };
+
+    // Archive segments first, then event segments (in chronological order)
+    parse_segments(store, store.segments()?, |(key, bytes, events, bad)| {
+        state.skipped.bad_lines += bad.len();
+        txns.add(&events);
+        if group
+            .first()
+            .is_some_and(|(first, _, _)| segment_group(first) != segment_group(&key))
+        {
+            ready(&mut state, std::mem::take(&mut group), &txns);
+        }
+        group.push((key, bytes, events));
+        Ok(())
+    })?;
+    if !group.is_empty() {
+        ready(&mut state, group, &txns);
+    }
+
+    let incomplete = txns.incomplete();
+    for group in waiting {
+        replay_files(&mut state, group, &incomplete);
+    }
+
+    state.summarize();
+    Ok(state)
+}
+
+/// A parsed segment: its key, contents, events and unreadable lines
+type ParsedSegment = (String, Vec<u8>, Vec<Event>, Vec<BadLine>);
+
+/// Parse segments on worker threads, handing each to `f` in the order given
+/// as soon as it and every segment before it are parsed
+///
+/// Segments are read on the calling thread, since stores needn't be `Sync`;
+/// parsing is what takes the time.
+pub(crate) fn parse_segments<S: EventStore + ?Sized>(
+    store: &S,
+    keys: Vec<String>,
+    mut f: impl FnMut(ParsedSegment) -> Result<()>,
+) -> Result<()> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(keys.len());
+    if workers <= 1 {
+        for key in keys {
+            let bytes = store.read(&key)?;
+            let (events, bad) = parse_events_lenient(&bytes, 1);
+            f((key, bytes, events, bad))?;
+        }
+        return Ok(());
+    }
+
+    let (job_tx, job_rx) = mpsc::channel::<(usize, String, Vec<u8>)>();
+    let (done_tx, done_rx) = mpsc::channel::<(usize, ParsedSegment)>();
+    let job_rx = &Mutex::new(job_rx);
+    // Owns the channel ends, so returning early stops the workers
+    thread::scope(move |scope| {
+        for _ in 0..workers {
+            let done_tx = done_tx.clone();
+            scope.spawn(move || loop {
+                let Ok(job) = job_rx.lock().map(|rx| rx.recv()) else {
+                    break;
+                };
+                let Ok((i, key, bytes)) = job else {
+                    break;
+                };
+                let (events, bad) = parse_events_lenient(&bytes, 1);
+                if done_tx.send((i, (key, bytes, events, bad))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(done_tx);
+
+        let mut parsed = BTreeMap::new();
+        let mut next = 0;
+        let mut deliver = |(i, segment): (usize, ParsedSegment)| -> Result<()> {
+            parsed.insert(i, segment);
+            while let Some(segment) = parsed.remove(&next) {
+                f(segment)?;
+                next += 1;
+            }
+            Ok(())
+        };
+        let total = keys.len();
+        for (i, key) in keys.into_iter().enumerate() {
+            let bytes = store.read(&key)?;
+            job_tx
+                .send((i, key, bytes))
+                .map_err(|_| anyhow!("Event parser threads stopped"))?;
+            while let Ok(done) = done_rx.try_recv() {
+                deliver(done)?;
+            }
+        }
+        drop(job_tx);
+        for done in done_rx {
+            deliver(done)?;
+        }
+        if next < total {
+            return Err(anyhow!("Event parser threads stopped"));
+        }
+        Ok(())
     })
 }
 
-fn apply_events(
-    tasks: &mut HashMap<String, Task>,
-    streams: &mut HashMap<String, Stream>,
-    events: Vec<Event>,
-) {
+/// A segment's key, contents and events
+type SegmentEvents = (String, Vec<u8>, Vec<Event>);
+
+/// Apply a group of files' events in replay order and advance each file's
+/// watermark to the end of its bytes
+///
+/// Events already replayed from these or earlier files are dropped.
+fn replay_files(state: &mut State, files: Vec<SegmentEvents>, incomplete: &HashSet<String>) {
+    let sharded = files.len() > 1;
+    let mut all = Vec::new();
+    let mut marks = Vec::new();
+    for (key, bytes, mut events) in files {
+        if !incomplete.is_empty() {
+            events.retain(|event| {
+                let partial = event
+                    .txn
+                    .as_ref()
+                    .is_some_and(|txn| incomplete.contains(&txn.id));
+                if partial {
+                    state.skipped.incomplete_txns += 1;
+                }
+                !partial
+            });
+        }
+
+        events.retain(|event| {
+            let first = state.seen.insert(event.content_hash());
+            if !first {
+                state.skipped.duplicates += 1;
+            }
+            first
+        });
+
+        let previous = state
+            .watermark
+            .as_ref()
+            .and_then(|w| w.get(&key))
+            .and_then(|mark| mark.max_clock);
+        let max_clock = events.iter().map(Event::clock).max().max(previous);
+        marks.push((
+            key,
+            FileMark {
+                offset: bytes.len() as u64,
+                hash: fnv1a(&bytes),
+                max_clock,
+            },
+        ));
+        all.extend(events);
+    }
+
+    // Each file is already in replay order; shards of a day interleave
+    if sharded {
+        sort_events(&mut all);
+    }
+    apply_events(state, all);
+
+    if let Some(watermark) = state.watermark.as_mut() {
+        watermark.extend(marks);
+    }
+}
+
+/// IDs of transactions with fewer distinct events in `events` than they were
+/// written with
+///
+/// A transaction is appended with a single write; a shortfall means the
+/// write was cut off.
+pub(crate) fn incomplete_transactions<'a>(
+    events: impl IntoIterator<Item = &'a Event>,
+) -> HashSet<String> {
+    let mut txns = TxnTally::default();
+    txns.add(events);
+    txns.incomplete()
+}
+
+/// Distinct events seen so far of each transaction
+#[derive(Default)]
+struct TxnTally {
+    txns: HashMap<String, (u32, HashSet<u64>)>,
+}
+
+impl TxnTally {
+    fn add<'a>(&mut self, events: impl IntoIterator<Item = &'a Event>) {
+        for event in events {
+            if let Some(txn) = &event.txn {
+                self.txns
+                    .entry(txn.id.clone())
+                    .or_insert_with(|| (txn.n, HashSet::new()))
+                    .1
+                    .insert(event.content_hash());
+            }
+        }
+    }
+
+    fn is_complete(&self, id: &str) -> bool {
+        self.txns
+            .get(id)
+            .is_some_and(|(n, hashes)| hashes.len() >= *n as usize)
+    }
+
+    fn incomplete(&self) -> HashSet<String> {
+        self.txns
+            .iter()
+            .filter(|(_, (n, hashes))| hashes.len() < *n as usize)
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+/// Replay only the events appended since the state's watermark
+pub(crate) fn catch_up<S: EventStore + ?Sized>(store: &S, state: &mut State) -> Result<CatchUp> {
+    let Some(watermark) = state.watermark.clone() else {
+        return Ok(CatchUp::Stale);
+    };
+    let max_clock = watermark.values().filter_map(|mark| mark.max_clock).max();
+
+    // Every replayed file must still exist, and new files may only join the
+    // last replayed group or come after it; a file inserted earlier would
+    // have to be applied before events already applied
+    let files = store.segments()?;
+    let last_group = watermark.keys().last().map(|key| segment_group(key));
+    let last_group_clock = watermark
+        .iter()
+        .filter(|(key, _)| Some(segment_group(key)) == last_group)
+        .filter_map(|(_, mark)| mark.max_clock)
+        .max();
+    // Without the hashes of compacted events, only events newer than all of
+    // them are known not to duplicate one
+    let appended_floor = if state.compacted {
+        max_clock
+    } else {
+        last_group_clock
+    };
+    if files
+        .iter()
+        .filter(|key| watermark.contains_key(*key))
+        .count()
+        < watermark.len()
+        || files
+            .iter()
+            .any(|key| !watermark.contains_key(key) && Some(segment_group(key)) < last_group)
+    {
+        return Ok(CatchUp::Stale);
+    }
+
+    let mut advanced = false;
+    let mut groups: Vec<Vec<&String>> = Vec::new();
+    for key in &files {
+        match groups.last_mut() {
+            Some(group) if segment_group(group[0]) == segment_group(key) => group.push(key),
+            _ => groups.push(vec![key]),
+        }
+    }
+    for group in groups {
+        let mut chunk = Vec::new();
+        for key in group {
+            let bytes = store.read(key)?;
+            let events = match watermark.get(key) {
+                Some(mark) => {
+                    let offset = mark.offset as usize;
+                    if bytes.len() < offset || fnv1a(&bytes[..offset]) != mark.hash {
+                        return Ok(CatchUp::Stale);
+                    }
+                    if bytes.len() == offset {
+                        continue;
+                    }
+                    // Only the last replayed group may grow; events appended
+                    // to an earlier file would otherwise be applied out of order
+                    if Some(segment_group(key)) != last_group {
+                        return Ok(CatchUp::Stale);
+                    }
+                    let first_line = bytes[..offset].iter().filter(|b| **b == b'\n').count() + 1;
+                    let (events, bad) = parse_events_lenient(&bytes[offset..], first_line);
+                    state.skipped.bad_lines += bad.len();
+                    // Appended events that sort before already-applied ones (e.g.
+                    // merged in from another branch) need a full replay
+                    if events.iter().any(|e| Some(e.clock()) <= appended_floor) {
+                        return Ok(CatchUp::Stale);
+                    }
+                    events
+                }
+                None => {
+                    let (events, bad) = parse_events_lenient(&bytes, 1);
+                    state.skipped.bad_lines += bad.len();
+                    // Likewise for a new file, e.g. one merged in from a branch
+                    if events.iter().any(|e| Some(e.clock()) <= max_clock) {
+                        return Ok(CatchUp::Stale);
+                    }
+                    events
+                }
+            };
+            chunk.push((key.clone(), bytes, events));
+        }
+        if chunk.is_empty() {
+            continue;
+        }
+        if state.compacted {
+            let events: Vec<&Event> = chunk.iter().flat_map(|(_, _, events)| events).collect();
+            if !state.knows_ancestry(&events) {
+                return Ok(CatchUp::Stale);
+            }
+        }
+        // A transaction is written in one append, so the appended events
+        // hold all of any group they include
+        let incomplete = incomplete_transactions(chunk.iter().flat_map(|(_, _, events)| events));
+        replay_files(state, chunk, &incomplete);
+        advanced = true;
+    }
+
+    if !advanced {
+        return Ok(CatchUp::Fresh);
+    }
+    state.summarize();
+    Ok(CatchUp::Advanced)
+}
+
+fn apply_events(state: &mut State, events: Vec<Event>) {
     for event in events {
-        apply_event(tasks, streams, event);
+        if event.v > CURRENT_VERSION {
+            *state.skipped.newer_versions.entry(event.v).or_default() += 1;
+            continue;
+        }
+        if let Operation::Unknown(op) = &event.op {
+            *state.skipped.unknown_ops.entry(op.clone()).or_default() += 1;
+            continue;
+        }
+        // Events whose payload doesn't match their operation are reported by
+        // `spool validate`; materialization skips them rather than guessing
+        let Ok(payload) = event.payload() else {
+            state.skipped.invalid_payloads += 1;
+            continue;
+        };
+        if state.tasks.contains_key(&event.id) || matches!(payload, Payload::Create(_)) {
+            state
+                .history
+                .entry(event.id.clone())
+                .or_default()
+                .record(&event, &payload);
+        }
+        apply_event(&mut state.tasks, &mut state.streams, event, payload);
     }
 }
 
@@ -133,173 +698,141 @@ fn apply_event(
     tasks: &mut HashMap<String, Task>,
     streams: &mut HashMap<String, Stream>,
     event: Event,
+    payload: Payload,
 ) {
-    match event.op {
-        Operation::Create => {
-            let d = &event.d;
+    match payload {
+        Payload::Create(d) => {
             let task = Task {
                 id: event.id.clone(),
-                title: d
-                    .get("title")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string(),
-                description: d
-                    .get("description")
-                    .and_then(|v| v.as_str())
-                    .map(String::from),
+                title: d.title,
+                description: d.description,
                 status: TaskStatus::Open,
-                priority: d.get("priority").and_then(|v| v.as_str()).map(String::from),
-                tags: d
-                    .get("tags")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(|v| v.as_str().map(String::from))
-                            .collect()
-                    })
-                    .unwrap_or_default(),
-                assignee: d.get("assignee").and_then(|v| v.as_str()).map(String::from),
+                priority: d.priority,
+                tags: d.tags,
+                assignee: d.assignee,
                 created: event.ts,
                 created_by: event.by.clone(),
                 created_branch: event.branch.clone(),
                 updated: event.ts,
                 completed: None,
                 resolution: None,
-                parent: d.get("parent").and_then(|v| v.as_str()).map(String::from),
-                blocks: d
-                    .get("blocks")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(|v| v.as_str().map(String::from))
-                            .collect()
-                    })
-                    .unwrap_or_default(),
-                blocked_by: d
-                    .get("blocked_by")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(|v| v.as_str().map(String::from))
-                            .collect()
-                    })
-                    .unwrap_or_default(),
+                parent: d.parent,
+                blocks: d.blocks,
+                blocked_by: d.blocked_by,
                 comments: Vec::new(),
                 archived: None,
-                stream: d.get("stream").and_then(|v| v.as_str()).map(String::from),
+                stream: d.stream,
             };
             tasks.insert(event.id, task);
         }
-        Operation::Update => {
+        Payload::Update(d) => {
             if let Some(task) = tasks.get_mut(&event.id) {
-                let d = &event.d;
-                if let Some(title) = d.get("title").and_then(|v| v.as_str()) {
-                    task.title = title.to_string();
+                if let Some(title) = d.title {
+                    task.title = title;
                 }
-                if let Some(desc) = d.get("description").and_then(|v| v.as_str()) {
-                    task.description = Some(desc.to_string());
+                if let Some(desc) = d.description {
+                    task.description = Some(desc);
                 }
-                if let Some(priority) = d.get("priority").and_then(|v| v.as_str()) {
-                    task.priority = Some(priority.to_string());
+                if let Some(priority) = d.priority {
+                    task.priority = Some(priority);
                 }
-                if let Some(tags) = d.get("tags").and_then(|v| v.as_array()) {
-                    task.tags = tags
-                        .iter()
-                        .filter_map(|v| v.as_str().map(String::from))
-                        .collect();
+                if let Some(tags) = d.tags {
+                    task.tags = tags;
                 }
                 task.updated = event.ts;
             }
         }
-        Operation::Assign => {
+        Payload::Assign(d) => {
             if let Some(task) = tasks.get_mut(&event.id) {
-                task.assignee = event.d.get("to").and_then(|v| {
-                    if v.is_null() {
-                        None
-                    } else {
-                        v.as_str().map(String::from)
-                    }
-                });
+                task.assignee = d.to;
                 task.updated = event.ts;
             }
         }
-        Operation::Comment => {
+        Payload::Comment(d) => {
             if let Some(task) = tasks.get_mut(&event.id) {
-                let d = &event.d;
                 task.comments.push(Comment {
+                    id: event.event_id(),
                     ts: event.ts,
                     by: event.by,
-                    body: d
-                        .get("body")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("")
-                        .to_string(),
-                    r#ref: d.get("ref").and_then(|v| v.as_str()).map(String::from),
+                    body: d.body,
+                    r#ref: d.r#ref,
+                    edited: None,
                 });
                 task.updated = event.ts;
             }
         }
-        Operation::Link => {
+        Payload::EditComment(d) => {
+            if let Some(task) = tasks.get_mut(&event.id) {
+                if let Some(comment) = task.comments.iter_mut().find(|c| c.id == d.comment) {
+                    comment.body = d.body;
+                    if d.r#ref.is_some() {
+                        comment.r#ref = d.r#ref;
+                    }
+                    comment.edited = Some(event.ts);
+                    task.updated = event.ts;
+                }
+            }
+        }
+        Payload::DeleteComment(d) => {
+            if let Some(task) = tasks.get_mut(&event.id) {
+                let before = task.comments.len();
+                task.comments.retain(|c| c.id != d.comment);
+                if task.comments.len() < before {
+                    task.updated = event.ts;
+                }
+            }
+        }
+        Payload::Tag(d) => {
+            if let Some(task) = tasks.get_mut(&event.id) {
+                for tag in d.tags {
+                    if !task.tags.contains(&tag) {
+                        task.tags.push(tag);
+                    }
+                }
+                task.updated = event.ts;
+            }
+        }
+        Payload::Untag(d) => {
             if let Some(task) = tasks.get_mut(&event.id) {
-                let d = &event.d;
-                if let (Some(rel), Some(target)) = (
-                    d.get("rel").and_then(|v| v.as_str()),
-                    d.get("target").and_then(|v| v.as_str()),
-                ) {
-                    match rel {
-                        "blocks" => {
-                            if !task.blocks.contains(&target.to_string()) {
-                                task.blocks.push(target.to_string());
-                            }
-                        }
-                        "blocked_by" => {
-                            if !task.blocked_by.contains(&target.to_string()) {
-                                task.blocked_by.push(target.to_string());
-                            }
-                        }
-                        "parent" => task.parent = Some(target.to_string()),
-                        _ => {}
+                task.tags.retain(|tag| !d.tags.contains(tag));
+                task.updated = event.ts;
+            }
+        }
+        Payload::Link(d) => {
+            if let Some(task) = tasks.get_mut(&event.id) {
+                match d.rel.as_str() {
+                    "blocks" if !task.blocks.contains(&d.target) => task.blocks.push(d.target),
+                    "blocked_by" if !task.blocked_by.contains(&d.target) => {
+                        task.blocked_by.push(d.target)
                     }
+                    "parent" => task.parent = Some(d.target),
+                    _ => {}
                 }
                 task.updated = event.ts;
             }
         }
-        Operation::Unlink => {
+        Payload::Unlink(d) => {
             if let Some(task) = tasks.get_mut(&event.id) {
-                let d = &event.d;
-                if let (Some(rel), Some(target)) = (
-                    d.get("rel").and_then(|v| v.as_str()),
-                    d.get("target").and_then(|v| v.as_str()),
-                ) {
-                    match rel {
-                        "blocks" => task.blocks.retain(|x| x != target),
-                        "blocked_by" => task.blocked_by.retain(|x| x != target),
-                        "parent" => {
-                            if task.parent.as_deref() == Some(target) {
-                                task.parent = None;
-                            }
-                        }
-                        _ => {}
+                match d.rel.as_str() {
+                    "blocks" => task.blocks.retain(|x| *x != d.target),
+                    "blocked_by" => task.blocked_by.retain(|x| *x != d.target),
+                    "parent" if task.parent.as_deref() == Some(d.target.as_str()) => {
+                        task.parent = None
                     }
+                    _ => {}
                 }
                 task.updated = event.ts;
             }
         }
-        Operation::Complete => {
+        Payload::Complete(d) => {
             if let Some(task) = tasks.get_mut(&event.id) {
                 task.status = TaskStatus::Complete;
                 task.completed = Some(event.ts);
-                task.resolution = event
-                    .d
-                    .get("resolution")
-                    .and_then(|v| v.as_str())
-                    .map(String::from)
-                    .or(Some("done".to_string()));
+                task.resolution = d.resolution.or(Some("done".to_string()));
                 task.updated = event.ts;
             }
         }
-        Operation::Reopen => {
+        Payload::Reopen(_) => {
             if let Some(task) = tasks.get_mut(&event.id) {
                 task.status = TaskStatus::Open;
                 task.completed = None;
@@ -307,196 +840,336 @@ fn apply_event(
                 task.updated = event.ts;
             }
         }
-        Operation::Archive => {
+        Payload::Archive(d) => {
             if let Some(task) = tasks.get_mut(&event.id) {
-                task.archived = event
-                    .d
-                    .get("ref")
-                    .and_then(|v| v.as_str())
-                    .map(String::from);
+                task.archived = d.r#ref;
                 task.updated = event.ts;
             }
         }
-        Operation::SetStream => {
+        Payload::SetStream(d) => {
             if let Some(task) = tasks.get_mut(&event.id) {
-                task.stream = event.d.get("stream").and_then(|v| {
-                    if v.is_null() {
-                        None
-                    } else {
-                        v.as_str().map(String::from)
-                    }
-                });
+                task.stream = d.stream;
                 task.updated = event.ts;
             }
         }
-        Operation::CreateStream => {
-            let d = &event.d;
+        Payload::CreateStream(d) => {
             let stream = Stream {
                 id: event.id.clone(),
-                name: d
-                    .get("name")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string(),
-                description: d
-                    .get("description")
-                    .and_then(|v| v.as_str())
-                    .map(String::from),
+                name: d.name,
+                description: d.description,
                 created: event.ts,
                 created_by: event.by.clone(),
             };
             streams.insert(event.id, stream);
         }
-        Operation::UpdateStream => {
+        Payload::UpdateStream(d) => {
             if let Some(stream) = streams.get_mut(&event.id) {
-                let d = &event.d;
-                if let Some(name) = d.get("name").and_then(|v| v.as_str()) {
-                    stream.name = name.to_string();
+                if let Some(name) = d.name {
+                    stream.name = name;
                 }
-                if let Some(desc) = d.get("description").and_then(|v| v.as_str()) {
-                    stream.description = Some(desc.to_string());
+                if let Some(desc) = d.description {
+                    stream.description = Some(desc);
                 }
             }
         }
-        Operation::DeleteStream => {
+        Payload::DeleteStream(_) => {
             streams.remove(&event.id);
         }
+        // Filtered out by `apply_events`
+        Payload::Unknown { .. } => {}
     }
 }
 
-/// Internal struct for building task index entries
-struct TaskIndexBuilder {
-    status: TaskStatus,
-    created: String,
-    updated: String,
-    completed: Option<String>,
-    archived: Option<String>,
+impl Index {
+    /// Segments holding an ID's events, in replay order
+    pub fn segments_of(&self, id: &str) -> Vec<String> {
+        match self.tasks.get(id) {
+            Some(task) => task.files.clone(),
+            None => self.untracked.get(id).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Index a batch of events, in replay order so creates come first
+    fn add_events(&mut self, mut events: Vec<(String, Event)>) {
+        events.sort_by(|a, b| replay_cmp(&a.1, &b.1));
+        for (segment, event) in events {
+            self.add_event(segment, &event);
+        }
+    }
+
+    fn add_event(&mut self, segment: String, event: &Event) {
+        let date = event.ts.format("%Y-%m-%d").to_string();
+
+        if event.op == Operation::Create {
+            let mut files = self.untracked.remove(&event.id).unwrap_or_default();
+            if let Some(task) = self.tasks.remove(&event.id) {
+                files.extend(task.files);
+            }
+            self.tasks.insert(
+                event.id.clone(),
+                TaskIndex {
+                    status: TaskStatus::Open,
+                    created: date.clone(),
+                    updated: date.clone(),
+                    completed: None,
+                    files,
+                    archived: None,
+                },
+            );
+        }
+
+        let Some(info) = self.tasks.get_mut(&event.id) else {
+            add_file(self.untracked.entry(event.id.clone()).or_default(), segment);
+            return;
+        };
+        add_file(&mut info.files, segment);
+        match event.op {
+            Operation::Create => {}
+            Operation::Complete => {
+                info.status = TaskStatus::Complete;
+                info.updated = date.clone();
+                info.completed = Some(date);
+            }
+            Operation::Reopen => {
+                info.status = TaskStatus::Open;
+                info.updated = date;
+                info.completed = None;
+            }
+            Operation::Archive => {
+                info.updated = date;
+                info.archived = match event.payload() {
+                    Ok(Payload::Archive(d)) => d.r#ref,
+                    _ => None,
+                };
+            }
+            _ => info.updated = date,
+        }
+    }
 }
 
-pub fn build_index(ctx: &SpoolContext) -> Result<Index> {
-    let mut task_files: HashMap<String, HashSet<String>> = HashMap::new();
-    let mut task_info: HashMap<String, TaskIndexBuilder> = HashMap::new();
+/// Add a segment to a sorted list of segments, once
+fn add_file(files: &mut Vec<String>, segment: String) {
+    if let Err(i) = files.binary_search(&segment) {
+        files.insert(i, segment);
+    }
+}
 
-    for file in ctx.get_event_files()? {
-        let filename = file
-            .file_name()
-            .ok_or_else(|| {
-                anyhow::anyhow!("event file path has no filename component: {:?}", file)
-            })?
-            .to_string_lossy()
-            .to_string();
-        let events = ctx.parse_events_from_file(&file)?;
-        for event in events {
-            task_files
-                .entry(event.id.clone())
-                .or_default()
-                .insert(filename.clone());
-
-            let date = event.ts.format("%Y-%m-%d").to_string();
-
-            match event.op {
-                Operation::Create => {
-                    task_info.insert(
-                        event.id.clone(),
-                        TaskIndexBuilder {
-                            status: TaskStatus::Open,
-                            created: date.clone(),
-                            updated: date,
-                            completed: None,
-                            archived: None,
-                        },
-                    );
-                }
-                Operation::Complete => {
-                    if let Some(info) = task_info.get_mut(&event.id) {
-                        info.status = TaskStatus::Complete;
-                        info.updated = date.clone();
-                        info.completed = Some(date);
-                    }
-                }
-                Operation::Reopen => {
-                    if let Some(info) = task_info.get_mut(&event.id) {
-                        info.status = TaskStatus::Open;
-                        info.updated = date;
-                        info.completed = None;
-                    }
-                }
-                Operation::Archive => {
-                    if let Some(info) = task_info.get_mut(&event.id) {
-                        info.updated = date;
-                        info.archived = event
-                            .d
-                            .get("ref")
-                            .and_then(|v| v.as_str())
-                            .map(String::from);
-                    }
-                }
-                _ => {
-                    if let Some(info) = task_info.get_mut(&event.id) {
-                        info.updated = date;
-                    }
+fn file_mark(bytes: &[u8]) -> FileMark {
+    FileMark {
+        offset: bytes.len() as u64,
+        hash: fnv1a(bytes),
+        max_clock: None,
+    }
+}
+
+pub fn build_index<S: EventStore + ?Sized>(store: &S) -> Result<Index> {
+    let mut watermark = Watermark::new();
+    let mut events = Vec::new();
+    parse_segments(store, store.segments()?, |(key, bytes, parsed, _)| {
+        events.extend(parsed.into_iter().map(|event| (key.clone(), event)));
+        watermark.insert(key, file_mark(&bytes));
+        Ok(())
+    })?;
+
+    let mut index = Index {
+        rebuilt: Utc::now(),
+        watermark: Some(watermark),
+        ..Default::default()
+    };
+    index.add_events(events);
+    Ok(index)
+}
+
+/// Index events appended since the index was written
+///
+/// Unlike state, the index doesn't depend on replay order, so new files
+/// anywhere are fine; only removed or rewritten files need a rebuild.
+fn catch_up_index<S: EventStore + ?Sized>(store: &S, index: &mut Index) -> Result<CatchUp> {
+    let Some(watermark) = &index.watermark else {
+        return Ok(CatchUp::Stale);
+    };
+    let segments = store.segments()?;
+    let present: HashSet<&String> = segments.iter().collect();
+    if watermark.keys().any(|key| !present.contains(key)) {
+        return Ok(CatchUp::Stale);
+    }
+
+    let mut marks = Vec::new();
+    let mut events = Vec::new();
+    for key in &segments {
+        let bytes = store.read(key)?;
+        let offset = match watermark.get(key) {
+            Some(mark) => {
+                let offset = mark.offset as usize;
+                if bytes.len() < offset || fnv1a(&bytes[..offset]) != mark.hash {
+                    return Ok(CatchUp::Stale);
                 }
+                offset
             }
+            None => 0,
+        };
+        if bytes.len() == offset {
+            continue;
         }
+        let first_line = bytes[..offset].iter().filter(|b| **b == b'\n').count() + 1;
+        let (parsed, _) = parse_events_lenient(&bytes[offset..], first_line);
+        events.extend(parsed.into_iter().map(|event| (key.clone(), event)));
+        marks.push((key.clone(), file_mark(&bytes)));
     }
 
-    let mut tasks = HashMap::new();
-    for (id, info) in task_info {
-        let files: Vec<String> = task_files
-            .get(&id)
-            .map(|s| {
-                let mut v: Vec<_> = s.iter().cloned().collect();
-                v.sort();
-                v
-            })
-            .unwrap_or_default();
-
-        tasks.insert(
-            id,
-            TaskIndex {
-                status: info.status,
-                created: info.created,
-                updated: info.updated,
-                completed: info.completed,
-                files,
-                archived: info.archived,
-            },
-        );
+    if marks.is_empty() {
+        return Ok(CatchUp::Fresh);
     }
+    index
+        .watermark
+        .get_or_insert_with(Watermark::new)
+        .extend(marks);
+    index.add_events(events);
+    Ok(CatchUp::Advanced)
+}
 
-    Ok(Index {
-        tasks,
-        rebuilt: Utc::now(),
-    })
+/// Load `.index.json`, indexing any events appended since it was written
+///
+/// Rebuilt when missing, unreadable, or when history changed underneath it.
+pub fn load_or_build_index(ctx: &SpoolContext) -> Result<Index> {
+    let cached = fs::read_to_string(ctx.index_path())
+        .ok()
+        .and_then(|content| serde_json::from_str::<Index>(&content).ok());
+
+    if let Some(mut index) = cached {
+        match catch_up_index(ctx, &mut index)? {
+            CatchUp::Fresh => return Ok(index),
+            CatchUp::Advanced => {
+                let _ = write_index(ctx, &index);
+                return Ok(index);
+            }
+            CatchUp::Stale => {}
+        }
+    }
+
+    let index = build_index(ctx)?;
+    let _ = write_index(ctx, &index);
+    Ok(index)
 }
 
+/// Record lines just appended to `segment` in `.index.json`
+///
+/// Only applies when the index was up to date with the segment before the
+/// append; otherwise the next read catches it up.
+pub(crate) fn index_appended(ctx: &SpoolContext, segment: &str, lines: &[u8]) -> Result<()> {
+    let Some(mut index) = fs::read_to_string(ctx.index_path())
+        .ok()
+        .and_then(|content| serde_json::from_str::<Index>(&content).ok())
+    else {
+        return Ok(());
+    };
+    let Some(watermark) = &mut index.watermark else {
+        return Ok(());
+    };
+
+    let bytes = ctx.read(segment)?;
+    let Some(before) = bytes.len().checked_sub(lines.len()) else {
+        return Ok(());
+    };
+    let up_to_date = match watermark.get(segment) {
+        Some(mark) => mark.offset as usize == before && mark.hash == fnv1a(&bytes[..before]),
+        None => before == 0,
+    };
+    if !up_to_date || !bytes.ends_with(lines) {
+        return Ok(());
+    }
+    watermark.insert(segment.to_string(), file_mark(&bytes));
+
+    let first_line = bytes[..before].iter().filter(|b| **b == b'\n').count() + 1;
+    let (events, _) = parse_events_lenient(lines, first_line);
+    index.add_events(
+        events
+            .into_iter()
+            .map(|event| (segment.to_string(), event))
+            .collect(),
+    );
+    write_index(ctx, &index)
+}
+
+/// Load the cached state, replaying any events appended since it was written
+///
+/// Falls back to a full replay when the cache is missing, unreadable, or the
+/// event history changed underneath it. The refreshed cache is written back
+/// on a best-effort basis so the next read starts from the new watermark.
 pub fn load_or_materialize_state(ctx: &SpoolContext) -> Result<State> {
-    let state_path = ctx.state_path();
-    if state_path.exists() {
-        let content = fs::read_to_string(&state_path)?;
-        let state: State = serde_json::from_str(&content)?;
-        Ok(state)
-    } else {
-        materialize(ctx)
+    let cached = fs::read_to_string(ctx.state_path())
+        .ok()
+        .and_then(|content| serde_json::from_str::<State>(&content).ok())
+        .filter(State::is_current_format);
+
+    if let Some(mut state) = cached {
+        match catch_up(ctx, &mut state)? {
+            CatchUp::Fresh => return Ok(state),
+            CatchUp::Advanced => {
+                let _ = write_state(ctx, &state);
+                return Ok(state);
+            }
+            CatchUp::Stale => {}
+        }
     }
+
+    let state = materialize(ctx)?;
+    let _ = write_state(ctx, &state);
+    Ok(state)
+}
+
+/// Atomically replace `.state.json` so concurrent readers never see a partial file
+fn write_state(ctx: &SpoolContext, state: &State) -> Result<()> {
+    write_cache(&ctx.state_path(), &serde_json::to_string_pretty(state)?)
+}
+
+/// Atomically replace `.index.json`, like `write_state`
+fn write_index(ctx: &SpoolContext, index: &Index) -> Result<()> {
+    write_cache(&ctx.index_path(), &serde_json::to_string_pretty(index)?)
+}
+
+fn write_cache(path: &std::path::Path, json: &str) -> Result<()> {
+    let tmp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    fs::write(&tmp_path, json)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
 }
 
 pub fn rebuild(ctx: &SpoolContext) -> Result<()> {
     println!("Rebuilding index and state...");
 
+    let archived = crate::archive::apply_retention(ctx)?;
+    if !archived.is_empty() {
+        println!(
+            "  Archived {} task(s) under the retention policy",
+            archived.len()
+        );
+    }
+
     let index = build_index(ctx)?;
-    let index_json = serde_json::to_string_pretty(&index)?;
-    fs::write(ctx.index_path(), index_json)?;
+    write_index(ctx, &index)?;
     println!("  Wrote .index.json ({} tasks)", index.tasks.len());
 
-    let state = materialize(ctx)?;
-    let state_json = serde_json::to_string_pretty(&state)?;
-    fs::write(ctx.state_path(), state_json)?;
+    let state = replay_all(ctx)?;
+    write_state(ctx, &state)?;
     println!(
         "  Wrote .state.json ({} tasks, {} streams)",
         state.tasks.len(),
         state.streams.len()
     );
+    for warning in &state.warnings {
+        println!("  Warning: {}", warning);
+    }
+
+    #[cfg(feature = "sqlite")]
+    if crate::config::Config::load(ctx)?.sqlite_index {
+        let path = ctx.sqlite_path();
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        crate::sqlite::SqliteIndex::open(ctx)?;
+        println!("  Wrote .index.db");
+    }
 
     println!("Rebuild complete.");
     Ok(())
@@ -0,0 +1,362 @@
+//! Storage backends for the event log
+//!
+//! The event log is a set of segments, each holding JSONL event lines. A
+//! segment is named by a key such as `events/2026-01-13.jsonl` (a day's
+//! events) or `archive/2026-01.jsonl` (a month of archived tasks), and keys
+//! sort in replay order: archive segments first, then by date.
+//!
+//! With the `author` layout (see `Layout`), each writer appends to its own
+//! shard of the day, e.g. `events/2026-01-13/@alice.jsonl`, so teammates
+//! working on the same day never touch the same file. A day's shards form
+//! one group (see `segment_group`) whose events replay in clock order.
+//!
+//! `SpoolContext` stores segments as files under `.spool/`, which is what the
+//! CLI uses, or on a git ref (see `gitref`). `MemoryStore` keeps them in
+//! memory, for embedding spool in other tools and for tests. Replay,
+//! indexing, writing and validation work with either through `EventStore`.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::concurrency::FileLock;
+use crate::config::Config;
+use crate::context::{parse_events_lenient, BadLine, SpoolContext};
+use crate::event::Event;
+use crate::state::{
+    index_appended, load_or_build_index, load_or_materialize_state, materialize, State,
+};
+use crate::writer::{append_durably, replace_durably};
+
+/// Directory-like prefix of live event segments
+pub const EVENTS: &str = "events";
+/// Directory-like prefix of archived event segments
+pub const ARCHIVE: &str = "archive";
+
+/// Key of the live segment for a day (`YYYY-MM-DD`)
+pub fn event_segment(day: &str) -> String {
+    format!("{}/{}.jsonl", EVENTS, day)
+}
+
+/// Key of the archive segment for a month (`YYYY-MM`)
+pub fn archive_segment(month: &str) -> String {
+    format!("{}/{}.jsonl", ARCHIVE, month)
+}
+
+/// Key of an author's shard of the live segment for a day
+pub fn author_segment(day: &str, author: &str) -> String {
+    format!("{}/{}/{}.jsonl", EVENTS, day, shard_name(author))
+}
+
+/// File name of a segment under `prefix` (`EVENTS` or `ARCHIVE`), if it's there
+///
+/// Shards keep their day directory, e.g. `2026-01-13/@alice.jsonl`.
+pub fn segment_name<'a>(segment: &'a str, prefix: &str) -> Option<&'a str> {
+    segment.strip_prefix(prefix)?.strip_prefix('/')
+}
+
+/// The group of segments a segment replays with: its day for live segments
+/// (`events/2026-01-13`), whether sharded or not, and itself otherwise
+pub fn segment_group(segment: &str) -> &str {
+    let base = segment.strip_suffix(".jsonl").unwrap_or(segment);
+    match segment_name(base, EVENTS).and_then(|name| name.find('/')) {
+        Some(end) => &base[..EVENTS.len() + 1 + end],
+        None => base,
+    }
+}
+
+/// File name for an author's shard, safe to use as a path component
+fn shard_name(author: &str) -> String {
+    let name: String = author
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "@-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    match name.as_str() {
+        "" => "unknown".to_string(),
+        _ if name.starts_with('.') => format!("_{}", name),
+        _ => name,
+    }
+}
+
+/// How new events are split into segments
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// One segment per day, shared by every writer
+    #[default]
+    Daily,
+    /// One segment per day for each writer, named after the event author or,
+    /// if set, `$SPOOL_SHARD` (e.g. a machine name)
+    Author,
+}
+
+impl Layout {
+    pub fn is_daily(&self) -> bool {
+        *self == Layout::Daily
+    }
+
+    /// Segment that events written by `author` on `day` are appended to
+    pub fn segment(&self, day: &str, author: &str) -> String {
+        match self {
+            Layout::Daily => event_segment(day),
+            Layout::Author => match std::env::var("SPOOL_SHARD") {
+                Ok(shard) if !shard.is_empty() => author_segment(day, &shard),
+                _ => author_segment(day, author),
+            },
+        }
+    }
+}
+
+/// Where events are kept
+///
+/// Writers hold `lock` while appending or replacing segments; readers don't
+/// lock, so `append` must never expose a partial line followed by more data.
+pub trait EventStore {
+    /// Held for exclusive write access
+    type Lock<'a>
+    where
+        Self: 'a;
+
+    /// Keys of all segments, in replay order
+    fn segments(&self) -> Result<Vec<String>>;
+
+    /// Raw contents of a segment
+    fn read(&self, segment: &str) -> Result<Vec<u8>>;
+
+    /// Append complete lines to a segment, creating it if needed
+    fn append(&self, segment: &str, lines: &[u8]) -> Result<()>;
+
+    /// Replace a segment's contents in one step; empty content removes it
+    ///
+    /// Used to move events between segments when archiving, and to clean up
+    /// duplicated or unreadable lines.
+    fn replace(&self, segment: &str, content: &[u8]) -> Result<()>;
+
+    /// Wait for exclusive write access
+    fn lock(&self) -> Result<Self::Lock<'_>>;
+
+    /// How new events are split into segments
+    fn layout(&self) -> Result<Layout> {
+        Ok(Layout::Daily)
+    }
+
+    /// Events in a segment in replay order, and the lines that couldn't be read
+    fn read_events(&self, segment: &str) -> Result<(Vec<Event>, Vec<BadLine>)> {
+        Ok(parse_events_lenient(&self.read(segment)?, 1))
+    }
+
+    /// Current state, from a cache if the store keeps one
+    fn current_state(&self) -> Result<State> {
+        materialize(self)
+    }
+
+    /// Segments that may hold events for `id`, in replay order, narrowed
+    /// down by an index if the store keeps one
+    fn task_segments(&self, _id: &str) -> Result<Vec<String>> {
+        self.segments()
+    }
+
+    /// Names of saved snapshots (see `snapshot`), oldest first
+    fn snapshots(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Contents of a saved snapshot
+    fn read_snapshot(&self, name: &str) -> Result<Vec<u8>> {
+        Err(anyhow!("No snapshot named {}", name))
+    }
+}
+
+/// The daily JSONL files under `.spool/events/` and `.spool/archive/`, or
+/// the same segments on a git ref if the repo is configured with one
+impl EventStore for SpoolContext {
+    type Lock<'a> = FileLock;
+
+    fn segments(&self) -> Result<Vec<String>> {
+        if let Some(git_ref) = &self.git_ref {
+            return git_ref.segments();
+        }
+        let mut segments = Vec::new();
+        for (prefix, paths) in [
+            (ARCHIVE, self.get_archive_files()?),
+            (EVENTS, self.get_event_files()?),
+        ] {
+            let dir = self.root.join(prefix);
+            for path in paths {
+                let name = path
+                    .strip_prefix(&dir)
+                    .map_err(|_| anyhow!("event file outside {:?}: {:?}", dir, path))?
+                    .to_string_lossy()
+                    .replace(std::path::MAIN_SEPARATOR, "/");
+                segments.push(format!("{}/{}", prefix, name));
+            }
+        }
+        Ok(segments)
+    }
+
+    fn read(&self, segment: &str) -> Result<Vec<u8>> {
+        if let Some(git_ref) = &self.git_ref {
+            return git_ref.read(segment);
+        }
+        let path = self.root.join(segment);
+        fs::read(&path).with_context(|| format!("Failed to open {:?}", path))
+    }
+
+    fn append(&self, segment: &str, lines: &[u8]) -> Result<()> {
+        if let Some(git_ref) = &self.git_ref {
+            git_ref.append(segment, lines)?;
+        } else {
+            let path = self.root.join(segment);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            append_durably(&path, lines)?;
+        }
+        // The event is written; the index is only a cache
+        let _ = index_appended(self, segment, lines);
+        Ok(())
+    }
+
+    fn replace(&self, segment: &str, content: &[u8]) -> Result<()> {
+        if let Some(git_ref) = &self.git_ref {
+            return git_ref.replace(segment, content);
+        }
+        let path = self.root.join(segment);
+        if content.is_empty() {
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+            // Drop a day's shard directory with its last shard
+            if segment_name(segment, EVENTS).is_some_and(|name| name.contains('/')) {
+                if let Some(dir) = path.parent() {
+                    let _ = fs::remove_dir(dir);
+                }
+            }
+            Ok(())
+        } else {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            replace_durably(&path, content)
+        }
+    }
+
+    /// Also guards the git ref, which only this lock moves locally
+    fn lock(&self) -> Result<FileLock> {
+        FileLock::wait(self)
+    }
+
+    fn layout(&self) -> Result<Layout> {
+        Ok(Config::load(self)?.layout)
+    }
+
+    fn current_state(&self) -> Result<State> {
+        load_or_materialize_state(self)
+    }
+
+    /// From `.index.json`
+    fn task_segments(&self, id: &str) -> Result<Vec<String>> {
+        Ok(load_or_build_index(self)?.segments_of(id))
+    }
+
+    fn snapshots(&self) -> Result<Vec<String>> {
+        let dir = self.snapshots_dir();
+        let mut names = Vec::new();
+        if dir.is_dir() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    if let Some(name) = path.file_name() {
+                        names.push(name.to_string_lossy().to_string());
+                    }
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn read_snapshot(&self, name: &str) -> Result<Vec<u8>> {
+        let path = self.snapshots_dir().join(name);
+        fs::read(&path).with_context(|| format!("Failed to read {:?}", path))
+    }
+}
+
+/// An event log held in memory
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    segments: Mutex<BTreeMap<String, Vec<u8>>>,
+    lock: Mutex<()>,
+    layout: Layout,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store that splits new events into segments by `layout`
+    pub fn with_layout(layout: Layout) -> Self {
+        Self {
+            layout,
+            ..Self::default()
+        }
+    }
+
+    fn segments_mut(&self) -> Result<MutexGuard<'_, BTreeMap<String, Vec<u8>>>> {
+        self.segments
+            .lock()
+            .map_err(|_| anyhow!("Event store poisoned by a panicked writer"))
+    }
+}
+
+impl EventStore for MemoryStore {
+    type Lock<'a> = MutexGuard<'a, ()>;
+
+    fn segments(&self) -> Result<Vec<String>> {
+        Ok(self.segments_mut()?.keys().cloned().collect())
+    }
+
+    fn read(&self, segment: &str) -> Result<Vec<u8>> {
+        self.segments_mut()?
+            .get(segment)
+            .cloned()
+            .ok_or_else(|| anyhow!("No segment named {}", segment))
+    }
+
+    fn append(&self, segment: &str, lines: &[u8]) -> Result<()> {
+        let mut segments = self.segments_mut()?;
+        let content = segments.entry(segment.to_string()).or_default();
+        if content.last().is_some_and(|b| *b != b'\n') {
+            content.push(b'\n');
+        }
+        content.extend_from_slice(lines);
+        Ok(())
+    }
+
+    fn replace(&self, segment: &str, content: &[u8]) -> Result<()> {
+        let mut segments = self.segments_mut()?;
+        if content.is_empty() {
+            segments.remove(segment);
+        } else {
+            segments.insert(segment.to_string(), content.to_vec());
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, ()>> {
+        self.lock
+            .lock()
+            .map_err(|_| anyhow!("Event store poisoned by a panicked writer"))
+    }
+
+    fn layout(&self) -> Result<Layout> {
+        Ok(self.layout)
+    }
+}
@@ -1,12 +1,17 @@
-use anyhow::{anyhow, Result};
-use chrono::DateTime;
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 
-use crate::context::SpoolContext;
-use crate::state::materialize;
+use crate::context::{parse_events_lenient, SpoolContext};
+use crate::deps::LinkGraph;
+use crate::event::{replay_cmp, upcast, Event, Operation, CURRENT_VERSION};
+use crate::snapshot;
+use crate::state::{incomplete_transactions, replay_all};
+use crate::store::{segment_group, segment_name, EventStore, ARCHIVE, EVENTS};
+use crate::writer::append_durably;
 
 #[derive(Debug)]
 pub struct ValidationResult {
@@ -14,51 +19,78 @@ pub struct ValidationResult {
     pub warnings: Vec<String>,
 }
 
-pub fn validate(ctx: &SpoolContext, strict: bool) -> Result<ValidationResult> {
+pub fn validate<S: EventStore + ?Sized>(store: &S, strict: bool) -> Result<ValidationResult> {
     let mut errors = Vec::new();
     let mut warnings = Vec::new();
     let mut created_ids: HashSet<String> = HashSet::new();
+    let mut seen: HashMap<u64, String> = HashMap::new();
+    let mut events = Vec::new();
 
-    // Validate event files
-    for file in ctx.get_event_files()? {
-        let filename = file
-            .file_name()
-            .ok_or_else(|| {
-                anyhow::anyhow!("event file path has no filename component: {:?}", file)
-            })?
-            .to_string_lossy()
-            .to_string();
-        validate_event_file(
-            &file,
-            &filename,
-            &mut errors,
-            &mut warnings,
-            &mut created_ids,
-        )?;
+    // Validate event segments, then archive segments, checking the order of
+    // creates a day (with all of its shards) at a time
+    let segments = store.segments()?;
+    let mut group: Vec<&str> = Vec::new();
+    let mut group_events: Vec<(Event, String)> = Vec::new();
+    for prefix in [EVENTS, ARCHIVE] {
+        for key in &segments {
+            let Some(filename) = segment_name(key, prefix) else {
+                continue;
+            };
+            if group
+                .first()
+                .is_some_and(|first| segment_group(first) != segment_group(key))
+            {
+                check_creates(
+                    std::mem::take(&mut group_events),
+                    group.len() > 1,
+                    &mut created_ids,
+                    &mut warnings,
+                );
+                group.clear();
+            }
+            group.push(key);
+            let content = match store.read(key) {
+                Ok(content) => content,
+                Err(e) => {
+                    errors.push(format!("Cannot open {}: {:#}", filename, e));
+                    continue;
+                }
+            };
+            validate_event_file(
+                &content,
+                filename,
+                &mut errors,
+                &mut warnings,
+                &mut seen,
+                &mut events,
+                &mut group_events,
+            )?;
+        }
     }
+    check_creates(
+        group_events,
+        group.len() > 1,
+        &mut created_ids,
+        &mut warnings,
+    );
 
-    // Validate archive files
-    for file in ctx.get_archive_files()? {
-        let filename = file
-            .file_name()
-            .ok_or_else(|| {
-                anyhow::anyhow!("archive file path has no filename component: {:?}", file)
-            })?
-            .to_string_lossy()
-            .to_string();
-        validate_event_file(
-            &file,
-            &filename,
-            &mut errors,
-            &mut warnings,
-            &mut created_ids,
-        )?;
+    // Archiving moves events between files, so transactions are checked
+    // across all of them
+    let mut incomplete: Vec<String> = incomplete_transactions(&events).into_iter().collect();
+    incomplete.sort();
+    for txn in incomplete {
+        warnings.push(format!(
+            "Transaction {} is incomplete (interrupted write?); its events will be skipped",
+            txn
+        ));
     }
 
     // Only check for orphaned references if no errors occurred
     // (materialize will fail on invalid events)
     if errors.is_empty() {
-        let state = materialize(ctx)?;
+        // Replay from scratch rather than trusting the snapshots being checked
+        let state = replay_all(store)?;
+        snapshot::verify(store, &state, &mut errors, &mut warnings)?;
         for task in state.tasks.values() {
             for blocked_by in &task.blocked_by {
                 if !state.tasks.contains_key(blocked_by) {
@@ -85,6 +117,11 @@ pub fn validate(ctx: &SpoolContext, strict: bool) -> Result<ValidationResult> {
                 }
             }
         }
+        // Links merged from different branches can close a cycle that
+        // neither branch could have written alone
+        for cycle in LinkGraph::new(&state).cycles() {
+            warnings.push(format!("Linked tasks form a cycle: {}", cycle.join(" -> ")));
+        }
     }
 
     let result = ValidationResult { errors, warnings };
@@ -124,23 +161,16 @@ pub fn validate(ctx: &SpoolContext, strict: bool) -> Result<ValidationResult> {
 }
 
 fn validate_event_file(
-    path: &Path,
+    content: &[u8],
     filename: &str,
     errors: &mut Vec<String>,
     warnings: &mut Vec<String>,
-    created_ids: &mut HashSet<String>,
+    seen: &mut HashMap<u64, String>,
+    events: &mut Vec<Event>,
+    located: &mut Vec<(Event, String)>,
 ) -> Result<()> {
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => {
-            errors.push(format!("Cannot open {}: {}", filename, e));
-            return Ok(());
-        }
-    };
-    let reader = BufReader::new(file);
-
-    for (line_num, line) in reader.lines().enumerate() {
-        let line = match line {
+    for (line_num, line) in content.split(|b| *b == b'\n').enumerate() {
+        let line = match std::str::from_utf8(line) {
             Ok(l) => l,
             Err(e) => {
                 errors.push(format!("{}:{}: Read error: {}", filename, line_num + 1, e));
@@ -152,7 +182,7 @@ fn validate_event_file(
             continue;
         }
 
-        let event: serde_json::Value = match serde_json::from_str(&line) {
+        let event: serde_json::Value = match serde_json::from_str(line) {
             Ok(v) => v,
             Err(e) => {
                 errors.push(format!(
@@ -178,42 +208,78 @@ fn validate_event_file(
             }
         }
 
-        // Check schema version
+        // Negotiate the schema version: older events are upcast on read,
+        // newer ones are kept but skipped by materialization
         if let Some(v) = event.get("v").and_then(|v| v.as_u64()) {
-            if v != 1 {
+            if v > CURRENT_VERSION as u64 {
                 warnings.push(format!(
-                    "{}:{}: Unknown schema version {}",
+                    "{}:{}: Unknown schema version {} (this spool supports up to {}); event will be skipped",
                     filename,
                     line_num + 1,
-                    v
+                    v,
+                    CURRENT_VERSION
                 ));
             }
         }
 
-        // Track creates for orphan detection
-        if let Some(op) = event.get("op").and_then(|v| v.as_str()) {
-            if let Some(id) = event.get("id").and_then(|v| v.as_str()) {
-                if op == "create" {
-                    if created_ids.contains(id) {
-                        warnings.push(format!(
-                            "{}:{}: Duplicate create for task {}",
-                            filename,
-                            line_num + 1,
-                            id
-                        ));
-                    }
-                    created_ids.insert(id.to_string());
-                } else if !created_ids.contains(id) {
+        // Check the event upcasts cleanly (a missing `v` is reported above)
+        let upcasted = match upcast(event.clone()) {
+            Ok(raw) => Some(raw),
+            Err(e) => {
+                if event.get("v").is_some() {
+                    errors.push(format!("{}:{}: {:#}", filename, line_num + 1, e));
+                }
+                None
+            }
+        };
+
+        let parsed = upcasted.and_then(|raw| serde_json::from_value::<Event>(raw).ok());
+        if let Some(parsed) = &parsed {
+            events.push(parsed.clone());
+        }
+
+        // Exact copies of an earlier event are ignored on replay; report them
+        // once instead of repeating every other check
+        if let Some(parsed) = &parsed {
+            let location = format!("{}:{}", filename, line_num + 1);
+            match seen.entry(parsed.content_hash()) {
+                Entry::Occupied(first) => {
+                    warnings.push(format!(
+                        "{}: Duplicate of event at {} ({} {})",
+                        location,
+                        first.get(),
+                        parsed.op,
+                        parsed.id
+                    ));
+                    continue;
+                }
+                Entry::Vacant(slot) => {
+                    slot.insert(location);
+                }
+            }
+        }
+
+        // Check the payload matches what its operation expects
+        if let Some(parsed) = &parsed {
+            if parsed.v <= CURRENT_VERSION {
+                if let Operation::Unknown(op) = &parsed.op {
                     warnings.push(format!(
-                        "{}:{}: Event for task {} before create",
+                        "{}:{}: Unknown operation '{}' (written by a newer spool?); event will be skipped",
                         filename,
                         line_num + 1,
-                        id
+                        op
                     ));
+                } else if let Err(e) = parsed.payload() {
+                    errors.push(format!("{}:{}: {:#}", filename, line_num + 1, e));
                 }
             }
         }
 
+        // Creates are checked in replay order (see `check_creates`)
+        if let Some(parsed) = parsed {
+            located.push((parsed, format!("{}:{}", filename, line_num + 1)));
+        }
+
         // Validate timestamp format
         if let Some(ts) = event.get("ts").and_then(|v| v.as_str()) {
             if DateTime::parse_from_rfc3339(ts).is_err() {
@@ -229,3 +295,143 @@ fn validate_event_file(
 
     Ok(())
 }
+
+/// Warn about duplicate creates and events before their task's create
+///
+/// `events` are a group's events with their locations, in file order; the
+/// shards of a day are sorted into replay order first, as replay does.
+fn check_creates(
+    mut events: Vec<(Event, String)>,
+    sharded: bool,
+    created_ids: &mut HashSet<String>,
+    warnings: &mut Vec<String>,
+) {
+    if sharded {
+        events.sort_by(|(a, _), (b, _)| replay_cmp(a, b));
+    }
+    for (event, location) in events {
+        if event.op == Operation::Create {
+            if created_ids.contains(&event.id) {
+                warnings.push(format!(
+                    "{}: Duplicate create for task {}",
+                    location, event.id
+                ));
+            }
+            created_ids.insert(event.id);
+        } else if !created_ids.contains(&event.id) {
+            warnings.push(format!(
+                "{}: Event for task {} before create",
+                location, event.id
+            ));
+        }
+    }
+}
+
+/// Rewrite event files without exact duplicate events
+///
+/// The first copy of each event in replay order is kept. Lines that don't
+/// parse are left in place for `validate` to report. Returns the number of
+/// lines removed.
+pub fn remove_duplicates<S: EventStore + ?Sized>(store: &S) -> Result<usize> {
+    let _lock = store.lock()?;
+    let mut seen: HashSet<u64> = HashSet::new();
+    let mut removed = 0;
+
+    for key in store.segments()? {
+        let content = String::from_utf8(store.read(&key)?)
+            .with_context(|| format!("Failed to read {}", key))?;
+
+        let mut kept = String::with_capacity(content.len());
+        let mut dropped = 0;
+        for line in content.lines() {
+            let duplicate = !line.trim().is_empty()
+                && Event::from_json(line).is_ok_and(|event| !seen.insert(event.content_hash()));
+            if duplicate {
+                dropped += 1;
+            } else {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+
+        if dropped == 0 {
+            continue;
+        }
+
+        store.replace(&key, kept.as_bytes())?;
+
+        println!("  Removed {} duplicate event(s) from {}", dropped, key);
+        removed += dropped;
+    }
+
+    Ok(removed)
+}
+
+/// Move lines that aren't valid events out of the event files
+///
+/// Each corrupt line is appended to `.spool/rejected/<dir>/<file>` as a JSON
+/// record of where it came from, why it was rejected and its original text,
+/// before the event file is rewritten without it. Returns the number of
+/// lines quarantined (or that would be, with `dry_run`).
+pub fn repair(ctx: &SpoolContext, dry_run: bool) -> Result<usize> {
+    let _lock = ctx.lock()?;
+    let mut quarantined = 0;
+
+    for key in ctx.segments()? {
+        let bytes = ctx.read(&key)?;
+        let (_, bad) = parse_events_lenient(&bytes, 1);
+        if bad.is_empty() {
+            continue;
+        }
+        quarantined += bad.len();
+
+        if dry_run {
+            println!("  Would quarantine {} line(s) from {}:", bad.len(), key);
+            for line in &bad {
+                println!("    line {}: {}", line.line, line.error);
+            }
+            continue;
+        }
+
+        // Save the lines before removing them, so a crash in between at
+        // worst leaves them in both places
+        let rejected_path = ctx.root.join("rejected").join(&key);
+        if let Some(dir) = rejected_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let now = Utc::now().to_rfc3339();
+        let mut records = String::new();
+        for line in &bad {
+            let record = json!({
+                "file": key,
+                "line": line.line,
+                "error": line.error,
+                "text": line.text,
+                "rejected": now,
+            });
+            records.push_str(&serde_json::to_string(&record)?);
+            records.push('\n');
+        }
+        append_durably(&rejected_path, records.as_bytes())?;
+
+        let rejected: HashSet<usize> = bad.iter().map(|line| line.line).collect();
+        let mut kept = Vec::with_capacity(bytes.len());
+        for (i, line) in bytes.split(|b| *b == b'\n').enumerate() {
+            if rejected.contains(&(i + 1)) || line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            kept.extend_from_slice(line);
+            kept.push(b'\n');
+        }
+        ctx.replace(&key, &kept)?;
+
+        println!(
+            "  Quarantined {} line(s) from {} to rejected/{}",
+            bad.len(),
+            key,
+            key
+        );
+    }
+
+    Ok(quarantined)
+}
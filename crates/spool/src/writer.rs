@@ -1,11 +1,24 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use chrono::Utc;
-use std::fs::OpenOptions;
-use std::io::{BufWriter, Write};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 
+use crate::clock::Hlc;
+use crate::concurrency::{check_version, write_event_with_version, Version, WriteResult};
 use crate::context::SpoolContext;
-use crate::event::{Event, Operation};
+use crate::deps::{has_link, ready_tasks, LinkGraph, Relation};
+use crate::event::{Event, Operation, Txn};
 use crate::id::generate_id;
+use crate::payload::{
+    AssignPayload, CommentPayload, CompletePayload, CreatePayload, CreateStreamPayload,
+    DeleteCommentPayload, DeleteStreamPayload, EditCommentPayload, LinkPayload, Payload,
+    ReopenPayload, SetStreamPayload, TagPayload, UpdatePayload, UpdateStreamPayload,
+};
+use crate::query::TaskQuery;
+use crate::state::{State, Task};
+use crate::store::{segment_group, segment_name, EventStore, EVENTS};
 
 /// Parameters for creating a new task
 #[derive(Default)]
@@ -19,69 +32,299 @@ pub struct CreateTaskParams<'a> {
 }
 
 /// Write an event to the current day's event file
-pub fn write_event(ctx: &SpoolContext, event: &Event) -> Result<()> {
-    let today = Utc::now().format("%Y-%m-%d").to_string();
-    let event_file = ctx.events_dir.join(format!("{}.jsonl", today));
+pub fn write_event<S: EventStore + ?Sized>(store: &S, event: &Event) -> Result<()> {
+    write_event_checked(store, event, None)
+}
+
+/// Write an event, only if its task is still at `expected` (when given)
+///
+/// Goes through the locked, version-checked append path; a stale expected
+/// version is reported as an error.
+pub fn write_event_checked<S: EventStore + ?Sized>(
+    store: &S,
+    event: &Event,
+    expected: Option<&Version>,
+) -> Result<()> {
+    check_result(write_event_with_version(store, event, expected)?, &event.id)
+}
+
+fn check_result(result: WriteResult, id: &str) -> Result<()> {
+    match result {
+        WriteResult::Success => Ok(()),
+        WriteResult::Conflict {
+            expected_version,
+            actual_version,
+        } => Err(anyhow!(
+            "{} was modified concurrently: expected version {}, found {}",
+            id,
+            expected_version.last_event_hash,
+            actual_version.last_event_hash
+        )),
+        WriteResult::Error(message) => Err(anyhow!("Cannot write to {}: {}", id, message)),
+    }
+}
+
+/// A group of events written all-or-nothing
+///
+/// Events are buffered until `commit`, which checks any expected versions
+/// and appends the whole group with a single write. Each event records the
+/// transaction's ID and size, so replay ignores a group that a crash left
+/// partly written.
+pub struct Transaction<'a, S: EventStore + ?Sized = SpoolContext> {
+    store: &'a S,
+    events: Vec<(Event, Option<Version>)>,
+}
+
+impl<'a, S: EventStore + ?Sized> Transaction<'a, S> {
+    pub fn new(store: &'a S) -> Self {
+        Self {
+            store,
+            events: Vec::new(),
+        }
+    }
+
+    /// Add an event, checked like `write_event`
+    pub fn push(&mut self, event: Event) -> &mut Self {
+        self.push_checked(event, None)
+    }
+
+    /// Add an event, checked like `write_event_checked`
+    ///
+    /// Versions are checked against the repo before the transaction, not
+    /// against events earlier in it.
+    pub fn push_checked(&mut self, event: Event, expected: Option<&Version>) -> &mut Self {
+        self.events.push((event, expected.cloned()));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Write every event, or none if any version check fails
+    pub fn commit(self) -> Result<()> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = self.store.lock()?;
+        self.commit_locked()
+    }
+
+    /// Like `commit`; callers hold the write lock
+    pub(crate) fn commit_locked(self) -> Result<()> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+
+        for (event, expected) in &self.events {
+            check_result(
+                check_version(self.store, event, expected.as_ref())?,
+                &event.id,
+            )?;
+        }
+
+        let mut events: Vec<Event> = self.events.into_iter().map(|(event, _)| event).collect();
+        if events.len() > 1 {
+            let txn = Txn {
+                id: generate_id(),
+                n: events.len() as u32,
+            };
+            for event in &mut events {
+                event.txn = Some(txn.clone());
+            }
+        }
+        append_events(self.store, &events)
+    }
+}
+
+/// Append events to the current day's event file, in order, under the
+/// spool's write lock
+pub fn write_events<S: EventStore + ?Sized>(store: &S, events: &[Event]) -> Result<()> {
+    let _lock = store.lock()?;
+    append_events(store, events)
+}
+
+/// Append events to the current day's segment; callers hold the write lock
+///
+/// Events are stamped first (see `stamp_events`) and written in one append.
+pub(crate) fn append_events<S: EventStore + ?Sized>(store: &S, events: &[Event]) -> Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    let segment = todays_segment(store, &events[0].by)?;
+    let mut lines = String::new();
+    for event in stamp_events(store, &segment, events)? {
+        lines.push_str(&serde_json::to_string(&event)?);
+        lines.push('\n');
+    }
+
+    store.append(&segment, lines.as_bytes())
+}
+
+/// Segment that new events by `author` are appended to
+pub(crate) fn todays_segment<S: EventStore + ?Sized>(store: &S, author: &str) -> Result<String> {
+    let day = Utc::now().format("%Y-%m-%d").to_string();
+    Ok(store.layout()?.segment(&day, author))
+}
+
+/// Prepare new events for appending to `segment`
+///
+/// Events without a clock are stamped with consecutive clock values, so they
+/// replay after everything already in the repo and in the order given. Task
+/// events without `prev` record the task's latest events, so edits made
+/// concurrently on other branches can be detected (see `conflict`).
+pub(crate) fn stamp_events<S: EventStore + ?Sized>(
+    store: &S,
+    segment: &str,
+    events: &[Event],
+) -> Result<Vec<Event>> {
+    let mut clock = None;
+    let mut state: Option<Option<State>> = None;
+    let mut frontiers: HashMap<String, Vec<String>> = HashMap::new();
+    let mut stamped = Vec::with_capacity(events.len());
+    for event in events {
+        let mut event = event.clone();
+        if event.prev.is_empty() && is_task_operation(&event.op) {
+            event.prev = match frontiers.get(&event.id) {
+                Some(frontier) => frontier.clone(),
+                // Ancestry is best effort; a repo whose state can't be
+                // loaded still accepts writes
+                None => state
+                    .get_or_insert_with(|| store.current_state().ok())
+                    .as_ref()
+                    .map(|state| state.frontier(&event.id))
+                    .unwrap_or_default(),
+            };
+        }
+        if event.hlc.is_none() {
+            if clock.is_none() {
+                clock = last_clock(store, segment)?;
+            }
+            event.hlc = Some(Hlc::tick(clock, Utc::now()));
+        }
+        clock = event.hlc;
+        if is_task_operation(&event.op) {
+            frontiers.insert(event.id.clone(), vec![event.event_id()]);
+        }
+        stamped.push(event);
+    }
+    Ok(stamped)
+}
 
-    let file = OpenOptions::new()
+/// Append complete lines to `path` with a single write and flush them to disk
+///
+/// A write cut short by a crash leaves at most one partial line at the end of
+/// the file. If the file doesn't end in a newline, one is written first, so
+/// that fragment stays on its own line instead of corrupting the next event.
+pub(crate) fn append_durably(path: &Path, lines: &[u8]) -> Result<()> {
+    let existed = path.exists();
+    let mut file = OpenOptions::new()
         .create(true)
+        .read(true)
         .append(true)
-        .open(&event_file)?;
-    let mut writer = BufWriter::new(file);
+        .open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+
+    let len = file.metadata()?.len();
+    let mut buf = Vec::with_capacity(lines.len() + 1);
+    if len > 0 {
+        let mut last = [0u8; 1];
+        file.seek(SeekFrom::Start(len - 1))?;
+        file.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            buf.push(b'\n');
+        }
+    }
+    buf.extend_from_slice(lines);
+
+    file.write_all(&buf)
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    file.sync_data()?;
+
+    // Make the new file's directory entry durable too
+    if !existed {
+        if let Some(dir) = path.parent() {
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+    }
+    Ok(())
+}
 
-    let json = serde_json::to_string(event)?;
-    writeln!(writer, "{}", json)?;
-    writer.flush()?;
+fn is_task_operation(op: &Operation) -> bool {
+    !matches!(
+        op,
+        Operation::CreateStream
+            | Operation::UpdateStream
+            | Operation::DeleteStream
+            | Operation::Unknown(_)
+    )
+}
+
+/// Newest clock value a writer has observed
+///
+/// Looks at the day being appended to and the latest day of event segments
+/// (every shard of it), which holds the newest history pulled from teammates
+/// (including any whose clocks run ahead), so new events always sort after
+/// what's already there.
+fn last_clock<S: EventStore + ?Sized>(store: &S, segment: &str) -> Result<Option<Hlc>> {
+    let existing = store.segments()?;
+    let latest = existing
+        .iter()
+        .rev()
+        .find(|key| segment_name(key, EVENTS).is_some())
+        .map(|key| segment_group(key));
+
+    let mut last = None;
+    for key in existing.iter().filter(|key| {
+        segment_group(key) == segment_group(segment) || Some(segment_group(key)) == latest
+    }) {
+        for event in store.read_events(key)?.0 {
+            last = last.max(Some(event.clock()));
+        }
+    }
+    Ok(last)
+}
 
+/// Replace a file's contents atomically, so an interrupted rewrite loses nothing
+pub(crate) fn replace_durably(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension(format!("jsonl.{}.tmp", std::process::id()));
+    let mut file =
+        File::create(&tmp_path).with_context(|| format!("Failed to write {:?}", tmp_path))?;
+    file.write_all(content)
+        .with_context(|| format!("Failed to write {:?}", tmp_path))?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path).with_context(|| format!("Failed to replace {:?}", path))?;
     Ok(())
 }
 
 /// Create a new task and return its ID
-pub fn create_task(
-    ctx: &SpoolContext,
+pub fn create_task<S: EventStore + ?Sized>(
+    store: &S,
     params: CreateTaskParams,
     by: &str,
     branch: &str,
 ) -> Result<String> {
     let id = generate_id();
 
-    let mut d = serde_json::json!({
-        "title": params.title,
+    let payload = Payload::Create(CreatePayload {
+        title: params.title.to_string(),
+        description: params.description.map(String::from),
+        priority: params.priority.map(String::from),
+        assignee: params.assignee.map(String::from),
+        tags: params.tags,
+        stream: params.stream.map(String::from),
+        ..Default::default()
     });
 
-    if let Some(desc) = params.description {
-        d["description"] = serde_json::Value::String(desc.to_string());
-    }
-    if let Some(p) = params.priority {
-        d["priority"] = serde_json::Value::String(p.to_string());
-    }
-    if let Some(a) = params.assignee {
-        d["assignee"] = serde_json::Value::String(a.to_string());
-    }
-    if !params.tags.is_empty() {
-        d["tags"] = serde_json::Value::Array(
-            params
-                .tags
-                .into_iter()
-                .map(serde_json::Value::String)
-                .collect(),
-        );
-    }
-    if let Some(s) = params.stream {
-        d["stream"] = serde_json::Value::String(s.to_string());
-    }
-
-    let event = Event {
-        v: 1,
-        op: Operation::Create,
-        id: id.clone(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d,
-    };
-
-    write_event(ctx, &event)?;
+    write_event(store, &Event::new(&id, payload, by, branch))?;
 
     Ok(id)
 }
@@ -100,8 +343,8 @@ pub fn get_current_branch() -> Result<String> {
 }
 
 /// Update a task's fields
-pub fn update_task(
-    ctx: &SpoolContext,
+pub fn update_task<S: EventStore + ?Sized>(
+    store: &S,
     id: &str,
     title: Option<&str>,
     description: Option<&str>,
@@ -109,126 +352,287 @@ pub fn update_task(
     by: &str,
     branch: &str,
 ) -> Result<()> {
-    let mut d = serde_json::Map::new();
-
-    if let Some(t) = title {
-        d.insert(
-            "title".to_string(),
-            serde_json::Value::String(t.to_string()),
-        );
-    }
-    if let Some(desc) = description {
-        d.insert(
-            "description".to_string(),
-            serde_json::Value::String(desc.to_string()),
-        );
-    }
-    if let Some(p) = priority {
-        d.insert(
-            "priority".to_string(),
-            serde_json::Value::String(p.to_string()),
-        );
-    }
+    let d = UpdatePayload {
+        title: title.map(String::from),
+        description: description.map(String::from),
+        priority: priority.map(String::from),
+        ..Default::default()
+    };
 
     if d.is_empty() {
         return Err(anyhow::anyhow!("No fields to update"));
     }
 
-    let event = Event {
-        v: 1,
-        op: Operation::Update,
-        id: id.to_string(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d: serde_json::Value::Object(d),
-    };
-
-    write_event(ctx, &event)
+    write_event(store, &Event::new(id, Payload::Update(d), by, branch))
 }
 
 /// Complete a task
-pub fn complete_task(
-    ctx: &SpoolContext,
+pub fn complete_task<S: EventStore + ?Sized>(
+    store: &S,
     id: &str,
     resolution: Option<&str>,
     by: &str,
     branch: &str,
 ) -> Result<()> {
-    let event = Event {
-        v: 1,
-        op: Operation::Complete,
-        id: id.to_string(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d: serde_json::json!({
-            "resolution": resolution.unwrap_or("done")
-        }),
-    };
+    let payload = Payload::Complete(CompletePayload {
+        resolution: Some(resolution.unwrap_or("done").to_string()),
+        ..Default::default()
+    });
 
-    write_event(ctx, &event)
+    write_event(store, &Event::new(id, payload, by, branch))
 }
 
 /// Reopen a completed task
-pub fn reopen_task(ctx: &SpoolContext, id: &str, by: &str, branch: &str) -> Result<()> {
-    let event = Event {
-        v: 1,
-        op: Operation::Reopen,
-        id: id.to_string(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d: serde_json::json!({}),
-    };
+pub fn reopen_task<S: EventStore + ?Sized>(
+    store: &S,
+    id: &str,
+    by: &str,
+    branch: &str,
+) -> Result<()> {
+    let payload = Payload::Reopen(ReopenPayload::default());
 
-    write_event(ctx, &event)
+    write_event(store, &Event::new(id, payload, by, branch))
 }
 
 /// Assign a task to a user
-pub fn assign_task(
-    ctx: &SpoolContext,
+pub fn assign_task<S: EventStore + ?Sized>(
+    store: &S,
     id: &str,
     assignee: Option<&str>,
     by: &str,
     branch: &str,
 ) -> Result<()> {
-    let event = Event {
-        v: 1,
-        op: Operation::Assign,
-        id: id.to_string(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d: serde_json::json!({
-            "to": assignee
-        }),
+    let payload = Payload::Assign(AssignPayload {
+        to: assignee.map(String::from),
+        ..Default::default()
+    });
+
+    write_event(store, &Event::new(id, payload, by, branch))
+}
+
+/// Add a comment to a task, optionally pointing at a commit or URL
+pub fn add_comment<S: EventStore + ?Sized>(
+    store: &S,
+    id: &str,
+    body: &str,
+    r#ref: Option<&str>,
+    by: &str,
+    branch: &str,
+) -> Result<()> {
+    let payload = Payload::Comment(CommentPayload {
+        body: body.to_string(),
+        r#ref: r#ref.map(String::from),
+        ..Default::default()
+    });
+
+    write_event(store, &Event::new(id, payload, by, branch))
+}
+
+/// Replace the body (and, if given, the ref) of a task's comment
+pub fn edit_comment<S: EventStore + ?Sized>(
+    store: &S,
+    id: &str,
+    comment: &str,
+    body: &str,
+    r#ref: Option<&str>,
+    by: &str,
+    branch: &str,
+) -> Result<()> {
+    let payload = Payload::EditComment(EditCommentPayload {
+        comment: comment.to_string(),
+        body: body.to_string(),
+        r#ref: r#ref.map(String::from),
+        ..Default::default()
+    });
+
+    write_event(store, &Event::new(id, payload, by, branch))
+}
+
+/// Remove a comment from a task
+pub fn delete_comment<S: EventStore + ?Sized>(
+    store: &S,
+    id: &str,
+    comment: &str,
+    by: &str,
+    branch: &str,
+) -> Result<()> {
+    let payload = Payload::DeleteComment(DeleteCommentPayload {
+        comment: comment.to_string(),
+        ..Default::default()
+    });
+
+    write_event(store, &Event::new(id, payload, by, branch))
+}
+
+/// Add and remove tags on a task, leaving its other tags alone
+///
+/// Writes a `tag` event for `add` and an `untag` event for `remove`, in one
+/// transaction when there are both.
+pub fn tag_task<S: EventStore + ?Sized>(
+    store: &S,
+    id: &str,
+    add: &[String],
+    remove: &[String],
+    by: &str,
+    branch: &str,
+) -> Result<()> {
+    let tags = |tags: &[String]| TagPayload {
+        tags: tags.to_vec(),
+        ..Default::default()
+    };
+    let mut txn = Transaction::new(store);
+    if !add.is_empty() {
+        txn.push(Event::new(id, Payload::Tag(tags(add)), by, branch));
+    }
+    if !remove.is_empty() {
+        txn.push(Event::new(id, Payload::Untag(tags(remove)), by, branch));
+    }
+    if txn.is_empty() {
+        return Err(anyhow!("No tags to change"));
+    }
+    txn.commit()
+}
+
+/// Link a task to another, recording the link on both tasks
+///
+/// Fails if either task doesn't exist, if the link is already recorded, or
+/// if it would make a task (transitively) block itself or be its own
+/// ancestor. The check and the write happen under the write lock, so
+/// concurrent links can't close a cycle between them.
+pub fn link_tasks<S: EventStore + ?Sized>(
+    store: &S,
+    id: &str,
+    rel: Relation,
+    target: &str,
+    by: &str,
+    branch: &str,
+) -> Result<()> {
+    let _lock = store.lock()?;
+    let state = store.current_state()?;
+    let (task, other) = linked_tasks(&state, id, target)?;
+
+    if let Some(cycle) = LinkGraph::new(&state).link_cycle(id, rel, target) {
+        return Err(anyhow!(
+            "Cannot link {} {} {}: it would create a cycle ({})",
+            id,
+            rel,
+            target,
+            cycle.join(" -> ")
+        ));
+    }
+
+    let mut txn = Transaction::new(store);
+    if !has_link(task, rel, target) {
+        txn.push(link_event(id, Payload::Link, rel, target, by, branch));
+    }
+    if let Some(inverse) = rel.inverse() {
+        if !has_link(other, inverse, id) {
+            txn.push(link_event(target, Payload::Link, inverse, id, by, branch));
+        }
+    }
+    if txn.is_empty() {
+        return Err(anyhow!("Task {} already {} {}", id, rel, target));
+    }
+    txn.commit_locked()
+}
+
+/// Remove a link between two tasks from both of them
+pub fn unlink_tasks<S: EventStore + ?Sized>(
+    store: &S,
+    id: &str,
+    rel: Relation,
+    target: &str,
+    by: &str,
+    branch: &str,
+) -> Result<()> {
+    let _lock = store.lock()?;
+    let state = store.current_state()?;
+    let (task, other) = linked_tasks(&state, id, target)?;
+
+    let mut txn = Transaction::new(store);
+    if has_link(task, rel, target) {
+        txn.push(link_event(id, Payload::Unlink, rel, target, by, branch));
+    }
+    if let Some(inverse) = rel.inverse() {
+        if has_link(other, inverse, id) {
+            txn.push(link_event(target, Payload::Unlink, inverse, id, by, branch));
+        }
+    }
+    if txn.is_empty() {
+        return Err(anyhow!("Task {} is not linked: {} {}", id, rel, target));
+    }
+    txn.commit_locked()
+}
+
+fn linked_tasks<'a>(state: &'a State, id: &str, target: &str) -> Result<(&'a Task, &'a Task)> {
+    let find = |id: &str| {
+        state
+            .tasks
+            .get(id)
+            .ok_or_else(|| anyhow!("Task not found: {}", id))
     };
+    Ok((find(id)?, find(target)?))
+}
 
-    write_event(ctx, &event)
+/// A `link` or `unlink` event, depending on `op` (`Payload::Link` or `Payload::Unlink`)
+fn link_event(
+    id: &str,
+    op: fn(LinkPayload) -> Payload,
+    rel: Relation,
+    target: &str,
+    by: &str,
+    branch: &str,
+) -> Event {
+    let d = LinkPayload {
+        rel: rel.as_str().to_string(),
+        target: target.to_string(),
+        ..Default::default()
+    };
+    Event::new(id, op(d), by, branch)
+}
+
+/// Assign the first ready task matching `query` (see `ready_tasks`) to `by`
+///
+/// The task is picked and assigned under the write lock, so concurrent
+/// callers never claim the same task. Returns its ID, or `None` if no task
+/// is ready.
+pub fn claim_next<S: EventStore + ?Sized>(
+    store: &S,
+    query: &TaskQuery,
+    by: &str,
+    branch: &str,
+) -> Result<Option<String>> {
+    let _lock = store.lock()?;
+    let state = store.current_state()?;
+    let Some(task) = ready_tasks(&state)
+        .into_iter()
+        .find(|task| query.matches(task))
+    else {
+        return Ok(None);
+    };
+
+    let payload = Payload::Assign(AssignPayload {
+        to: Some(by.to_string()),
+        ..Default::default()
+    });
+    append_events(store, &[Event::new(&task.id, payload, by, branch)])?;
+    Ok(Some(task.id.clone()))
 }
 
 /// Set a task's stream (or remove from stream if None)
-pub fn set_stream(
-    ctx: &SpoolContext,
+pub fn set_stream<S: EventStore + ?Sized>(
+    store: &S,
     id: &str,
     stream: Option<&str>,
     by: &str,
     branch: &str,
 ) -> Result<()> {
-    let event = Event {
-        v: 1,
-        op: Operation::SetStream,
-        id: id.to_string(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d: serde_json::json!({
-            "stream": stream
-        }),
-    };
+    let payload = Payload::SetStream(SetStreamPayload {
+        stream: stream.map(String::from),
+        ..Default::default()
+    });
 
-    write_event(ctx, &event)
+    write_event(store, &Event::new(id, payload, by, branch))
 }
 
 /// Get the current user (from git config or environment)
@@ -254,8 +658,8 @@ pub fn get_current_user() -> Result<String> {
 }
 
 /// Create a new stream and return its ID
-pub fn create_stream(
-    ctx: &SpoolContext,
+pub fn create_stream<S: EventStore + ?Sized>(
+    store: &S,
     name: &str,
     description: Option<&str>,
     by: &str,
@@ -263,78 +667,47 @@ pub fn create_stream(
 ) -> Result<String> {
     let id = generate_id();
 
-    let mut d = serde_json::json!({
-        "name": name,
+    let payload = Payload::CreateStream(CreateStreamPayload {
+        name: name.to_string(),
+        description: description.map(String::from),
+        ..Default::default()
     });
 
-    if let Some(desc) = description {
-        d["description"] = serde_json::Value::String(desc.to_string());
-    }
-
-    let event = Event {
-        v: 1,
-        op: Operation::CreateStream,
-        id: id.clone(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d,
-    };
-
-    write_event(ctx, &event)?;
+    write_event(store, &Event::new(&id, payload, by, branch))?;
 
     Ok(id)
 }
 
 /// Update a stream's metadata
-pub fn update_stream(
-    ctx: &SpoolContext,
+pub fn update_stream<S: EventStore + ?Sized>(
+    store: &S,
     id: &str,
     name: Option<&str>,
     description: Option<&str>,
     by: &str,
     branch: &str,
 ) -> Result<()> {
-    let mut d = serde_json::Map::new();
-
-    if let Some(n) = name {
-        d.insert("name".to_string(), serde_json::Value::String(n.to_string()));
-    }
-    if let Some(desc) = description {
-        d.insert(
-            "description".to_string(),
-            serde_json::Value::String(desc.to_string()),
-        );
-    }
-
-    if d.is_empty() {
+    if name.is_none() && description.is_none() {
         return Err(anyhow::anyhow!("No fields to update"));
     }
 
-    let event = Event {
-        v: 1,
-        op: Operation::UpdateStream,
-        id: id.to_string(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d: serde_json::Value::Object(d),
-    };
+    let payload = Payload::UpdateStream(UpdateStreamPayload {
+        name: name.map(String::from),
+        description: description.map(String::from),
+        ..Default::default()
+    });
 
-    write_event(ctx, &event)
+    write_event(store, &Event::new(id, payload, by, branch))
 }
 
 /// Delete a stream
-pub fn delete_stream(ctx: &SpoolContext, id: &str, by: &str, branch: &str) -> Result<()> {
-    let event = Event {
-        v: 1,
-        op: Operation::DeleteStream,
-        id: id.to_string(),
-        ts: Utc::now(),
-        by: by.to_string(),
-        branch: branch.to_string(),
-        d: serde_json::json!({}),
-    };
+pub fn delete_stream<S: EventStore + ?Sized>(
+    store: &S,
+    id: &str,
+    by: &str,
+    branch: &str,
+) -> Result<()> {
+    let payload = Payload::DeleteStream(DeleteStreamPayload::default());
 
-    write_event(ctx, &event)
+    write_event(store, &Event::new(id, payload, by, branch))
 }
@@ -115,7 +115,7 @@ fn test_archive_old_completed_archived() {
     let archive_files: Vec<_> = fs::read_dir(spool_dir.join("archive"))
         .unwrap()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().map_or(false, |ext| ext == "jsonl"))
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "jsonl"))
         .collect();
     assert!(!archive_files.is_empty());
 }
@@ -156,7 +156,7 @@ fn test_archive_dry_run() {
     let archive_files: Vec<_> = fs::read_dir(spool_dir.join("archive"))
         .unwrap()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().map_or(false, |ext| ext == "jsonl"))
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "jsonl"))
         .collect();
     assert!(archive_files.is_empty());
 }
@@ -320,3 +320,270 @@ fn test_archive_multiple_tasks_grouped_by_month() {
     assert!(archived.contains(&"task-month1".to_string()));
     assert!(archived.contains(&"task-month2".to_string()));
 }
+
+fn old_completed_task(spool_dir: &std::path::Path, id: &str) -> std::path::PathBuf {
+    let old_date = Utc::now() - Duration::days(60);
+    let events = vec![
+        json!({
+            "v": 1, "op": "create", "id": id,
+            "ts": old_date.to_rfc3339(), "by": "tester", "branch": "main",
+            "d": {"title": "Old Completed Task"}
+        }),
+        json!({
+            "v": 1, "op": "complete", "id": id,
+            "ts": (old_date + Duration::seconds(1)).to_rfc3339(), "by": "tester", "branch": "main",
+            "d": {}
+        }),
+    ];
+    let filename = format!("{}.jsonl", old_date.format("%Y-%m-%d"));
+    write_events(&spool_dir.join("events"), &filename, &events);
+    spool_dir.join("events").join(filename)
+}
+
+#[test]
+fn test_archive_moves_events_out_of_events_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let event_file = old_completed_task(&spool_dir, "old-task");
+    write_events(
+        &spool_dir.join("events"),
+        "2099-01-01.jsonl",
+        &[json!({
+            "v": 1, "op": "create", "id": "live-task",
+            "ts": "2099-01-01T10:00:00Z", "by": "tester", "branch": "main",
+            "d": {"title": "Live"}
+        })],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    spool::archive::archive_tasks(&ctx, 30, false).unwrap();
+
+    // The day's file held only the archived task, so it's gone
+    assert!(!event_file.exists());
+    let live = fs::read_to_string(spool_dir.join("events/2099-01-01.jsonl")).unwrap();
+    assert!(!live.contains("old-task"));
+
+    let archive_file = ctx.get_archive_files().unwrap().pop().unwrap();
+    let archived = ctx.parse_events_from_file(&archive_file).unwrap();
+    assert_eq!(archived.len(), 3);
+    assert_eq!(archived[2].op, spool::event::Operation::Archive);
+
+    let state = spool::state::materialize(&ctx).unwrap();
+    assert!(state.tasks["old-task"].archived.is_some());
+    assert!(state.warnings.is_empty());
+
+    // Running again changes nothing
+    assert!(spool::archive::archive_tasks(&ctx, 30, false)
+        .unwrap()
+        .is_empty());
+    assert_eq!(ctx.parse_events_from_file(&archive_file).unwrap().len(), 3);
+}
+
+#[test]
+fn test_archive_finishes_interrupted_move() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let event_file = old_completed_task(&spool_dir, "old-task");
+
+    let ctx = create_test_context(&spool_dir);
+    spool::archive::archive_tasks(&ctx, 30, false).unwrap();
+
+    // Simulate a crash after copying but before removing the originals
+    let archive_file = ctx.get_archive_files().unwrap().pop().unwrap();
+    let archived = fs::read_to_string(&archive_file).unwrap();
+    let originals: Vec<&str> = archived.lines().take(2).collect();
+    fs::write(&event_file, format!("{}\n", originals.join("\n"))).unwrap();
+    assert_eq!(spool::state::materialize(&ctx).unwrap().tasks.len(), 1);
+
+    spool::archive::archive_tasks(&ctx, 30, false).unwrap();
+    assert!(!event_file.exists());
+}
+
+#[test]
+fn test_unarchive_restores_task() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let event_file = old_completed_task(&spool_dir, "old-task");
+    let ctx = create_test_context(&spool_dir);
+    let original = ctx.parse_events_from_file(&event_file).unwrap();
+
+    spool::archive::archive_tasks(&ctx, 30, false).unwrap();
+
+    let restored = spool::archive::unarchive_task(&ctx, "old-task").unwrap();
+    assert_eq!(restored, 2);
+    let events = ctx.parse_events_from_file(&event_file).unwrap();
+    let ids = |events: &[spool::Event]| events.iter().map(|e| e.event_id()).collect::<Vec<_>>();
+    assert_eq!(ids(&events), ids(&original));
+    assert!(ctx.get_archive_files().unwrap().is_empty());
+
+    let state = spool::state::materialize(&ctx).unwrap();
+    assert!(state.tasks["old-task"].archived.is_none());
+
+    let err = spool::archive::unarchive_task(&ctx, "old-task").unwrap_err();
+    assert!(err.to_string().contains("not archived"));
+}
+
+/// Tasks completed yesterday: one per stream, resolution and tag combination
+fn recently_completed_tasks(spool_dir: &std::path::Path) {
+    let day = Utc::now() - Duration::days(1);
+    let ts = day.to_rfc3339();
+    let mut events = vec![json!({
+        "v": 1, "op": "create_stream", "id": "stream-1",
+        "ts": ts, "by": "tester", "branch": "main",
+        "d": {"name": "Backend"}
+    })];
+    let tasks = [
+        (
+            "in-stream",
+            json!({"title": "A", "stream": "stream-1"}),
+            "done",
+        ),
+        ("wontfix", json!({"title": "B"}), "wontfix"),
+        (
+            "tagged",
+            json!({"title": "C", "tags": ["spike", "ui"]}),
+            "done",
+        ),
+        ("other", json!({"title": "D"}), "done"),
+    ];
+    for (id, create, resolution) in tasks {
+        events.push(json!({
+            "v": 1, "op": "create", "id": id,
+            "ts": ts, "by": "tester", "branch": "main", "d": create
+        }));
+        events.push(json!({
+            "v": 1, "op": "complete", "id": id,
+            "ts": ts, "by": "tester", "branch": "main",
+            "d": {"resolution": resolution}
+        }));
+    }
+    write_events(
+        &spool_dir.join("events"),
+        &format!("{}.jsonl", day.format("%Y-%m-%d")),
+        &events,
+    );
+}
+
+#[test]
+fn test_archive_matching_by_stream_resolution_and_tag() {
+    use spool::archive::{archive_matching, ArchiveRule};
+
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    recently_completed_tasks(&spool_dir);
+    let ctx = create_test_context(&spool_dir);
+
+    let by_stream = ArchiveRule {
+        streams: vec!["backend".to_string()],
+        ..Default::default()
+    };
+    let by_resolution = ArchiveRule {
+        resolutions: vec!["wontfix".to_string()],
+        ..Default::default()
+    };
+    let by_tag = ArchiveRule {
+        tags: vec!["spike".to_string()],
+        ..Default::default()
+    };
+
+    for (rule, expected) in [
+        (&by_stream, "in-stream"),
+        (&by_resolution, "wontfix"),
+        (&by_tag, "tagged"),
+    ] {
+        let selected = archive_matching(&ctx, std::slice::from_ref(rule), true).unwrap();
+        assert_eq!(selected, vec![expected.to_string()]);
+    }
+
+    // Criteria within a rule must all match, including age
+    let too_recent = ArchiveRule {
+        days: 30,
+        ..by_resolution.clone()
+    };
+    assert!(archive_matching(&ctx, &[too_recent], true)
+        .unwrap()
+        .is_empty());
+
+    // A task is archived if any rule selects it
+    let mut archived = archive_matching(&ctx, &[by_stream, by_tag], false).unwrap();
+    archived.sort();
+    assert_eq!(archived, vec!["in-stream", "tagged"]);
+    let state = spool::state::materialize(&ctx).unwrap();
+    assert!(state.tasks["in-stream"].archived.is_some());
+    assert!(state.tasks["other"].archived.is_none());
+}
+
+#[test]
+fn test_rebuild_applies_retention_policy() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    recently_completed_tasks(&spool_dir);
+    let ctx = create_test_context(&spool_dir);
+
+    // No policy configured: nothing happens
+    assert!(spool::archive::apply_retention(&ctx).unwrap().is_empty());
+
+    fs::write(
+        spool_dir.join("config.json"),
+        r#"{"retention": [{"days": 90}, {"resolutions": ["wontfix"]}]}"#,
+    )
+    .unwrap();
+    spool::state::rebuild(&ctx).unwrap();
+
+    let state = spool::state::materialize(&ctx).unwrap();
+    let archived: Vec<&str> = state
+        .tasks
+        .values()
+        .filter(|t| t.archived.is_some())
+        .map(|t| t.id.as_str())
+        .collect();
+    assert_eq!(archived, vec!["wontfix"]);
+    assert!(spool::archive::apply_retention(&ctx).unwrap().is_empty());
+}
+
+#[test]
+fn test_invalid_config_is_reported() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    fs::write(spool_dir.join("config.json"), r#"{"retention": {}}"#).unwrap();
+    let ctx = create_test_context(&spool_dir);
+
+    let err = spool::archive::apply_retention(&ctx).unwrap_err();
+    assert!(err.to_string().contains("Invalid config"));
+}
+
+#[test]
+fn test_archived_task_keeps_its_version() {
+    use spool::concurrency::{get_task_version, write_event_with_version, WriteResult};
+
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    old_completed_task(&spool_dir, "old-task");
+    let ctx = create_test_context(&spool_dir);
+    spool::archive::archive_tasks(&ctx, 30, false).unwrap();
+
+    let archived = ctx.parse_events_from_file(&ctx.get_archive_files().unwrap()[0]);
+    let marker = archived.unwrap().pop().unwrap();
+    let version = get_task_version(&ctx, "old-task").unwrap().unwrap();
+    assert_eq!(version.last_event_hash, marker.event_id());
+
+    // The ID stays taken
+    let create: spool::Event = serde_json::from_value(json!({
+        "v": 1, "op": "create", "id": "old-task",
+        "ts": Utc::now().to_rfc3339(), "by": "tester", "branch": "main",
+        "d": {"title": "Reused"}
+    }))
+    .unwrap();
+    let result = write_event_with_version(&ctx, &create, None).unwrap();
+    assert!(matches!(result, WriteResult::Error(e) if e.contains("already exists")));
+
+    // A write guarded by the archived version goes through
+    let reopen: spool::Event = serde_json::from_value(json!({
+        "v": 1, "op": "reopen", "id": "old-task",
+        "ts": Utc::now().to_rfc3339(), "by": "tester", "branch": "main",
+        "d": {}
+    }))
+    .unwrap();
+    let result = write_event_with_version(&ctx, &reopen, Some(&version)).unwrap();
+    assert!(matches!(result, WriteResult::Success));
+}
@@ -1,5 +1,6 @@
 use clap::Parser;
 use spool::cli::{Cli, Commands, OutputFormat, StreamCommands};
+use spool::deps::Relation;
 
 #[test]
 fn test_output_format_from_str() {
@@ -15,7 +16,31 @@ fn test_output_format_from_str() {
 #[test]
 fn test_cli_parse_init() {
     let cli = Cli::parse_from(["spool", "init"]);
-    assert!(matches!(cli.command, Commands::Init));
+    assert!(matches!(
+        cli.command,
+        Commands::Init {
+            git: false,
+            events_ref: false
+        }
+    ));
+}
+
+#[test]
+fn test_cli_parse_init_git() {
+    let cli = Cli::parse_from(["spool", "init", "--git"]);
+    assert!(matches!(cli.command, Commands::Init { git: true, .. }));
+}
+
+#[test]
+fn test_cli_parse_init_events_ref() {
+    let cli = Cli::parse_from(["spool", "init", "--events-ref"]);
+    assert!(matches!(
+        cli.command,
+        Commands::Init {
+            git: false,
+            events_ref: true
+        }
+    ));
 }
 
 #[test]
@@ -30,6 +55,8 @@ fn test_cli_parse_list_defaults() {
         stream,
         stream_name,
         no_stream,
+        archived,
+        include_archived,
         format,
     } = cli.command
     {
@@ -40,6 +67,8 @@ fn test_cli_parse_list_defaults() {
         assert!(stream.is_none());
         assert!(stream_name.is_none());
         assert!(!no_stream);
+        assert!(!archived);
+        assert!(!include_archived);
         assert_eq!(format, "table");
     } else {
         panic!("Expected List command");
@@ -152,12 +181,31 @@ fn test_cli_parse_rebuild() {
     assert!(matches!(cli.command, Commands::Rebuild));
 }
 
+#[test]
+fn test_cli_parse_compact() {
+    let cli = Cli::parse_from(["spool", "compact"]);
+    assert!(matches!(cli.command, Commands::Compact));
+}
+
+#[test]
+fn test_cli_parse_sync() {
+    let cli = Cli::parse_from(["spool", "sync"]);
+    if let Commands::Sync { remote } = cli.command {
+        assert_eq!(remote, "origin");
+    } else {
+        panic!("Expected Sync command");
+    }
+
+    let cli = Cli::parse_from(["spool", "sync", "--remote", "upstream"]);
+    assert!(matches!(cli.command, Commands::Sync { remote } if remote == "upstream"));
+}
+
 #[test]
 fn test_cli_parse_archive_defaults() {
     let cli = Cli::parse_from(["spool", "archive"]);
 
-    if let Commands::Archive { days, dry_run } = cli.command {
-        assert_eq!(days, 30);
+    if let Commands::Archive { days, dry_run, .. } = cli.command {
+        assert_eq!(days, None);
         assert!(!dry_run);
     } else {
         panic!("Expected Archive command");
@@ -168,8 +216,8 @@ fn test_cli_parse_archive_defaults() {
 fn test_cli_parse_archive_with_options() {
     let cli = Cli::parse_from(["spool", "archive", "--days", "60", "--dry-run"]);
 
-    if let Commands::Archive { days, dry_run } = cli.command {
-        assert_eq!(days, 60);
+    if let Commands::Archive { days, dry_run, .. } = cli.command {
+        assert_eq!(days, Some(60));
         assert!(dry_run);
     } else {
         panic!("Expected Archive command");
@@ -180,20 +228,69 @@ fn test_cli_parse_archive_with_options() {
 fn test_cli_parse_archive_short_flag() {
     let cli = Cli::parse_from(["spool", "archive", "-d", "7"]);
 
-    if let Commands::Archive { days, dry_run } = cli.command {
-        assert_eq!(days, 7);
+    if let Commands::Archive { days, dry_run, .. } = cli.command {
+        assert_eq!(days, Some(7));
         assert!(!dry_run);
     } else {
         panic!("Expected Archive command");
     }
 }
 
+#[test]
+fn test_cli_parse_archive_filters() {
+    let cli = Cli::parse_from([
+        "spool",
+        "archive",
+        "--days",
+        "0",
+        "--stream",
+        "Backend",
+        "--resolution",
+        "wontfix",
+        "--resolution",
+        "dupe",
+        "--tag",
+        "spike",
+    ]);
+
+    if let Commands::Archive {
+        days,
+        streams,
+        resolutions,
+        tags,
+        policy,
+        ..
+    } = cli.command
+    {
+        assert_eq!(days, Some(0));
+        assert_eq!(streams, vec!["Backend"]);
+        assert_eq!(resolutions, vec!["wontfix", "dupe"]);
+        assert_eq!(tags, vec!["spike"]);
+        assert!(!policy);
+    } else {
+        panic!("Expected Archive command");
+    }
+}
+
+#[test]
+fn test_cli_parse_archive_policy_conflicts_with_filters() {
+    let cli = Cli::parse_from(["spool", "archive", "--policy"]);
+    assert!(matches!(
+        cli.command,
+        Commands::Archive { policy: true, .. }
+    ));
+
+    let result = Cli::try_parse_from(["spool", "archive", "--policy", "--tag", "bug"]);
+    assert!(result.is_err());
+}
+
 #[test]
 fn test_cli_parse_validate() {
     let cli = Cli::parse_from(["spool", "validate"]);
 
-    if let Commands::Validate { strict } = cli.command {
+    if let Commands::Validate { strict, dedupe } = cli.command {
         assert!(!strict);
+        assert!(!dedupe);
     } else {
         panic!("Expected Validate command");
     }
@@ -203,13 +300,52 @@ fn test_cli_parse_validate() {
 fn test_cli_parse_validate_strict() {
     let cli = Cli::parse_from(["spool", "validate", "--strict"]);
 
-    if let Commands::Validate { strict } = cli.command {
+    if let Commands::Validate { strict, .. } = cli.command {
         assert!(strict);
     } else {
         panic!("Expected Validate command");
     }
 }
 
+#[test]
+fn test_cli_parse_list_archived_conflicts_with_include_archived() {
+    let result = Cli::try_parse_from(["spool", "list", "--archived", "--include-archived"]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_cli_parse_unarchive() {
+    let cli = Cli::parse_from(["spool", "unarchive", "task-001"]);
+
+    if let Commands::Unarchive { id } = cli.command {
+        assert_eq!(id, "task-001");
+    } else {
+        panic!("Expected Unarchive command");
+    }
+}
+
+#[test]
+fn test_cli_parse_unlock_force() {
+    let cli = Cli::parse_from(["spool", "unlock", "--force"]);
+
+    if let Commands::Unlock { force } = cli.command {
+        assert!(force);
+    } else {
+        panic!("Expected Unlock command");
+    }
+}
+
+#[test]
+fn test_cli_parse_validate_dedupe() {
+    let cli = Cli::parse_from(["spool", "validate", "--dedupe"]);
+
+    if let Commands::Validate { dedupe, .. } = cli.command {
+        assert!(dedupe);
+    } else {
+        panic!("Expected Validate command");
+    }
+}
+
 #[test]
 fn test_output_format_equality() {
     assert!(OutputFormat::Table == OutputFormat::Table);
@@ -229,7 +365,7 @@ fn test_output_format_clone() {
 fn test_cli_parse_complete_defaults() {
     let cli = Cli::parse_from(["spool", "complete", "task-123"]);
 
-    if let Commands::Complete { id, resolution } = cli.command {
+    if let Commands::Complete { id, resolution, .. } = cli.command {
         assert_eq!(id, "task-123");
         assert_eq!(resolution, "done");
     } else {
@@ -241,7 +377,7 @@ fn test_cli_parse_complete_defaults() {
 fn test_cli_parse_complete_with_resolution() {
     let cli = Cli::parse_from(["spool", "complete", "task-456", "--resolution", "wontfix"]);
 
-    if let Commands::Complete { id, resolution } = cli.command {
+    if let Commands::Complete { id, resolution, .. } = cli.command {
         assert_eq!(id, "task-456");
         assert_eq!(resolution, "wontfix");
     } else {
@@ -253,7 +389,7 @@ fn test_cli_parse_complete_with_resolution() {
 fn test_cli_parse_complete_short_flag() {
     let cli = Cli::parse_from(["spool", "complete", "task-789", "-r", "duplicate"]);
 
-    if let Commands::Complete { id, resolution } = cli.command {
+    if let Commands::Complete { id, resolution, .. } = cli.command {
         assert_eq!(id, "task-789");
         assert_eq!(resolution, "duplicate");
     } else {
@@ -265,7 +401,7 @@ fn test_cli_parse_complete_short_flag() {
 fn test_cli_parse_reopen() {
     let cli = Cli::parse_from(["spool", "reopen", "task-abc"]);
 
-    if let Commands::Reopen { id } = cli.command {
+    if let Commands::Reopen { id, .. } = cli.command {
         assert_eq!(id, "task-abc");
     } else {
         panic!("Expected Reopen command");
@@ -281,7 +417,7 @@ fn test_cli_parse_update_with_title() {
         title,
         description,
         priority,
-        stream: _,
+        ..
     } = cli.command
     {
         assert_eq!(id, "task-123");
@@ -308,7 +444,7 @@ fn test_cli_parse_update_with_description() {
         title,
         description,
         priority,
-        stream: _,
+        ..
     } = cli.command
     {
         assert_eq!(id, "task-456");
@@ -329,7 +465,7 @@ fn test_cli_parse_update_with_priority() {
         title,
         description,
         priority,
-        stream: _,
+        ..
     } = cli.command
     {
         assert_eq!(id, "task-789");
@@ -360,7 +496,7 @@ fn test_cli_parse_update_all_fields() {
         title,
         description,
         priority,
-        stream: _,
+        ..
     } = cli.command
     {
         assert_eq!(id, "task-full");
@@ -391,7 +527,7 @@ fn test_cli_parse_update_short_flags() {
         title,
         description,
         priority,
-        stream: _,
+        ..
     } = cli.command
     {
         assert_eq!(id, "task-short");
@@ -412,7 +548,7 @@ fn test_cli_parse_update_no_options() {
         title,
         description,
         priority,
-        stream: _,
+        ..
     } = cli.command
     {
         assert_eq!(id, "task-empty");
@@ -489,7 +625,7 @@ fn test_cli_parse_add_with_all_options() {
 fn test_cli_parse_assign() {
     let cli = Cli::parse_from(["spool", "assign", "task-123", "@bob"]);
 
-    if let Commands::Assign { id, assignee } = cli.command {
+    if let Commands::Assign { id, assignee, .. } = cli.command {
         assert_eq!(id, "task-123");
         assert_eq!(assignee, "@bob");
     } else {
@@ -501,7 +637,7 @@ fn test_cli_parse_assign() {
 fn test_cli_parse_claim() {
     let cli = Cli::parse_from(["spool", "claim", "task-456"]);
 
-    if let Commands::Claim { id } = cli.command {
+    if let Commands::Claim { id, .. } = cli.command {
         assert_eq!(id, "task-456");
     } else {
         panic!("Expected Claim command");
@@ -512,13 +648,187 @@ fn test_cli_parse_claim() {
 fn test_cli_parse_free() {
     let cli = Cli::parse_from(["spool", "free", "task-789"]);
 
-    if let Commands::Free { id } = cli.command {
+    if let Commands::Free { id, .. } = cli.command {
         assert_eq!(id, "task-789");
     } else {
         panic!("Expected Free command");
     }
 }
 
+#[test]
+fn test_cli_parse_comment() {
+    let cli = Cli::parse_from([
+        "spool",
+        "comment",
+        "task-1",
+        "Looks good",
+        "--ref",
+        "abc123",
+    ]);
+
+    if let Commands::Comment {
+        id,
+        body,
+        reference,
+        edit,
+        delete,
+    } = cli.command
+    {
+        assert_eq!(id, "task-1");
+        assert_eq!(body.as_deref(), Some("Looks good"));
+        assert_eq!(reference.as_deref(), Some("abc123"));
+        assert!(edit.is_none() && delete.is_none());
+    } else {
+        panic!("Expected Comment command");
+    }
+}
+
+#[test]
+fn test_cli_parse_comment_without_body() {
+    let cli = Cli::parse_from(["spool", "comment", "task-1"]);
+    assert!(matches!(cli.command, Commands::Comment { body: None, .. }));
+}
+
+#[test]
+fn test_cli_parse_comment_edit_and_delete() {
+    let cli = Cli::parse_from(["spool", "comment", "task-1", "--edit", "1a2b3c4d", "Fixed"]);
+    if let Commands::Comment { edit, body, .. } = cli.command {
+        assert_eq!(edit.as_deref(), Some("1a2b3c4d"));
+        assert_eq!(body.as_deref(), Some("Fixed"));
+    } else {
+        panic!("Expected Comment command");
+    }
+
+    let cli = Cli::parse_from(["spool", "comment", "task-1", "--delete", "1a2b3c4d"]);
+    assert!(matches!(
+        cli.command,
+        Commands::Comment {
+            delete: Some(_),
+            ..
+        }
+    ));
+
+    // Deleting takes no text, and a comment is either edited or deleted
+    for args in [
+        &["spool", "comment", "task-1", "--delete", "1a2b", "text"][..],
+        &[
+            "spool", "comment", "task-1", "--delete", "1a2b", "--edit", "1a2b",
+        ],
+    ] {
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+}
+
+#[test]
+fn test_cli_parse_link() {
+    let cli = Cli::parse_from(["spool", "link", "task-1", "--blocks", "task-2"]);
+    if let Commands::Link { id, link } = cli.command {
+        assert_eq!(id, "task-1");
+        assert_eq!(link.link(), (Relation::Blocks, "task-2"));
+    } else {
+        panic!("Expected Link command");
+    }
+
+    let cli = Cli::parse_from(["spool", "unlink", "task-1", "--parent", "task-3"]);
+    if let Commands::Unlink { link, .. } = cli.command {
+        assert_eq!(link.link(), (Relation::Parent, "task-3"));
+    } else {
+        panic!("Expected Unlink command");
+    }
+
+    // Exactly one relation
+    assert!(Cli::try_parse_from(["spool", "link", "task-1"]).is_err());
+    assert!(Cli::try_parse_from([
+        "spool", "link", "task-1", "--blocks", "task-2", "--parent", "task-3"
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_cli_parse_deps() {
+    let cli = Cli::parse_from(["spool", "deps", "task-1", "--reverse"]);
+    assert!(matches!(cli.command, Commands::Deps { reverse: true, .. }));
+}
+
+#[test]
+fn test_cli_parse_ready() {
+    let cli = Cli::parse_from(["spool", "ready", "-t", "bug", "-n", "5", "-f", "ids"]);
+    if let Commands::Ready {
+        filter,
+        limit,
+        format,
+    } = cli.command
+    {
+        assert_eq!(filter.query().tag.as_deref(), Some("bug"));
+        assert_eq!(limit, Some(5));
+        assert_eq!(format, "ids");
+    } else {
+        panic!("Expected Ready command");
+    }
+}
+
+#[test]
+fn test_cli_parse_next() {
+    let cli = Cli::parse_from(["spool", "next", "--claim", "--stream", "s-1"]);
+    if let Commands::Next { filter, claim } = cli.command {
+        assert!(claim);
+        assert_eq!(filter.query().stream.as_deref(), Some("s-1"));
+    } else {
+        panic!("Expected Next command");
+    }
+}
+
+#[test]
+fn test_cli_parse_tree() {
+    let cli = Cli::parse_from(["spool", "tree"]);
+    assert!(matches!(cli.command, Commands::Tree { id: None }));
+
+    let cli = Cli::parse_from(["spool", "tree", "task-1"]);
+    if let Commands::Tree { id } = cli.command {
+        assert_eq!(id.as_deref(), Some("task-1"));
+    } else {
+        panic!("Expected Tree command");
+    }
+}
+
+#[test]
+fn test_cli_parse_complete_force() {
+    let cli = Cli::parse_from(["spool", "complete", "task-1", "--force"]);
+    assert!(matches!(
+        cli.command,
+        Commands::Complete { force: true, .. }
+    ));
+
+    let cli = Cli::parse_from(["spool", "complete", "task-1"]);
+    assert!(matches!(
+        cli.command,
+        Commands::Complete { force: false, .. }
+    ));
+}
+
+#[test]
+fn test_cli_parse_tag() {
+    let cli = Cli::parse_from(["spool", "tag", "task-1", "+bug", "-wip", "docs"]);
+    if let Commands::Tag { id, changes } = cli.command {
+        assert_eq!(id, "task-1");
+        assert_eq!(changes, vec!["+bug", "-wip", "docs"]);
+    } else {
+        panic!("Expected Tag command");
+    }
+
+    assert!(Cli::try_parse_from(["spool", "tag", "task-1"]).is_err());
+}
+
+#[test]
+fn test_cli_parse_tags() {
+    let cli = Cli::parse_from(["spool", "tags", "-f", "json"]);
+    if let Commands::Tags { format } = cli.command {
+        assert_eq!(format, "json");
+    } else {
+        panic!("Expected Tags command");
+    }
+}
+
 // Stream command tests
 
 #[test]
@@ -689,3 +999,32 @@ fn test_cli_parse_stream_delete() {
         panic!("Expected Stream command");
     }
 }
+
+#[test]
+fn test_cli_parse_conflicts_resolve() {
+    let cli = Cli::parse_from([
+        "spool",
+        "conflicts",
+        "task-123",
+        "--resolve",
+        "title",
+        "--keep",
+        "3f2a",
+    ]);
+
+    if let Commands::Conflicts {
+        id, resolve, keep, ..
+    } = cli.command
+    {
+        assert_eq!(id.as_deref(), Some("task-123"));
+        assert_eq!(resolve.as_deref(), Some("title"));
+        assert_eq!(keep.as_deref(), Some("3f2a"));
+    } else {
+        panic!("Expected Conflicts command");
+    }
+}
+
+#[test]
+fn test_cli_parse_conflicts_resolve_requires_id() {
+    assert!(Cli::try_parse_from(["spool", "conflicts", "--resolve", "title"]).is_err());
+}
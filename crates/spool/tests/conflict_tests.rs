@@ -0,0 +1,287 @@
+use serde_json::{json, Value};
+use spool::context::SpoolContext;
+use spool::event::Event;
+use spool::writer::{create_task, update_task, CreateTaskParams};
+use std::fs;
+use std::io::Write;
+use tempfile::TempDir;
+
+fn setup_spool_dir(temp_dir: &TempDir) -> std::path::PathBuf {
+    let spool_dir = temp_dir.path().join(".spool");
+    fs::create_dir_all(spool_dir.join("events")).unwrap();
+    fs::create_dir_all(spool_dir.join("archive")).unwrap();
+    spool_dir
+}
+
+fn write_events(dir: &std::path::Path, filename: &str, events: &[Event]) {
+    let mut file = fs::File::create(dir.join(filename)).unwrap();
+    for event in events {
+        writeln!(file, "{}", serde_json::to_string(event).unwrap()).unwrap();
+    }
+}
+
+fn event(op: &str, ts: &str, by: &str, branch: &str, prev: &[&Event], d: Value) -> Event {
+    serde_json::from_value(json!({
+        "v": 1, "op": op, "id": "task-1", "ts": ts, "by": by, "branch": branch,
+        "prev": prev.iter().map(|e| e.event_id()).collect::<Vec<_>>(),
+        "d": d
+    }))
+    .unwrap()
+}
+
+fn create() -> Event {
+    event(
+        "create",
+        "2024-01-15T10:00:00Z",
+        "@alice",
+        "main",
+        &[],
+        json!({"title": "Original"}),
+    )
+}
+
+fn materialize(events: &[Event]) -> spool::state::State {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", events);
+    spool::state::materialize(&SpoolContext::new(spool_dir)).unwrap()
+}
+
+#[test]
+fn test_concurrent_updates_conflict() {
+    let create = create();
+    let ours = event(
+        "update",
+        "2024-01-15T11:00:00Z",
+        "@alice",
+        "main",
+        &[&create],
+        json!({"title": "Ours"}),
+    );
+    let theirs = event(
+        "update",
+        "2024-01-15T12:00:00Z",
+        "@bob",
+        "feature",
+        &[&create],
+        json!({"title": "Theirs", "priority": "p1"}),
+    );
+
+    let state = materialize(&[create, ours.clone(), theirs.clone()]);
+
+    assert_eq!(state.tasks["task-1"].title, "Theirs");
+    assert_eq!(state.conflicts.len(), 1);
+    let conflict = &state.conflicts[0];
+    assert_eq!(conflict.task, "task-1");
+    assert_eq!(conflict.field, "title");
+    assert_eq!(conflict.writes.len(), 2);
+    assert_eq!(conflict.writes[0].event, ours.event_id());
+    assert_eq!(conflict.writes[0].value, json!("Ours"));
+    assert_eq!(conflict.writes[1].event, theirs.event_id());
+    assert_eq!(conflict.writes[1].branch, "feature");
+}
+
+#[test]
+fn test_sequential_updates_do_not_conflict() {
+    let create = create();
+    let first = event(
+        "update",
+        "2024-01-15T11:00:00Z",
+        "@alice",
+        "main",
+        &[&create],
+        json!({"title": "First"}),
+    );
+    let second = event(
+        "update",
+        "2024-01-15T12:00:00Z",
+        "@bob",
+        "feature",
+        &[&first],
+        json!({"title": "Second"}),
+    );
+
+    let state = materialize(&[create, first, second]);
+
+    assert_eq!(state.tasks["task-1"].title, "Second");
+    assert!(state.conflicts.is_empty());
+}
+
+#[test]
+fn test_events_without_ancestry_do_not_conflict() {
+    let create = create();
+    let first = event(
+        "update",
+        "2024-01-15T11:00:00Z",
+        "@alice",
+        "main",
+        &[],
+        json!({"title": "First"}),
+    );
+    let second = event(
+        "update",
+        "2024-01-15T12:00:00Z",
+        "@bob",
+        "feature",
+        &[],
+        json!({"title": "Second"}),
+    );
+
+    let state = materialize(&[create, first, second]);
+    assert!(state.conflicts.is_empty());
+}
+
+#[test]
+fn test_complete_and_reopen_on_different_branches_conflict() {
+    let create = create();
+    let complete = event(
+        "complete",
+        "2024-01-15T11:00:00Z",
+        "@alice",
+        "main",
+        &[&create],
+        json!({"resolution": "done"}),
+    );
+    let reopen_base = event(
+        "complete",
+        "2024-01-15T10:30:00Z",
+        "@bob",
+        "feature",
+        &[&create],
+        json!({}),
+    );
+    let reopen = event(
+        "reopen",
+        "2024-01-15T12:00:00Z",
+        "@bob",
+        "feature",
+        &[&reopen_base],
+        json!({}),
+    );
+
+    let state = materialize(&[create, reopen_base, complete, reopen]);
+
+    assert_eq!(state.conflicts.len(), 1);
+    assert_eq!(state.conflicts[0].field, "status");
+    let values: Vec<&Value> = state.conflicts[0].writes.iter().map(|w| &w.value).collect();
+    assert_eq!(
+        values,
+        vec![
+            &json!({"status": "complete", "resolution": "done"}),
+            &json!({"status": "open"})
+        ]
+    );
+}
+
+#[test]
+fn test_write_that_saw_both_sides_resolves_conflict() {
+    let create = create();
+    let ours = event(
+        "update",
+        "2024-01-15T11:00:00Z",
+        "@alice",
+        "main",
+        &[&create],
+        json!({"title": "Ours"}),
+    );
+    let theirs = event(
+        "update",
+        "2024-01-15T12:00:00Z",
+        "@bob",
+        "feature",
+        &[&create],
+        json!({"title": "Theirs"}),
+    );
+    let resolution = event(
+        "update",
+        "2024-01-15T13:00:00Z",
+        "@alice",
+        "main",
+        &[&ours, &theirs],
+        json!({"title": "Ours"}),
+    );
+
+    let state = materialize(&[create, ours, theirs, resolution]);
+
+    assert_eq!(state.tasks["task-1"].title, "Ours");
+    assert!(state.conflicts.is_empty());
+}
+
+#[test]
+fn test_cached_state_resolves_conflict_from_appended_write() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = SpoolContext::new(spool_dir.clone());
+
+    // A long chain on main, and a branch that forked at the start
+    let create = create();
+    let mut events = vec![create.clone()];
+    for i in 0..50 {
+        let prev = events.last().unwrap().clone();
+        let ts = format!("2024-01-15T11:{:02}:00Z", i);
+        events.push(event(
+            "update",
+            &ts,
+            "@alice",
+            "main",
+            &[&prev],
+            json!({"title": format!("Ours {}", i)}),
+        ));
+    }
+    let ours = events.last().unwrap().clone();
+    let theirs = event(
+        "update",
+        "2024-01-15T12:00:00Z",
+        "@bob",
+        "feature",
+        &[&create],
+        json!({"title": "Theirs"}),
+    );
+    events.push(theirs.clone());
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &events);
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.conflicts.len(), 1);
+
+    // Caught up from the cache written above
+    let resolution = event(
+        "update",
+        "2024-01-15T13:00:00Z",
+        "@alice",
+        "main",
+        &[&ours, &theirs],
+        json!({"title": "Ours"}),
+    );
+    events.push(resolution);
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &events);
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.tasks["task-1"].title, "Ours");
+    assert!(state.conflicts.is_empty());
+}
+
+#[test]
+fn test_writer_records_task_frontier() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = SpoolContext::new(spool_dir);
+
+    let id = create_task(
+        &ctx,
+        CreateTaskParams {
+            title: "Tracked",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+    update_task(&ctx, &id, Some("Renamed"), None, None, "@tester", "main").unwrap();
+
+    let files = ctx.get_event_files().unwrap();
+    let events = ctx.parse_events_from_file(&files[0]).unwrap();
+    assert!(events[0].prev.is_empty());
+    assert_eq!(events[1].prev, vec![events[0].event_id()]);
+
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.frontier(&id), vec![events[1].event_id()]);
+    assert!(state.conflicts.is_empty());
+}
@@ -177,7 +177,7 @@ fn test_parse_events_from_file_skips_empty_lines() {
     let event_file = spool_dir.join("events").join("2024-01-15.jsonl");
     let mut file = fs::File::create(&event_file).unwrap();
     writeln!(file, r#"{{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{{"title":"First"}}}}"#).unwrap();
-    writeln!(file, "").unwrap(); // Empty line
+    writeln!(file).unwrap(); // Empty line
     writeln!(file, "   ").unwrap(); // Whitespace line
     writeln!(file, r#"{{"v":1,"op":"create","id":"task-002","ts":"2024-01-15T11:00:00Z","by":"@tester","branch":"main","d":{{"title":"Second"}}}}"#).unwrap();
 
@@ -198,6 +198,33 @@ fn test_parse_events_from_file_invalid_json_errors() {
     assert!(result.is_err());
 }
 
+#[test]
+fn test_read_events_lenient_skips_bad_lines() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = SpoolContext::new(spool_dir.clone());
+
+    let event_file = spool_dir.join("events").join("2024-01-15.jsonl");
+    fs::write(
+        &event_file,
+        concat!(
+            r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Kept"}}"#,
+            "\n",
+            r#"{"v":1,"op":"upd"#,
+            "\n",
+            r#"{"v":1,"op":"complete","id":"task-001","ts":"2024-01-15T11:00:00Z","by":"@tester","branch":"main","d":{}}"#,
+            "\n",
+        ),
+    )
+    .unwrap();
+
+    let (events, bad) = ctx.read_events_lenient(&event_file).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(bad.len(), 1);
+    assert_eq!(bad[0].line, 2);
+    assert_eq!(bad[0].text, r#"{"v":1,"op":"upd"#);
+}
+
 #[test]
 fn test_parse_events_from_file_missing_field_errors() {
     let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,231 @@
+use spool::concurrency::FileLock;
+use spool::context::SpoolContext;
+use spool::deps::{priority_rank, ready_tasks, Hierarchy, LinkGraph, Progress, Relation};
+use spool::query::TaskQuery;
+use spool::state::materialize;
+use spool::writer::{
+    assign_task, claim_next, complete_task, create_task, link_tasks, unlink_tasks, CreateTaskParams,
+};
+use std::fs;
+use tempfile::TempDir;
+
+fn setup(temp_dir: &TempDir) -> SpoolContext {
+    let spool_dir = temp_dir.path().join(".spool");
+    fs::create_dir_all(spool_dir.join("events")).unwrap();
+    fs::create_dir_all(spool_dir.join("archive")).unwrap();
+    SpoolContext::new(spool_dir)
+}
+
+fn add(ctx: &SpoolContext, title: &str) -> String {
+    add_with_priority(ctx, title, None)
+}
+
+fn add_with_priority(ctx: &SpoolContext, title: &str, priority: Option<&str>) -> String {
+    create_task(
+        ctx,
+        CreateTaskParams {
+            title,
+            priority,
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap()
+}
+
+fn link(ctx: &SpoolContext, id: &str, rel: Relation, target: &str) -> anyhow::Result<()> {
+    link_tasks(ctx, id, rel, target, "@tester", "main")
+}
+
+#[test]
+fn test_link_records_both_sides() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let a = add(&ctx, "A");
+    let b = add(&ctx, "B");
+    let c = add(&ctx, "C");
+
+    link(&ctx, &a, Relation::Blocks, &b).unwrap();
+    link(&ctx, &c, Relation::BlockedBy, &b).unwrap();
+    link(&ctx, &c, Relation::Parent, &a).unwrap();
+
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks[&a].blocks, vec![b.clone()]);
+    assert_eq!(state.tasks[&b].blocked_by, vec![a.clone()]);
+    assert_eq!(state.tasks[&b].blocks, vec![c.clone()]);
+    assert_eq!(state.tasks[&c].blocked_by, vec![b.clone()]);
+    assert_eq!(state.tasks[&c].parent.as_deref(), Some(a.as_str()));
+
+    let graph = LinkGraph::new(&state);
+    assert_eq!(graph.blockers(&c), vec![b.as_str()]);
+    assert_eq!(graph.dependents(&a), vec![b.as_str()]);
+    assert_eq!(Hierarchy::new(state.tasks.values()).children(&a), vec![c]);
+
+    // Both sides are written as one transaction
+    let content = fs::read_to_string(&ctx.get_event_files().unwrap()[0]).unwrap();
+    let links: Vec<&str> = content
+        .lines()
+        .filter(|l| l.contains("\"op\":\"link\""))
+        .collect();
+    assert_eq!(links.len(), 5);
+    assert!(links[..2].iter().all(|l| l.contains("\"txn\"")));
+
+    let err = link(&ctx, &a, Relation::Blocks, &b).unwrap_err();
+    assert!(err.to_string().contains("already blocks"));
+    assert!(link(&ctx, &a, Relation::Blocks, "missing").is_err());
+}
+
+#[test]
+fn test_link_rejects_cycles() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let a = add(&ctx, "A");
+    let b = add(&ctx, "B");
+    let c = add(&ctx, "C");
+    link(&ctx, &a, Relation::Blocks, &b).unwrap();
+    link(&ctx, &b, Relation::Blocks, &c).unwrap();
+    link(&ctx, &b, Relation::Parent, &a).unwrap();
+
+    let err = link(&ctx, &c, Relation::Blocks, &a).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains(&format!("{} -> {} -> {} -> {}", c, a, b, c)));
+    assert!(link(&ctx, &a, Relation::BlockedBy, &c).is_err());
+    assert!(link(&ctx, &a, Relation::Blocks, &a).is_err());
+    assert!(link(&ctx, &a, Relation::Parent, &b).is_err());
+
+    // Not a cycle: a already leads to c directly and through b
+    link(&ctx, &a, Relation::Blocks, &c).unwrap();
+    let state = materialize(&ctx).unwrap();
+    assert!(LinkGraph::new(&state).cycles().is_empty());
+}
+
+#[test]
+fn test_concurrent_links_cannot_close_a_cycle() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let a = add(&ctx, "A");
+    let b = add(&ctx, "B");
+
+    // Both wait for the lock, then each checks for a cycle in turn
+    let lock = FileLock::acquire(&ctx).unwrap();
+    let results = std::thread::scope(|scope| {
+        let forward = scope.spawn(|| link(&ctx, &a, Relation::Blocks, &b));
+        let backward = scope.spawn(|| link(&ctx, &b, Relation::Blocks, &a));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        drop(lock);
+        [forward.join().unwrap(), backward.join().unwrap()]
+    });
+
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    let err = results.iter().find_map(|r| r.as_ref().err()).unwrap();
+    assert!(err.to_string().contains("cycle"));
+    assert!(LinkGraph::new(&materialize(&ctx).unwrap())
+        .cycles()
+        .is_empty());
+}
+
+#[test]
+fn test_unlink_removes_both_sides() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let a = add(&ctx, "A");
+    let b = add(&ctx, "B");
+    link(&ctx, &a, Relation::Blocks, &b).unwrap();
+    link(&ctx, &b, Relation::Parent, &a).unwrap();
+
+    // Named from the other side
+    unlink_tasks(&ctx, &b, Relation::BlockedBy, &a, "@tester", "main").unwrap();
+    unlink_tasks(&ctx, &b, Relation::Parent, &a, "@tester", "main").unwrap();
+
+    let state = materialize(&ctx).unwrap();
+    assert!(state.tasks[&a].blocks.is_empty());
+    assert!(state.tasks[&b].blocked_by.is_empty());
+    assert!(state.tasks[&b].parent.is_none());
+
+    let err = unlink_tasks(&ctx, &a, Relation::Blocks, &b, "@tester", "main").unwrap_err();
+    assert!(err.to_string().contains("not linked"));
+}
+
+#[test]
+fn test_priority_rank() {
+    assert!(priority_rank(Some("p0")) < priority_rank(Some("p1")));
+    assert!(priority_rank(Some("p3")) < priority_rank(Some("urgent")));
+    assert_eq!(priority_rank(Some("urgent")), priority_rank(None));
+}
+
+#[test]
+fn test_ready_tasks_skip_blocked_and_assigned() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let old = add(&ctx, "Old, no priority");
+    let low = add_with_priority(&ctx, "Low", Some("p2"));
+    let high = add_with_priority(&ctx, "High", Some("p0"));
+    let blocked = add_with_priority(&ctx, "Blocked", Some("p0"));
+    let taken = add_with_priority(&ctx, "Taken", Some("p0"));
+    let done = add_with_priority(&ctx, "Done", Some("p0"));
+    link(&ctx, &blocked, Relation::BlockedBy, &low).unwrap();
+    link(&ctx, &low, Relation::BlockedBy, &done).unwrap();
+    assign_task(&ctx, &taken, Some("@alice"), "@tester", "main").unwrap();
+    complete_task(&ctx, &done, None, "@tester", "main").unwrap();
+
+    let state = materialize(&ctx).unwrap();
+    let ids: Vec<&str> = ready_tasks(&state).iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, vec![high.as_str(), low.as_str(), old.as_str()]);
+
+    // Finishing the blocker makes the blocked task ready
+    complete_task(&ctx, &low, None, "@tester", "main").unwrap();
+    let state = materialize(&ctx).unwrap();
+    let ids: Vec<&str> = ready_tasks(&state).iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, vec![high.as_str(), blocked.as_str(), old.as_str()]);
+}
+
+#[test]
+fn test_claim_next_takes_each_task_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let first = add_with_priority(&ctx, "First", Some("p1"));
+    let second = add(&ctx, "Second");
+    let query = TaskQuery::default();
+
+    let claim = |by: &str| claim_next(&ctx, &query, by, "main").unwrap();
+    assert_eq!(claim("@alice"), Some(first.clone()));
+    assert_eq!(claim("@bob"), Some(second.clone()));
+    assert_eq!(claim("@carol"), None);
+
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks[&first].assignee.as_deref(), Some("@alice"));
+    assert_eq!(state.tasks[&second].assignee.as_deref(), Some("@bob"));
+}
+
+#[test]
+fn test_hierarchy_progress_counts_all_descendants() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let epic = add(&ctx, "Epic");
+    let a = add(&ctx, "A");
+    let b = add(&ctx, "B");
+    let b1 = add(&ctx, "B1");
+    add(&ctx, "Unrelated");
+    link(&ctx, &b, Relation::Parent, &epic).unwrap();
+    link(&ctx, &a, Relation::Parent, &epic).unwrap();
+    link(&ctx, &b1, Relation::Parent, &b).unwrap();
+    complete_task(&ctx, &a, None, "@tester", "main").unwrap();
+    complete_task(&ctx, &b1, None, "@tester", "main").unwrap();
+
+    let state = materialize(&ctx).unwrap();
+    let hierarchy = Hierarchy::new(state.tasks.values());
+    // Oldest first, whatever order they were linked in
+    assert_eq!(hierarchy.children(&epic), [a.clone(), b.clone()]);
+    assert_eq!(hierarchy.roots(), vec![epic.as_str()]);
+    assert_eq!(
+        hierarchy.progress(&epic),
+        Progress {
+            complete: 2,
+            total: 3
+        }
+    );
+    assert_eq!(hierarchy.progress(&epic).open(), 1);
+    assert_eq!(hierarchy.progress(&a), Progress::default());
+}
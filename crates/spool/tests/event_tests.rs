@@ -1,6 +1,7 @@
 use chrono::Utc;
 use serde_json::json;
-use spool::event::{Event, Operation};
+use spool::clock::Hlc;
+use spool::event::{sort_events, Event, Operation};
 
 #[test]
 fn test_operation_serialization() {
@@ -9,6 +10,10 @@ fn test_operation_serialization() {
         (Operation::Update, "update"),
         (Operation::Assign, "assign"),
         (Operation::Comment, "comment"),
+        (Operation::EditComment, "edit_comment"),
+        (Operation::DeleteComment, "delete_comment"),
+        (Operation::Tag, "tag"),
+        (Operation::Untag, "untag"),
         (Operation::Link, "link"),
         (Operation::Unlink, "unlink"),
         (Operation::Complete, "complete"),
@@ -30,6 +35,10 @@ fn test_operation_deserialization() {
         ("\"update\"", Operation::Update),
         ("\"assign\"", Operation::Assign),
         ("\"comment\"", Operation::Comment),
+        ("\"edit_comment\"", Operation::EditComment),
+        ("\"delete_comment\"", Operation::DeleteComment),
+        ("\"tag\"", Operation::Tag),
+        ("\"untag\"", Operation::Untag),
         ("\"link\"", Operation::Link),
         ("\"unlink\"", Operation::Unlink),
         ("\"complete\"", Operation::Complete),
@@ -65,6 +74,9 @@ fn test_event_serialization_roundtrip() {
         op: Operation::Create,
         id: "task-123".to_string(),
         ts: Utc::now(),
+        hlc: None,
+        prev: vec![],
+        txn: None,
         by: "user@example.com".to_string(),
         branch: "main".to_string(),
         d: json!({"title": "Test task", "priority": "p2"}),
@@ -102,6 +114,9 @@ fn test_event_with_all_data_fields() {
         op: Operation::Create,
         id: "full-task".to_string(),
         ts: Utc::now(),
+        hlc: None,
+        prev: vec![],
+        txn: None,
         by: "author".to_string(),
         branch: "main".to_string(),
         d: json!({
@@ -123,3 +138,107 @@ fn test_event_with_all_data_fields() {
     assert_eq!(parsed.d["blocks"].as_array().unwrap().len(), 2);
     assert_eq!(parsed.d["blocked_by"].as_array().unwrap().len(), 1);
 }
+
+#[test]
+fn test_unknown_operation_roundtrip() {
+    let op: Operation = serde_json::from_str("\"estimate\"").unwrap();
+    assert_eq!(op, Operation::Unknown("estimate".to_string()));
+    assert_eq!(op.to_string(), "estimate");
+    assert_eq!(serde_json::to_string(&op).unwrap(), "\"estimate\"");
+}
+
+#[test]
+fn test_event_with_unknown_operation_parses() {
+    let json_line = r#"{"v":1,"op":"estimate","id":"abc-1234","ts":"2024-01-15T10:30:00Z","by":"@a","branch":"main","d":{"points":3}}"#;
+
+    let event = Event::from_json(json_line).unwrap();
+
+    assert_eq!(event.op, Operation::Unknown("estimate".to_string()));
+    assert!(!event.is_supported());
+    let roundtrip: serde_json::Value =
+        serde_json::from_str(&serde_json::to_string(&event).unwrap()).unwrap();
+    assert_eq!(roundtrip["op"], "estimate");
+    assert_eq!(roundtrip["d"]["points"], 3);
+}
+
+#[test]
+fn test_from_json_keeps_newer_schema_version() {
+    let json_line = r#"{"v":2,"op":"create","id":"abc-1234","ts":"2024-01-15T10:30:00Z","by":"@a","branch":"main","d":{"title":"Future"}}"#;
+
+    let event = Event::from_json(json_line).unwrap();
+
+    assert_eq!(event.v, 2);
+    assert!(!event.is_supported());
+}
+
+#[test]
+fn test_upcast_rejects_version_zero() {
+    let raw = json!({"v": 0, "op": "create"});
+    assert!(spool::event::upcast(raw).is_err());
+}
+
+#[test]
+fn test_upcast_current_version_is_identity() {
+    let raw = json!({"v": spool::event::CURRENT_VERSION, "op": "create", "d": {"title": "x"}});
+    assert_eq!(spool::event::upcast(raw.clone()).unwrap(), raw);
+}
+
+#[test]
+fn test_hlc_tick_is_monotonic_when_clock_runs_ahead() {
+    let now = Utc::now();
+    let ahead = Hlc {
+        wall_ms: now.timestamp_millis() + 60_000,
+        counter: 4,
+    };
+
+    let next = Hlc::tick(Some(ahead), now);
+    assert_eq!(next.wall_ms, ahead.wall_ms);
+    assert_eq!(next.counter, 5);
+    assert!(next > ahead);
+
+    let fresh = Hlc::tick(None, now);
+    assert_eq!(fresh, Hlc::from_ts(now));
+}
+
+#[test]
+fn test_hlc_tick_advances_wall_time_when_counter_is_exhausted() {
+    let now = Utc::now();
+    let last = Hlc {
+        wall_ms: now.timestamp_millis() + 60_000,
+        counter: u32::MAX,
+    };
+
+    let next = Hlc::tick(Some(last), now);
+    assert_eq!(next.wall_ms, last.wall_ms + 1);
+    assert_eq!(next.counter, 0);
+    assert!(next > last);
+}
+
+#[test]
+fn test_hlc_serializes_as_pair() {
+    let json = r#"{"v":1,"op":"reopen","id":"task-001","ts":"2024-01-15T10:00:00Z","hlc":[1705312800000,2],"by":"@tester","branch":"main","d":{}}"#;
+    let event = Event::from_json(json).unwrap();
+
+    assert_eq!(
+        event.hlc,
+        Some(Hlc {
+            wall_ms: 1705312800000,
+            counter: 2
+        })
+    );
+    assert_eq!(serde_json::to_string(&event).unwrap(), json);
+}
+
+#[test]
+fn test_sort_events_orders_by_clock_before_file_order() {
+    let late = r#"{"v":1,"op":"update","id":"t","ts":"2024-01-15T10:00:00Z","hlc":[1705312800000,1],"by":"@a","branch":"main","d":{"title":"Late"}}"#;
+    let early = r#"{"v":1,"op":"update","id":"t","ts":"2024-01-15T10:00:05Z","hlc":[1705312800000,0],"by":"@b","branch":"feature","d":{"title":"Early"}}"#;
+    let mut events = vec![
+        Event::from_json(late).unwrap(),
+        Event::from_json(early).unwrap(),
+    ];
+
+    sort_events(&mut events);
+    assert_eq!(events[0].d["title"], "Early");
+    assert_eq!(events[1].d["title"], "Late");
+}
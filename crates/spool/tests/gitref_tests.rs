@@ -0,0 +1,216 @@
+use spool::context::SpoolContext;
+use spool::gitref::{sync, GitRefStore, SyncReport, DEFAULT_REF};
+use spool::state::materialize;
+use spool::store::EventStore;
+use spool::writer::{complete_task, create_task, CreateTaskParams};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// A git repository with a spool that keeps its events on the default ref
+fn setup_repo(dir: &Path) -> SpoolContext {
+    if !dir.join(".git").exists() {
+        fs::create_dir_all(dir).unwrap();
+        git(dir, &["init", "--quiet"]);
+    }
+    let spool_dir = dir.join(".spool");
+    fs::create_dir_all(&spool_dir).unwrap();
+    SpoolContext::new(spool_dir).with_git_ref(DEFAULT_REF)
+}
+
+/// A bare remote and two clones of it
+fn setup_clones(temp_dir: &TempDir) -> (SpoolContext, SpoolContext) {
+    let remote = temp_dir.path().join("remote.git");
+    git(
+        temp_dir.path(),
+        &["init", "--quiet", "--bare", "remote.git"],
+    );
+    for name in ["alice", "bob"] {
+        git(
+            temp_dir.path(),
+            &["clone", "--quiet", remote.to_str().unwrap(), name],
+        );
+    }
+    (
+        setup_repo(&temp_dir.path().join("alice")),
+        setup_repo(&temp_dir.path().join("bob")),
+    )
+}
+
+fn add(ctx: &SpoolContext, title: &str) -> String {
+    create_task(
+        ctx,
+        CreateTaskParams {
+            title,
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_events_are_committed_to_the_ref() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup_repo(temp_dir.path());
+
+    let id = add(&ctx, "On the ref");
+    complete_task(&ctx, &id, None, "@tester", "main").unwrap();
+
+    // Nothing in the working tree
+    assert!(!ctx.events_dir.exists());
+    assert!(ctx.get_event_files().unwrap().is_empty());
+
+    let segments = ctx.segments().unwrap();
+    assert_eq!(segments.len(), 1);
+    assert!(segments[0].starts_with("events/"));
+    let commits = git(temp_dir.path(), &["rev-list", "--count", DEFAULT_REF]);
+    assert_eq!(commits, "2");
+    let content = git(
+        temp_dir.path(),
+        &["show", &format!("{}:{}", DEFAULT_REF, segments[0])],
+    );
+    assert_eq!(content.lines().count(), 2);
+
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks[&id].status, spool::TaskStatus::Complete);
+}
+
+#[test]
+fn test_ref_store_replace_removes_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    git(temp_dir.path(), &["init", "--quiet"]);
+    let store = GitRefStore::new(temp_dir.path(), "refs/spool/test");
+    assert!(store.segments().unwrap().is_empty());
+
+    store.append("events/2024-01-15.jsonl", b"one\n").unwrap();
+    store.append("archive/2023-12.jsonl", b"two\n").unwrap();
+    store.append("events/2024-01-15.jsonl", b"three\n").unwrap();
+    assert_eq!(
+        store.segments().unwrap(),
+        vec!["archive/2023-12.jsonl", "events/2024-01-15.jsonl"]
+    );
+    assert_eq!(
+        store.read("events/2024-01-15.jsonl").unwrap(),
+        b"one\nthree\n"
+    );
+
+    store.replace("archive/2023-12.jsonl", b"").unwrap();
+    assert_eq!(store.segments().unwrap(), vec!["events/2024-01-15.jsonl"]);
+
+    // A second store over the same ref sees the same commits
+    let other = GitRefStore::new(temp_dir.path(), "refs/spool/test");
+    assert_eq!(
+        other.read("events/2024-01-15.jsonl").unwrap(),
+        b"one\nthree\n"
+    );
+}
+
+#[test]
+fn test_sync_pushes_and_pulls() {
+    let temp_dir = TempDir::new().unwrap();
+    let (alice, bob) = setup_clones(&temp_dir);
+
+    // Nothing to do on either side yet
+    assert_eq!(sync(&alice, "origin").unwrap(), SyncReport::default());
+
+    let id = add(&alice, "Shared");
+    let report = sync(&alice, "origin").unwrap();
+    assert!(report.pushed && !report.pulled);
+
+    let report = sync(&bob, "origin").unwrap();
+    assert!(report.pulled && !report.pushed && !report.merged);
+    assert_eq!(materialize(&bob).unwrap().tasks[&id].title, "Shared");
+
+    assert_eq!(sync(&bob, "origin").unwrap(), SyncReport::default());
+}
+
+#[test]
+fn test_sync_merges_concurrent_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let (alice, bob) = setup_clones(&temp_dir);
+    let shared = add(&alice, "Shared");
+    sync(&alice, "origin").unwrap();
+    sync(&bob, "origin").unwrap();
+
+    let from_alice = add(&alice, "From alice");
+    complete_task(&bob, &shared, None, "@tester", "main").unwrap();
+    let from_bob = add(&bob, "From bob");
+    sync(&alice, "origin").unwrap();
+
+    let report = sync(&bob, "origin").unwrap();
+    assert!(report.merged && report.pushed);
+    let state = materialize(&bob).unwrap();
+    assert!(state.tasks.contains_key(&from_alice));
+    assert!(state.tasks.contains_key(&from_bob));
+    assert_eq!(state.tasks[&shared].status, spool::TaskStatus::Complete);
+
+    // Merged files hold each event once
+    for segment in bob.segments().unwrap() {
+        let (events, bad) = bob.read_events(&segment).unwrap();
+        assert!(bad.is_empty());
+        let mut hashes: Vec<u64> = events.iter().map(|e| e.content_hash()).collect();
+        hashes.dedup();
+        assert_eq!(hashes.len(), events.len());
+    }
+
+    let report = sync(&alice, "origin").unwrap();
+    assert!(report.pulled && !report.merged && !report.pushed);
+    assert_eq!(
+        serde_json::to_value(materialize(&alice).unwrap().tasks).unwrap(),
+        serde_json::to_value(state.tasks).unwrap()
+    );
+}
+
+#[test]
+fn test_sync_requires_events_ref() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = temp_dir.path().join(".spool");
+    fs::create_dir_all(spool_dir.join("events")).unwrap();
+    let ctx = SpoolContext::new(spool_dir);
+
+    let err = sync(&ctx, "origin").unwrap_err();
+    assert!(err.to_string().contains("spool init --events-ref"));
+}
+
+#[test]
+fn test_author_shards_on_the_ref() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup_repo(temp_dir.path());
+    fs::write(ctx.config_path(), r#"{"layout": "author"}"#).unwrap();
+
+    let id = add(&ctx, "Sharded");
+    complete_task(&ctx, &id, None, "@other", "main").unwrap();
+
+    let files = git(
+        temp_dir.path(),
+        &["ls-tree", "-r", "--name-only", DEFAULT_REF],
+    );
+    let day = chrono::Utc::now().format("%Y-%m-%d");
+    assert_eq!(
+        files.lines().collect::<Vec<_>>(),
+        vec![
+            format!("events/{}/@other.jsonl", day),
+            format!("events/{}/@tester.jsonl", day)
+        ]
+    );
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks[&id].status, spool::TaskStatus::Complete);
+}
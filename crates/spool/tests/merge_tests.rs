@@ -0,0 +1,55 @@
+use spool::merge::merge_event_logs;
+
+const CREATE: &str = r#"{"v":1,"op":"create","id":"task-1","ts":"2024-01-15T10:00:00Z","hlc":[1705312800000,0],"by":"@alice","branch":"main","d":{"title":"Task"}}"#;
+const OURS: &str = r#"{"v":1,"op":"comment","id":"task-1","ts":"2024-01-15T11:00:00Z","hlc":[1705316400000,0],"by":"@alice","branch":"main","d":{"body":"Ours"}}"#;
+const THEIRS: &str = r#"{"v":1,"op":"comment","id":"task-1","ts":"2024-01-15T10:30:00Z","hlc":[1705314600000,0],"by":"@bob","branch":"feature","d":{"body":"Theirs"}}"#;
+
+fn lines(lines: &[&str]) -> String {
+    lines.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+#[test]
+fn test_merge_unions_both_sides_in_replay_order() {
+    let merged = merge_event_logs(
+        &lines(&[CREATE]),
+        &lines(&[CREATE, OURS]),
+        &lines(&[CREATE, THEIRS]),
+    );
+
+    assert_eq!(merged, lines(&[CREATE, THEIRS, OURS]));
+}
+
+#[test]
+fn test_merge_keeps_events_added_on_both_sides_once() {
+    let merged = merge_event_logs("", &lines(&[CREATE, OURS]), &lines(&[CREATE, OURS]));
+
+    assert_eq!(merged, lines(&[CREATE, OURS]));
+}
+
+#[test]
+fn test_merge_drops_lines_removed_on_one_side() {
+    // e.g. the task's events were moved to the archive on one branch
+    let merged = merge_event_logs(
+        &lines(&[CREATE, OURS]),
+        &lines(&[CREATE, OURS, THEIRS]),
+        &lines(&[]),
+    );
+
+    assert_eq!(merged, lines(&[THEIRS]));
+}
+
+#[test]
+fn test_merge_matches_events_by_content_not_formatting() {
+    let reformatted = r#"{"by":"@alice","v":1,"op":"create","id":"task-1","ts":"2024-01-15T10:00:00Z","hlc":[1705312800000,0],"branch":"main","d":{"title":"Task"}}"#;
+
+    let merged = merge_event_logs("", &lines(&[CREATE]), &lines(&[reformatted]));
+
+    assert_eq!(merged, lines(&[CREATE]));
+}
+
+#[test]
+fn test_merge_keeps_unparseable_lines_at_end() {
+    let merged = merge_event_logs("", &lines(&["not json", OURS]), &lines(&[CREATE]));
+
+    assert_eq!(merged, lines(&[CREATE, OURS, "not json"]));
+}
@@ -0,0 +1,124 @@
+use serde_json::json;
+use spool::event::{Event, Operation};
+use spool::payload::{AssignPayload, CreatePayload, LinkPayload, Payload};
+
+fn event_with(op: Operation, d: serde_json::Value) -> Event {
+    Event {
+        v: 1,
+        op,
+        id: "task-001".to_string(),
+        ts: chrono::Utc::now(),
+        hlc: None,
+        prev: vec![],
+        txn: None,
+        by: "@tester".to_string(),
+        branch: "main".to_string(),
+        d,
+    }
+}
+
+#[test]
+fn test_decode_create_payload() {
+    let event = event_with(
+        Operation::Create,
+        json!({"title": "Typed", "priority": "p1", "tags": ["bug"], "blocks": ["task-002"]}),
+    );
+
+    match event.payload().unwrap() {
+        Payload::Create(p) => {
+            assert_eq!(p.title, "Typed");
+            assert_eq!(p.priority.as_deref(), Some("p1"));
+            assert_eq!(p.tags, vec!["bug".to_string()]);
+            assert_eq!(p.blocks, vec!["task-002".to_string()]);
+            assert!(p.extra.is_empty());
+        }
+        other => panic!("Expected create payload, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_decode_rejects_wrong_field_type() {
+    let event = event_with(Operation::Create, json!({"title": 42}));
+    let err = event.payload().unwrap_err();
+    assert!(format!("{:#}", err).contains("create"));
+}
+
+#[test]
+fn test_decode_create_skips_null_title_and_non_string_tags() {
+    let event = event_with(
+        Operation::Create,
+        json!({"title": null, "tags": ["bug", 7, null, "ui"], "blocks": null}),
+    );
+
+    match event.payload().unwrap() {
+        Payload::Create(p) => {
+            assert_eq!(p.title, "");
+            assert_eq!(p.tags, vec!["bug", "ui"]);
+            assert!(p.blocks.is_empty());
+        }
+        other => panic!("expected create payload, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_decode_link_requires_rel_and_target() {
+    let event = event_with(Operation::Link, json!({"rel": "blocks"}));
+    assert!(event.payload().is_err());
+}
+
+#[test]
+fn test_tag_and_untag_share_a_payload() {
+    for op in [Operation::Tag, Operation::Untag] {
+        let event = event_with(op.clone(), json!({"tags": ["bug", "wip"]}));
+        let payload = event.payload().unwrap();
+        assert_eq!(payload.operation(), op);
+        assert_eq!(payload.to_value(), json!({"tags": ["bug", "wip"]}));
+    }
+}
+
+#[test]
+fn test_decode_null_payload_for_empty_operation() {
+    let event = event_with(Operation::Reopen, serde_json::Value::Null);
+    assert!(matches!(event.payload().unwrap(), Payload::Reopen(_)));
+}
+
+#[test]
+fn test_unknown_fields_survive_roundtrip() {
+    let d = json!({"title": "Future", "estimate": {"points": 3}, "labels": ["x"]});
+    let event = event_with(Operation::Create, d.clone());
+
+    let payload = event.payload().unwrap();
+    if let Payload::Create(p) = &payload {
+        assert_eq!(p.extra.get("estimate"), Some(&json!({"points": 3})));
+    }
+    assert_eq!(payload.to_value(), d);
+}
+
+#[test]
+fn test_assign_null_roundtrips_as_null() {
+    let payload = Payload::Assign(AssignPayload::default());
+    assert_eq!(payload.to_value(), json!({"to": null}));
+}
+
+#[test]
+fn test_event_new_sets_operation_from_payload() {
+    let payload = Payload::Unlink(LinkPayload {
+        rel: "parent".to_string(),
+        target: "task-002".to_string(),
+        ..Default::default()
+    });
+    let event = Event::new("task-001", payload, "@tester", "main");
+
+    assert_eq!(event.v, 1);
+    assert_eq!(event.op, Operation::Unlink);
+    assert_eq!(event.d, json!({"rel": "parent", "target": "task-002"}));
+}
+
+#[test]
+fn test_create_payload_omits_empty_fields() {
+    let payload = Payload::Create(CreatePayload {
+        title: "Minimal".to_string(),
+        ..Default::default()
+    });
+    assert_eq!(payload.to_value(), json!({"title": "Minimal"}));
+}
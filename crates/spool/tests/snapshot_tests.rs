@@ -0,0 +1,254 @@
+use serde_json::json;
+use spool::context::SpoolContext;
+use spool::snapshot::compact;
+use spool::state::materialize;
+use spool::validation::validate;
+use spool::writer::{create_task, update_task, CreateTaskParams};
+use std::fs;
+use std::io::Write;
+use tempfile::TempDir;
+
+/// Helper to create a spool directory structure for testing
+fn setup_spool_dir(temp_dir: &TempDir) -> std::path::PathBuf {
+    let spool_dir = temp_dir.path().join(".spool");
+    fs::create_dir_all(spool_dir.join("events")).unwrap();
+    fs::create_dir_all(spool_dir.join("archive")).unwrap();
+    spool_dir
+}
+
+/// Append events to a file
+fn append_events(dir: &std::path::Path, filename: &str, events: &[serde_json::Value]) {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(filename))
+        .unwrap();
+    for event in events {
+        writeln!(file, "{}", serde_json::to_string(event).unwrap()).unwrap();
+    }
+}
+
+fn create(id: &str, ts: &str, title: &str) -> serde_json::Value {
+    json!({
+        "v": 1, "op": "create", "id": id,
+        "ts": ts, "by": "tester", "branch": "main",
+        "d": {"title": title}
+    })
+}
+
+/// Compact a repo with one task, then rewrite the task's title inside the
+/// snapshot so reads that start from it can be told apart from a full replay
+fn tampered_snapshot(spool_dir: &std::path::Path) -> (SpoolContext, std::path::PathBuf) {
+    append_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create("task-001", "2024-01-15T10:00:00Z", "Original")],
+    );
+    let ctx = SpoolContext::new(spool_dir.to_path_buf());
+    let path = compact(&ctx).unwrap();
+
+    let content = fs::read_to_string(&path).unwrap();
+    fs::write(&path, content.replace("\"Original\"", "\"From snapshot\"")).unwrap();
+    (ctx, path)
+}
+
+#[test]
+fn test_compact_writes_snapshot_of_current_state() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    append_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create("task-001", "2024-01-15T10:00:00Z", "First")],
+    );
+    let ctx = SpoolContext::new(spool_dir.clone());
+
+    let first = compact(&ctx).unwrap();
+    assert!(first.starts_with(spool_dir.join("snapshots")));
+    let snapshot: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&first).unwrap()).unwrap();
+    assert_eq!(snapshot["v"], 1);
+    assert_eq!(snapshot["state"]["tasks"]["task-001"]["title"], "First");
+
+    // A new snapshot replaces the old one
+    let second = compact(&ctx).unwrap();
+    assert_ne!(first, second);
+    assert!(!first.exists());
+    assert!(second.exists());
+}
+
+#[test]
+fn test_materialize_starts_from_snapshot() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let (ctx, _) = tampered_snapshot(&spool_dir);
+
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks["task-001"].title, "From snapshot");
+
+    // Later events are replayed on top of it, whether appended or in new files
+    append_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create("task-002", "2024-01-15T11:00:00Z", "Appended")],
+    );
+    append_events(
+        &spool_dir.join("events"),
+        "2024-01-16.jsonl",
+        &[create("task-003", "2024-01-16T10:00:00Z", "New file")],
+    );
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks["task-001"].title, "From snapshot");
+    assert_eq!(state.tasks.len(), 3);
+}
+
+#[test]
+fn test_snapshot_ignored_when_history_changes() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let (ctx, _) = tampered_snapshot(&spool_dir);
+
+    // An older event merged in from another branch
+    append_events(
+        &spool_dir.join("events"),
+        "2024-01-14.jsonl",
+        &[create("task-000", "2024-01-14T10:00:00Z", "Merged")],
+    );
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks["task-001"].title, "Original");
+    assert_eq!(state.tasks.len(), 2);
+}
+
+#[test]
+fn test_snapshot_ignored_when_new_events_sort_before_it() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let (ctx, _) = tampered_snapshot(&spool_dir);
+
+    // A later file holding an event that replays before the snapshot's
+    append_events(
+        &spool_dir.join("events"),
+        "2024-01-16.jsonl",
+        &[create("task-000", "2024-01-15T09:00:00Z", "Earlier")],
+    );
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks["task-001"].title, "Original");
+    assert_eq!(state.tasks.len(), 2);
+}
+
+fn update(ts: &str, prev: &serde_json::Value, d: serde_json::Value) -> serde_json::Value {
+    let prev: spool::event::Event = serde_json::from_value(prev.clone()).unwrap();
+    json!({
+        "v": 1, "op": "update", "id": "task-001",
+        "ts": ts, "by": "tester", "branch": "main",
+        "prev": [prev.event_id()], "d": d
+    })
+}
+
+#[test]
+fn test_snapshot_size_does_not_grow_with_events_compacted() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = SpoolContext::new(setup_spool_dir(&temp_dir));
+    let id = create_task(
+        &ctx,
+        CreateTaskParams {
+            title: "Busy",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+    let rename = |n: usize| {
+        for i in 0..n {
+            let title = format!("Busy {}", i % 2);
+            update_task(&ctx, &id, Some(&title), None, None, "@tester", "main").unwrap();
+        }
+        fs::metadata(compact(&ctx).unwrap()).unwrap().len()
+    };
+
+    let small = rename(10);
+    let large = rename(200);
+    // Only the watermark's offsets and hashes may get a few digits longer
+    assert!(large < small + 64, "{} bytes, then {}", small, large);
+}
+
+#[test]
+fn test_snapshot_replays_in_full_for_compacted_ancestors() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let created = create("task-001", "2024-01-15T10:00:00Z", "Original");
+    let titled = update("2024-01-15T11:00:00Z", &created, json!({"title": "Titled"}));
+    let forked = update("2024-01-15T12:00:00Z", &titled, json!({"priority": "p1"}));
+    let main = update("2024-01-15T13:00:00Z", &forked, json!({"priority": "p2"}));
+    append_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[created, titled, forked.clone(), main],
+    );
+    let ctx = SpoolContext::new(spool_dir.clone());
+    compact(&ctx).unwrap();
+
+    // A branch that forked at an event the snapshot dropped, and had seen
+    // the title it overwrites
+    append_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[update(
+            "2024-01-15T14:00:00Z",
+            &forked,
+            json!({"title": "Branch"}),
+        )],
+    );
+    let state = materialize(&ctx).unwrap();
+    assert_eq!(state.tasks["task-001"].title, "Branch");
+    assert!(state.conflicts.is_empty());
+}
+
+#[test]
+fn test_validate_reports_snapshot_mismatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let (ctx, _) = tampered_snapshot(&spool_dir);
+
+    let result = validate(&ctx, false).unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert!(result.errors[0].contains("does not match a full replay"));
+
+    compact(&ctx).unwrap();
+    let result = validate(&ctx, false).unwrap();
+    assert!(result.errors.is_empty());
+    assert!(result.warnings.is_empty());
+}
+
+#[test]
+fn test_validate_warns_about_stale_and_unreadable_snapshots() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let (ctx, path) = tampered_snapshot(&spool_dir);
+
+    fs::write(
+        spool_dir.join("events/2024-01-15.jsonl"),
+        format!(
+            "{}\n",
+            create("task-001", "2024-01-15T10:00:00Z", "Rewritten")
+        ),
+    )
+    .unwrap();
+    let result = validate(&ctx, false).unwrap();
+    assert!(result.errors.is_empty());
+    assert_eq!(result.warnings.len(), 1);
+    assert!(result.warnings[0].contains("no longer matches"));
+    assert_eq!(
+        materialize(&ctx).unwrap().tasks["task-001"].title,
+        "Rewritten"
+    );
+
+    fs::write(&path, "{not json").unwrap();
+    let result = validate(&ctx, false).unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(
+        materialize(&ctx).unwrap().tasks["task-001"].title,
+        "Rewritten"
+    );
+}
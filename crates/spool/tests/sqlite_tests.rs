@@ -0,0 +1,182 @@
+#![cfg(feature = "sqlite")]
+
+use spool::context::SpoolContext;
+use spool::query::{ArchivedFilter, TaskQuery, TaskSource};
+use spool::sqlite::SqliteIndex;
+use spool::state::{load_or_materialize_state, TaskStatus};
+use spool::store::EventStore;
+use spool::writer::{
+    assign_task, complete_task, create_stream, create_task, tag_task, CreateTaskParams,
+};
+use std::fs;
+use tempfile::TempDir;
+
+fn setup(temp_dir: &TempDir) -> SpoolContext {
+    let spool_dir = temp_dir.path().join(".spool");
+    fs::create_dir_all(spool_dir.join("events")).unwrap();
+    fs::create_dir_all(spool_dir.join("archive")).unwrap();
+    SpoolContext::new(spool_dir)
+}
+
+fn add(ctx: &SpoolContext, title: &str, tags: &[&str], stream: Option<&str>) -> String {
+    create_task(
+        ctx,
+        CreateTaskParams {
+            title,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            stream,
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap()
+}
+
+fn ids(tasks: Vec<spool::Task>) -> Vec<String> {
+    tasks.into_iter().map(|t| t.id).collect()
+}
+
+#[test]
+fn test_queries_match_the_state() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let stream = create_stream(&ctx, "Backend", None, "@tester", "main").unwrap();
+    let a = add(&ctx, "Fix the parser", &["bug"], Some(&stream));
+    let b = add(&ctx, "Write docs", &["docs"], None);
+    add(&ctx, "Ünicode title", &["Bug"], Some(&stream));
+    assign_task(&ctx, &b, Some("@alice"), "@tester", "main").unwrap();
+    complete_task(&ctx, &a, None, "@tester", "main").unwrap();
+
+    let state = TaskSource::State(Box::new(load_or_materialize_state(&ctx).unwrap()));
+    let index = TaskSource::Sqlite(SqliteIndex::open(&ctx).unwrap());
+    let queries = [
+        TaskQuery::default(),
+        TaskQuery {
+            status: Some(TaskStatus::Open),
+            ..Default::default()
+        },
+        TaskQuery {
+            status: Some(TaskStatus::Complete),
+            ..Default::default()
+        },
+        TaskQuery {
+            tag: Some("bug".into()),
+            ..Default::default()
+        },
+        TaskQuery {
+            assignee: Some("@alice".into()),
+            ..Default::default()
+        },
+        TaskQuery {
+            stream: Some(stream.clone()),
+            ..Default::default()
+        },
+        TaskQuery {
+            no_stream: true,
+            ..Default::default()
+        },
+        TaskQuery {
+            search: Some("ÜNICODE".into()),
+            ..Default::default()
+        },
+        TaskQuery {
+            archived: ArchivedFilter::Only,
+            ..Default::default()
+        },
+    ];
+    for query in &queries {
+        assert_eq!(
+            ids(index.tasks(query).unwrap()),
+            ids(state.tasks(query).unwrap()),
+            "{:?}",
+            query
+        );
+    }
+    assert_eq!(index.tasks(&queries[0]).unwrap().len(), 3);
+    let (from_state, from_index) = (state.hierarchy().unwrap(), index.hierarchy().unwrap());
+    assert_eq!(from_index.roots(), from_state.roots());
+    assert_eq!(index.streams().unwrap()[&stream].name, "Backend");
+}
+
+#[test]
+fn test_index_follows_appended_events() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let first = add(&ctx, "First", &[], None);
+    let index = SqliteIndex::open(&ctx).unwrap();
+    assert_eq!(index.events(&first).unwrap().len(), 1);
+    drop(index);
+
+    let second = add(&ctx, "Second", &[], None);
+    complete_task(&ctx, &first, Some("done"), "@tester", "main").unwrap();
+    let index = SqliteIndex::open(&ctx).unwrap();
+    assert_eq!(index.events(&first).unwrap().len(), 2);
+    assert_eq!(index.events(&second).unwrap().len(), 1);
+    let first = index.task(&first).unwrap().unwrap();
+    assert_eq!(first.status, TaskStatus::Complete);
+    assert_eq!(first.resolution.as_deref(), Some("done"));
+}
+
+#[test]
+fn test_index_follows_rewritten_history() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let kept = add(&ctx, "Kept", &[], None);
+    let dropped = add(&ctx, "Dropped", &["gone"], None);
+    SqliteIndex::open(&ctx).unwrap();
+
+    // Rewrite the day file without the second task
+    let segment = ctx.segments().unwrap().remove(0);
+    let content = String::from_utf8(ctx.read(&segment).unwrap()).unwrap();
+    let rewritten: String = content
+        .lines()
+        .filter(|line| !line.contains(&dropped))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    ctx.replace(&segment, rewritten.as_bytes()).unwrap();
+
+    let index = SqliteIndex::open(&ctx).unwrap();
+    assert_eq!(ids(index.tasks(&TaskQuery::default()).unwrap()), vec![kept]);
+    assert!(index.task(&dropped).unwrap().is_none());
+    assert!(index.events(&dropped).unwrap().is_empty());
+    let tagged = TaskQuery {
+        tag: Some("gone".into()),
+        ..Default::default()
+    };
+    assert!(index.tasks(&tagged).unwrap().is_empty());
+}
+
+#[test]
+fn test_index_from_older_replay_is_rebuilt() {
+    let temp_dir = TempDir::new().unwrap();
+    let ctx = setup(&temp_dir);
+    let id = add(&ctx, "Tagged", &["old"], None);
+    tag_task(
+        &ctx,
+        &id,
+        &["new".into()],
+        &["old".into()],
+        "@tester",
+        "main",
+    )
+    .unwrap();
+    SqliteIndex::open(&ctx).unwrap();
+
+    // What a build that ignored tag events left behind, at the same watermark
+    let conn = rusqlite::Connection::open(ctx.sqlite_path()).unwrap();
+    conn.execute_batch(
+        "DELETE FROM tags; INSERT INTO tags (task_id, tag) SELECT id, 'old' FROM tasks;
+         UPDATE meta SET value = '1' WHERE key = 'state_format';",
+    )
+    .unwrap();
+    drop(conn);
+
+    let index = SqliteIndex::open(&ctx).unwrap();
+    let tagged = |tag: &str| TaskQuery {
+        tag: Some(tag.into()),
+        ..Default::default()
+    };
+    assert!(index.tasks(&tagged("old")).unwrap().is_empty());
+    assert_eq!(ids(index.tasks(&tagged("new")).unwrap()), vec![id]);
+}
@@ -1,6 +1,7 @@
 use serde_json::json;
 use spool::context::SpoolContext;
 use spool::state::{Task, TaskStatus};
+use spool::store::EventStore;
 use std::fs;
 use std::io::Write;
 use tempfile::TempDir;
@@ -633,3 +634,743 @@ fn test_state_materialization_streams_and_tasks() {
         Some("stream-backend")
     );
 }
+
+#[test]
+fn test_state_materialization_skips_unsupported_events() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let events = vec![
+        json!({
+            "v": 1, "op": "create", "id": "task-1",
+            "ts": "2024-01-15T10:00:00Z", "by": "@tester", "branch": "main",
+            "d": {"title": "Known"}
+        }),
+        json!({
+            "v": 1, "op": "estimate", "id": "task-1",
+            "ts": "2024-01-15T10:01:00Z", "by": "@tester", "branch": "main",
+            "d": {"points": 3}
+        }),
+        json!({
+            "v": 2, "op": "update", "id": "task-1",
+            "ts": "2024-01-15T10:02:00Z", "by": "@tester", "branch": "main",
+            "d": {"title": "From the future"}
+        }),
+    ];
+
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &events);
+
+    let ctx = create_test_context(&spool_dir);
+    let state = spool::state::materialize(&ctx).unwrap();
+
+    assert_eq!(state.tasks.get("task-1").unwrap().title, "Known");
+    assert_eq!(state.warnings.len(), 2);
+    assert!(state.warnings.iter().any(|w| w.contains("'estimate'")));
+    assert!(state
+        .warnings
+        .iter()
+        .any(|w| w.contains("schema version 2")));
+}
+
+#[test]
+fn test_load_state_replays_events_appended_after_cache() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "@tester", "branch": "main",
+        "d": {"title": "Cached"}
+    });
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &[create]);
+
+    let ctx = create_test_context(&spool_dir);
+    spool::state::rebuild(&ctx).unwrap();
+
+    // Append to the cached file and start a new one
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(spool_dir.join("events/2024-01-15.jsonl"))
+        .unwrap();
+    let complete = json!({
+        "v": 1, "op": "complete", "id": "task-1",
+        "ts": "2024-01-15T11:00:00Z", "by": "@tester", "branch": "main",
+        "d": {}
+    });
+    writeln!(file, "{}", complete).unwrap();
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-2",
+        "ts": "2024-01-16T10:00:00Z", "by": "@tester", "branch": "main",
+        "d": {"title": "New day"}
+    });
+    write_events(&spool_dir.join("events"), "2024-01-16.jsonl", &[create]);
+
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.tasks.len(), 2);
+    assert_eq!(state.tasks["task-1"].status, TaskStatus::Complete);
+
+    // The refreshed cache covers both files
+    let cached: spool::state::State =
+        serde_json::from_str(&fs::read_to_string(ctx.state_path()).unwrap()).unwrap();
+    let watermark = cached.watermark.unwrap();
+    assert!(watermark.contains_key("events/2024-01-15.jsonl"));
+    assert!(watermark.contains_key("events/2024-01-16.jsonl"));
+}
+
+#[test]
+fn test_load_state_replays_fully_when_history_rewritten() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "@tester", "branch": "main",
+        "d": {"title": "Original"}
+    });
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &[create]);
+
+    let ctx = create_test_context(&spool_dir);
+    spool::state::rebuild(&ctx).unwrap();
+
+    // Rewrite the file in place, e.g. after a git checkout
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "@tester", "branch": "main",
+        "d": {"title": "Rewritten"}
+    });
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &[create]);
+
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.tasks["task-1"].title, "Rewritten");
+}
+
+#[test]
+fn test_load_state_replays_fully_when_earlier_file_appears() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let update = json!({
+        "v": 1, "op": "update", "id": "task-1",
+        "ts": "2024-01-16T10:00:00Z", "by": "@tester", "branch": "main",
+        "d": {"title": "Updated"}
+    });
+    write_events(&spool_dir.join("events"), "2024-01-16.jsonl", &[update]);
+
+    let ctx = create_test_context(&spool_dir);
+    spool::state::rebuild(&ctx).unwrap();
+
+    // A merge brings in an older day file that must be replayed first
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "@tester", "branch": "main",
+        "d": {"title": "Original"}
+    });
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &[create]);
+
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.tasks["task-1"].title, "Updated");
+}
+
+#[test]
+fn test_materialize_is_independent_of_line_order() {
+    let feature = json!({
+        "v": 1, "op": "update", "id": "task-1",
+        "ts": "2024-01-15T10:05:00Z", "hlc": [1705313100000i64, 0], "by": "@bob", "branch": "feature",
+        "d": {"title": "From feature"}
+    });
+    let main = json!({
+        "v": 1, "op": "update", "id": "task-1",
+        "ts": "2024-01-15T10:05:00Z", "hlc": [1705313100000i64, 1], "by": "@alice", "branch": "main",
+        "d": {"title": "From main"}
+    });
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "hlc": [1705312800000i64, 0], "by": "@alice", "branch": "main",
+        "d": {"title": "Original"}
+    });
+
+    // The same lines as two different merges might leave them
+    let orders = [
+        vec![create.clone(), main.clone(), feature.clone()],
+        vec![feature, create, main],
+    ];
+    for events in orders {
+        let temp_dir = TempDir::new().unwrap();
+        let spool_dir = setup_spool_dir(&temp_dir);
+        write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &events);
+
+        let ctx = create_test_context(&spool_dir);
+        let state = spool::state::materialize(&ctx).unwrap();
+        assert_eq!(state.tasks["task-1"].title, "From main");
+    }
+}
+
+#[test]
+fn test_load_state_replays_fully_when_appended_event_sorts_earlier() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "hlc": [1705312800000i64, 0], "by": "@alice", "branch": "main",
+        "d": {"title": "Original"}
+    });
+    let update = json!({
+        "v": 1, "op": "update", "id": "task-1",
+        "ts": "2024-01-15T10:10:00Z", "hlc": [1705313400000i64, 0], "by": "@alice", "branch": "main",
+        "d": {"title": "Newest"}
+    });
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create.clone(), update.clone()],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    spool::state::rebuild(&ctx).unwrap();
+
+    // A merge appends an older event from another branch
+    let merged = json!({
+        "v": 1, "op": "update", "id": "task-1",
+        "ts": "2024-01-15T10:05:00Z", "hlc": [1705313100000i64, 0], "by": "@bob", "branch": "feature",
+        "d": {"title": "Older"}
+    });
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create, update, merged],
+    );
+
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.tasks["task-1"].title, "Newest");
+}
+
+#[test]
+fn test_materialize_applies_duplicated_events_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "@tester", "branch": "main",
+        "d": {"title": "Duplicated"}
+    });
+    let comment = json!({
+        "v": 1, "op": "comment", "id": "task-1",
+        "ts": "2024-01-15T11:00:00Z", "by": "@reviewer", "branch": "main",
+        "d": {"body": "Looks good"}
+    });
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create, comment.clone(), comment.clone()],
+    );
+    // A cherry-pick copied the comment into a later file as well
+    write_events(&spool_dir.join("events"), "2024-01-16.jsonl", &[comment]);
+
+    let ctx = create_test_context(&spool_dir);
+    let state = spool::state::materialize(&ctx).unwrap();
+
+    assert_eq!(state.tasks["task-1"].comments.len(), 1);
+    assert!(state
+        .warnings
+        .iter()
+        .any(|w| w.contains("Ignored 2 duplicate event(s)")));
+}
+
+#[test]
+fn test_materialize_ignores_incomplete_transactions() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "@tester", "branch": "main",
+        "d": {"title": "Original"}
+    });
+    let txn_event = |id: &str, n: u32, title: &str, ts: &str| {
+        json!({
+            "v": 1, "op": "update", "id": "task-1", "ts": ts,
+            "txn": {"id": id, "n": n},
+            "by": "@tester", "branch": "main",
+            "d": {"title": title}
+        })
+    };
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[
+            create,
+            txn_event("complete", 2, "Committed", "2024-01-15T11:00:00Z"),
+            txn_event("complete", 2, "Committed again", "2024-01-15T11:00:01Z"),
+            // The second event of this group never made it to disk
+            txn_event("torn", 2, "Half written", "2024-01-15T12:00:00Z"),
+        ],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    let state = spool::state::materialize(&ctx).unwrap();
+
+    assert_eq!(state.tasks["task-1"].title, "Committed again");
+    assert!(state
+        .warnings
+        .iter()
+        .any(|w| w.contains("Ignored 1 event(s) from incomplete transactions")));
+}
+
+#[test]
+fn test_materialize_skips_unreadable_lines() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let path = spool_dir.join("events").join("2024-01-15.jsonl");
+    fs::write(
+        &path,
+        concat!(
+            r#"{"v":1,"op":"create","id":"task-1","ts":"2024-01-15T10:00:00Z","by":"@tester","branch":"main","d":{"title":"Readable"}}"#,
+            "\n",
+            "{\"v\":1,\"op\":\"comm\n",
+        ),
+    )
+    .unwrap();
+
+    let ctx = create_test_context(&spool_dir);
+    let state = spool::state::materialize(&ctx).unwrap();
+
+    assert_eq!(state.tasks["task-1"].title, "Readable");
+    assert!(state
+        .warnings
+        .iter()
+        .any(|w| w.contains("Skipped 1 unreadable line(s)")));
+}
+
+#[test]
+fn test_materialize_interleaves_author_shards() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let day = spool_dir.join("events/2024-01-15");
+    fs::create_dir_all(&day).unwrap();
+
+    let event = |op: &str, ts: &str, by: &str, d: serde_json::Value| {
+        json!({
+            "v": 1, "op": op, "id": "task-1",
+            "ts": ts, "by": by, "branch": "main", "d": d
+        })
+    };
+    write_events(
+        &day,
+        "@alice.jsonl",
+        &[
+            event(
+                "create",
+                "2024-01-15T09:00:00Z",
+                "@alice",
+                json!({"title": "Created"}),
+            ),
+            event(
+                "update",
+                "2024-01-15T11:00:00Z",
+                "@alice",
+                json!({"title": "By alice"}),
+            ),
+        ],
+    );
+    write_events(
+        &day,
+        "@bob.jsonl",
+        &[event(
+            "update",
+            "2024-01-15T10:00:00Z",
+            "@bob",
+            json!({"title": "By bob"}),
+        )],
+    );
+
+    // Bob's shard sorts last but his update came first
+    let ctx = create_test_context(&spool_dir);
+    let state = spool::state::materialize(&ctx).unwrap();
+    assert_eq!(state.tasks["task-1"].title, "By alice");
+}
+
+#[test]
+fn test_load_state_catches_up_across_author_shards() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let day = spool_dir.join("events/2024-01-15");
+    fs::create_dir_all(&day).unwrap();
+
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T09:00:00Z", "by": "@bob", "branch": "main",
+        "d": {"title": "Cached"}
+    });
+    write_events(&day, "@bob.jsonl", &[create]);
+
+    let ctx = create_test_context(&spool_dir);
+    spool::state::rebuild(&ctx).unwrap();
+
+    // A new shard sorting before the cached one, then an append to it
+    let update = json!({
+        "v": 1, "op": "update", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "@alice", "branch": "main",
+        "d": {"title": "Updated"}
+    });
+    write_events(&day, "@alice.jsonl", &[update]);
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.tasks["task-1"].title, "Updated");
+
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(day.join("@alice.jsonl"))
+        .unwrap();
+    let complete = json!({
+        "v": 1, "op": "complete", "id": "task-1",
+        "ts": "2024-01-15T11:00:00Z", "by": "@alice", "branch": "main",
+        "d": {}
+    });
+    writeln!(file, "{}", complete).unwrap();
+    let state = spool::state::load_or_materialize_state(&ctx).unwrap();
+    assert_eq!(state.tasks["task-1"].status, TaskStatus::Complete);
+
+    // The refreshed cache covers both shards
+    let cached: spool::state::State =
+        serde_json::from_str(&fs::read_to_string(ctx.state_path()).unwrap()).unwrap();
+    let watermark = cached.watermark.unwrap();
+    assert!(watermark.contains_key("events/2024-01-15/@alice.jsonl"));
+    assert!(watermark.contains_key("events/2024-01-15/@bob.jsonl"));
+}
+
+#[test]
+fn test_index_lists_each_tasks_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[
+            json!({"v": 1, "op": "create", "id": "task-a", "ts": "2024-01-15T10:00:00Z", "by": "tester", "branch": "main", "d": {"title": "A"}}),
+            json!({"v": 1, "op": "create", "id": "task-b", "ts": "2024-01-15T11:00:00Z", "by": "tester", "branch": "main", "d": {"title": "B"}}),
+        ],
+    );
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-16.jsonl",
+        &[
+            json!({"v": 1, "op": "complete", "id": "task-b", "ts": "2024-01-16T10:00:00Z", "by": "tester", "branch": "main", "d": {}}),
+        ],
+    );
+    let ctx = create_test_context(&spool_dir);
+
+    let index = spool::state::load_or_build_index(&ctx).unwrap();
+    assert_eq!(index.segments_of("task-a"), vec!["events/2024-01-15.jsonl"]);
+    assert_eq!(
+        index.segments_of("task-b"),
+        vec!["events/2024-01-15.jsonl", "events/2024-01-16.jsonl"]
+    );
+    assert_eq!(index.tasks["task-b"].status, TaskStatus::Complete);
+    assert!(index.segments_of("task-missing").is_empty());
+
+    // Reads of one task only touch its files
+    assert_eq!(
+        ctx.task_segments("task-a").unwrap(),
+        vec!["events/2024-01-15.jsonl"]
+    );
+    let events = spool::archive::collect_task_events(&ctx, "task-b").unwrap();
+    assert_eq!(events.len(), 2);
+}
+
+#[test]
+fn test_index_is_updated_on_write() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+    spool::state::rebuild(&ctx).unwrap();
+
+    let id = spool::writer::create_task(
+        &ctx,
+        spool::writer::CreateTaskParams {
+            title: "Indexed on write",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+
+    // Without catching up: the write recorded the event in the file
+    let content = fs::read_to_string(ctx.index_path()).unwrap();
+    let index: spool::state::Index = serde_json::from_str(&content).unwrap();
+    assert_eq!(index.segments_of(&id).len(), 1);
+    let watermark = index.watermark.unwrap();
+    let segment = &index.tasks[&id].files[0];
+    assert_eq!(
+        watermark[segment].offset,
+        fs::metadata(spool_dir.join(segment)).unwrap().len()
+    );
+}
+
+#[test]
+fn test_index_is_rebuilt_when_history_changes() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let create = |id: &str| json!({"v": 1, "op": "create", "id": id, "ts": "2024-01-15T10:00:00Z", "by": "tester", "branch": "main", "d": {"title": id}});
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create("task-a"), create("task-b")],
+    );
+    let ctx = create_test_context(&spool_dir);
+    assert_eq!(
+        spool::state::load_or_build_index(&ctx).unwrap().tasks.len(),
+        2
+    );
+
+    // e.g. a checkout of an older commit
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create("task-a")],
+    );
+    let index = spool::state::load_or_build_index(&ctx).unwrap();
+    assert_eq!(index.tasks.len(), 1);
+    assert!(index.segments_of("task-b").is_empty());
+}
+
+#[test]
+fn test_materialize_many_segments_in_order() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    // Each day renames the previous day's task and creates a new one, so
+    // applying any file out of order changes the result
+    for day in 1..=28 {
+        let mut events = vec![json!({
+            "v": 1, "op": "create", "id": format!("task-{}", day),
+            "ts": format!("2024-02-{:02}T10:00:00Z", day), "by": "@tester", "branch": "main",
+            "d": {"title": "New"}
+        })];
+        if day > 1 {
+            events.push(json!({
+                "v": 1, "op": "update", "id": format!("task-{}", day - 1),
+                "ts": format!("2024-02-{:02}T09:00:00Z", day), "by": "@tester", "branch": "main",
+                "d": {"title": format!("Renamed on {}", day)}
+            }));
+        }
+        write_events(
+            &spool_dir.join("events"),
+            &format!("2024-02-{:02}.jsonl", day),
+            &events,
+        );
+    }
+
+    let ctx = create_test_context(&spool_dir);
+    let state = spool::state::replay_all(&ctx).unwrap();
+    assert_eq!(state.tasks.len(), 28);
+    for day in 1..28 {
+        assert_eq!(
+            state.tasks[&format!("task-{}", day)].title,
+            format!("Renamed on {}", day + 1)
+        );
+    }
+    assert_eq!(state.tasks["task-28"].title, "New");
+    assert_eq!(state.watermark.unwrap().len(), 28);
+}
+
+#[test]
+fn test_materialize_transaction_split_by_archiving() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let event = |id: &str, op: &str, ts: &str, txn: serde_json::Value, d: serde_json::Value| {
+        json!({
+            "v": 1, "op": op, "id": id, "ts": ts, "txn": txn,
+            "by": "@tester", "branch": "main", "d": d
+        })
+    };
+    let whole = json!({"id": "whole", "n": 2});
+    let torn = json!({"id": "torn", "n": 2});
+
+    // One event of each transaction was archived with its task
+    write_events(
+        &spool_dir.join("archive"),
+        "2024-01.jsonl",
+        &[
+            event(
+                "task-a",
+                "create",
+                "2024-01-15T10:00:00Z",
+                json!(null),
+                json!({"title": "A"}),
+            ),
+            event(
+                "task-a",
+                "update",
+                "2024-01-15T11:00:00Z",
+                whole.clone(),
+                json!({"title": "A2"}),
+            ),
+            event(
+                "task-a",
+                "update",
+                "2024-01-15T12:00:00Z",
+                torn,
+                json!({"title": "Torn"}),
+            ),
+        ],
+    );
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[
+            event(
+                "task-b",
+                "create",
+                "2024-01-15T10:00:00Z",
+                json!(null),
+                json!({"title": "B"}),
+            ),
+            event(
+                "task-b",
+                "update",
+                "2024-01-15T11:00:00Z",
+                whole,
+                json!({"title": "B2"}),
+            ),
+        ],
+    );
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-16.jsonl",
+        &[event(
+            "task-b",
+            "update",
+            "2024-01-16T10:00:00Z",
+            json!(null),
+            json!({"title": "B3"}),
+        )],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    let state = spool::state::replay_all(&ctx).unwrap();
+    assert_eq!(state.tasks["task-a"].title, "A2");
+    assert_eq!(state.tasks["task-b"].title, "B3");
+}
+
+#[test]
+fn test_comment_edits_apply_to_the_comment_they_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let event = |op: &str, ts: &str, d: serde_json::Value| {
+        json!({
+            "v": 1, "op": op, "id": "task-1", "ts": ts,
+            "by": "@tester", "branch": "main", "d": d
+        })
+    };
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[
+            event("create", "2024-01-15T10:00:00Z", json!({"title": "Task"})),
+            event("comment", "2024-01-15T11:00:00Z", json!({"body": "Typo"})),
+        ],
+    );
+    let ctx = create_test_context(&spool_dir);
+    let comment = spool::state::materialize(&ctx).unwrap().tasks["task-1"].comments[0]
+        .id
+        .clone();
+    assert_eq!(comment.len(), 16);
+
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-16.jsonl",
+        &[
+            event(
+                "edit_comment",
+                "2024-01-16T10:00:00Z",
+                json!({"comment": comment, "body": "Fixed", "ref": "def456"}),
+            ),
+            // Edits of unknown comments change nothing
+            event(
+                "edit_comment",
+                "2024-01-16T11:00:00Z",
+                json!({"comment": "0000000000000000", "body": "Lost"}),
+            ),
+        ],
+    );
+    let state = spool::state::materialize(&ctx).unwrap();
+    let task = &state.tasks["task-1"];
+    assert_eq!(task.comments.len(), 1);
+    assert_eq!(task.comments[0].body, "Fixed");
+    assert_eq!(task.comments[0].r#ref.as_deref(), Some("def456"));
+    assert_eq!(
+        task.comments[0].edited.unwrap().to_rfc3339(),
+        "2024-01-16T10:00:00+00:00"
+    );
+    assert_eq!(task.updated.to_rfc3339(), "2024-01-16T10:00:00+00:00");
+
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-17.jsonl",
+        &[event(
+            "delete_comment",
+            "2024-01-17T10:00:00Z",
+            json!({"comment": comment}),
+        )],
+    );
+    let state = spool::state::materialize(&ctx).unwrap();
+    assert!(state.tasks["task-1"].comments.is_empty());
+}
+
+#[test]
+fn test_tags_added_on_different_branches_all_survive() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let event = |op: &str, ts: &str, branch: &str, d: serde_json::Value| {
+        json!({
+            "v": 1, "op": op, "id": "task-1", "ts": ts,
+            "by": "@tester", "branch": branch, "d": d
+        })
+    };
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[
+            event(
+                "create",
+                "2024-01-15T10:00:00Z",
+                "main",
+                json!({"title": "Task", "tags": ["ui", "wip"]}),
+            ),
+            // Merged from two branches
+            event(
+                "tag",
+                "2024-01-15T11:00:00Z",
+                "fix",
+                json!({"tags": ["bug"]}),
+            ),
+            event(
+                "untag",
+                "2024-01-15T11:01:00Z",
+                "fix",
+                json!({"tags": ["wip"]}),
+            ),
+            event(
+                "tag",
+                "2024-01-15T11:02:00Z",
+                "docs",
+                json!({"tags": ["docs", "ui"]}),
+            ),
+            // Removing a tag that isn't there changes nothing
+            event(
+                "untag",
+                "2024-01-15T11:03:00Z",
+                "docs",
+                json!({"tags": ["p0"]}),
+            ),
+        ],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    let state = spool::state::materialize(&ctx).unwrap();
+    assert_eq!(state.tasks["task-1"].tags, vec!["ui", "bug", "docs"]);
+    assert!(state.conflicts.is_empty());
+}
@@ -0,0 +1,253 @@
+use chrono::{Duration, Utc};
+use serde_json::json;
+use spool::concurrency::get_task_version;
+use spool::context::SpoolContext;
+use spool::event::Event;
+use spool::payload::{CompletePayload, Payload, UpdatePayload};
+use spool::state::{build_index, materialize};
+use spool::store::{EventStore, Layout, MemoryStore};
+use spool::writer::{complete_task, create_task, write_event, CreateTaskParams, Transaction};
+use std::fs;
+use tempfile::TempDir;
+
+fn line(event: serde_json::Value) -> Vec<u8> {
+    format!("{}\n", event).into_bytes()
+}
+
+#[test]
+fn test_memory_store_append_read_replace() {
+    let store = MemoryStore::new();
+    assert!(store.segments().unwrap().is_empty());
+
+    store.append("events/2024-01-02.jsonl", b"b\n").unwrap();
+    store.append("archive/2024-01.jsonl", b"a\n").unwrap();
+    // A torn last line stays on its own line
+    store.append("events/2024-01-02.jsonl", b"partial").unwrap();
+    store.append("events/2024-01-02.jsonl", b"c\n").unwrap();
+
+    assert_eq!(
+        store.segments().unwrap(),
+        vec!["archive/2024-01.jsonl", "events/2024-01-02.jsonl"]
+    );
+    assert_eq!(
+        store.read("events/2024-01-02.jsonl").unwrap(),
+        b"b\npartial\nc\n"
+    );
+
+    store.replace("archive/2024-01.jsonl", b"").unwrap();
+    assert_eq!(store.segments().unwrap(), vec!["events/2024-01-02.jsonl"]);
+    assert!(store.read("archive/2024-01.jsonl").is_err());
+}
+
+#[test]
+fn test_write_and_materialize_in_memory() {
+    let store = MemoryStore::new();
+    let id = create_task(
+        &store,
+        CreateTaskParams {
+            title: "In memory",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+
+    let mut txn = Transaction::new(&store);
+    txn.push(Event::new(
+        &id,
+        Payload::Update(UpdatePayload {
+            title: Some("Renamed".to_string()),
+            ..Default::default()
+        }),
+        "@tester",
+        "main",
+    ));
+    txn.push(Event::new(
+        &id,
+        Payload::Complete(CompletePayload::default()),
+        "@tester",
+        "main",
+    ));
+    txn.commit().unwrap();
+
+    let state = materialize(&store).unwrap();
+    let task = &state.tasks[&id];
+    assert_eq!(task.title, "Renamed");
+    assert_eq!(task.status, spool::TaskStatus::Complete);
+    assert_eq!(task.resolution.as_deref(), Some("done"));
+
+    let index = build_index(&store).unwrap();
+    assert_eq!(index.tasks[&id].files.len(), 1);
+
+    // Creates are still refused for a taken ID
+    let duplicate = Event::new(&id, Payload::Create(Default::default()), "@tester", "main");
+    assert!(write_event(&store, &duplicate).is_err());
+}
+
+#[test]
+fn test_validate_in_memory() {
+    let store = MemoryStore::new();
+    store
+        .append(
+            "events/2024-01-15.jsonl",
+            &line(json!({
+                "v": 1, "op": "create", "id": "task-001",
+                "ts": "2024-01-15T10:00:00Z", "by": "tester", "branch": "main",
+                "d": {"title": "Valid"}
+            })),
+        )
+        .unwrap();
+    store
+        .append("events/2024-01-15.jsonl", b"not json\n")
+        .unwrap();
+
+    let result = spool::validation::validate(&store, false).unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert!(result.errors[0].starts_with("2024-01-15.jsonl:2:"));
+}
+
+#[test]
+fn test_archive_in_memory() {
+    let store = MemoryStore::new();
+    let old = Utc::now() - Duration::days(60);
+    let segment = format!("events/{}.jsonl", old.format("%Y-%m-%d"));
+    for (op, d) in [("create", json!({"title": "Old"})), ("complete", json!({}))] {
+        store
+            .append(
+                &segment,
+                &line(json!({
+                    "v": 1, "op": op, "id": "old-task",
+                    "ts": old.to_rfc3339(), "by": "tester", "branch": "main", "d": d
+                })),
+            )
+            .unwrap();
+    }
+
+    let archived = spool::archive::archive_tasks(&store, 30, false).unwrap();
+    assert_eq!(archived, vec!["old-task"]);
+    let archive = format!("archive/{}.jsonl", old.format("%Y-%m"));
+    assert_eq!(store.segments().unwrap(), vec![archive]);
+    assert!(materialize(&store).unwrap().tasks["old-task"]
+        .archived
+        .is_some());
+
+    spool::archive::unarchive_task(&store, "old-task").unwrap();
+    assert_eq!(store.segments().unwrap(), vec![segment]);
+}
+
+#[test]
+fn test_filesystem_store_uses_spool_layout() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = temp_dir.path().join(".spool");
+    fs::create_dir_all(spool_dir.join("events")).unwrap();
+    let ctx = SpoolContext::new(spool_dir.clone());
+
+    ctx.append("events/2024-01-15.jsonl", b"one\n").unwrap();
+    ctx.append("archive/2023-12.jsonl", b"two\n").unwrap();
+
+    assert_eq!(
+        ctx.segments().unwrap(),
+        vec!["archive/2023-12.jsonl", "events/2024-01-15.jsonl"]
+    );
+    assert_eq!(
+        fs::read_to_string(spool_dir.join("archive/2023-12.jsonl")).unwrap(),
+        "two\n"
+    );
+
+    ctx.replace("events/2024-01-15.jsonl", b"").unwrap();
+    assert!(!spool_dir.join("events/2024-01-15.jsonl").exists());
+}
+
+#[test]
+fn test_author_layout_writes_a_shard_per_author() {
+    let store = MemoryStore::with_layout(Layout::Author);
+    let id = create_task(
+        &store,
+        CreateTaskParams {
+            title: "Sharded",
+            ..Default::default()
+        },
+        "@bob",
+        "main",
+    )
+    .unwrap();
+    complete_task(&store, &id, None, "@alice", "main").unwrap();
+
+    let day = Utc::now().format("%Y-%m-%d");
+    let alice = format!("events/{}/@alice.jsonl", day);
+    let bob = format!("events/{}/@bob.jsonl", day);
+    assert_eq!(store.segments().unwrap(), vec![alice.clone(), bob.clone()]);
+
+    let state = materialize(&store).unwrap();
+    assert_eq!(state.tasks[&id].status, spool::TaskStatus::Complete);
+    let index = build_index(&store).unwrap();
+    assert_eq!(index.tasks[&id].files.len(), 2);
+
+    // The task's version is its latest event across the day's shards, not
+    // the last event in the last shard
+    let version = get_task_version(&store, &id).unwrap().unwrap();
+    let (alice_events, _) = store.read_events(&alice).unwrap();
+    assert_eq!(version.last_event_hash, alice_events[0].event_id());
+}
+
+#[test]
+fn test_unarchive_restores_author_shards() {
+    let store = MemoryStore::with_layout(Layout::Author);
+    let old = Utc::now() - Duration::days(60);
+    let day = old.format("%Y-%m-%d");
+    for (op, by, d) in [
+        ("create", "@alice", json!({"title": "Old"})),
+        ("complete", "@bob", json!({})),
+    ] {
+        store
+            .append(
+                &format!("events/{}/{}.jsonl", day, by),
+                &line(json!({
+                    "v": 1, "op": op, "id": "old-task",
+                    "ts": old.to_rfc3339(), "by": by, "branch": "main", "d": d
+                })),
+            )
+            .unwrap();
+    }
+
+    spool::archive::archive_tasks(&store, 30, false).unwrap();
+    let archive = format!("archive/{}.jsonl", old.format("%Y-%m"));
+    assert_eq!(store.segments().unwrap(), vec![archive]);
+
+    spool::archive::unarchive_task(&store, "old-task").unwrap();
+    assert_eq!(
+        store.segments().unwrap(),
+        vec![
+            format!("events/{}/@alice.jsonl", day),
+            format!("events/{}/@bob.jsonl", day)
+        ]
+    );
+}
+
+#[test]
+fn test_filesystem_store_lists_author_shards() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = temp_dir.path().join(".spool");
+    fs::create_dir_all(spool_dir.join("events")).unwrap();
+    let ctx = SpoolContext::new(spool_dir.clone());
+
+    ctx.append("events/2024-01-15/@bob.jsonl", b"one\n")
+        .unwrap();
+    ctx.append("events/2024-01-15.jsonl", b"two\n").unwrap();
+    ctx.append("events/2024-01-14/@alice.jsonl", b"three\n")
+        .unwrap();
+
+    assert_eq!(
+        ctx.segments().unwrap(),
+        vec![
+            "events/2024-01-14/@alice.jsonl",
+            "events/2024-01-15.jsonl",
+            "events/2024-01-15/@bob.jsonl"
+        ]
+    );
+
+    // Removing a day's last shard removes its directory
+    ctx.replace("events/2024-01-14/@alice.jsonl", b"").unwrap();
+    assert!(!spool_dir.join("events/2024-01-14").exists());
+}
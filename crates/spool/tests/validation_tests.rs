@@ -240,6 +240,44 @@ fn test_validation_orphaned_blocked_by() {
         .any(|w| w.contains("non-existent blocked_by")));
 }
 
+#[test]
+fn test_validation_link_cycle_from_merged_branches() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    // Each branch linked one way round, which was fine on its own
+    let events = vec![
+        json!({
+            "v": 1, "op": "create", "id": "task-a",
+            "ts": "2024-01-15T10:00:00Z", "by": "tester", "branch": "main",
+            "d": {"title": "A", "blocks": ["task-b"]}
+        }),
+        json!({
+            "v": 1, "op": "create", "id": "task-b",
+            "ts": "2024-01-15T10:01:00Z", "by": "tester", "branch": "main",
+            "d": {"title": "B", "blocked_by": ["task-a"]}
+        }),
+        json!({
+            "v": 1, "op": "link", "id": "task-b",
+            "ts": "2024-01-15T11:00:00Z", "by": "tester", "branch": "feature",
+            "d": {"rel": "blocks", "target": "task-a"}
+        }),
+    ];
+
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &events);
+
+    let ctx = create_test_context(&spool_dir);
+    let result = spool::validation::validate(&ctx, false).unwrap();
+
+    let cycles: Vec<&String> = result
+        .warnings
+        .iter()
+        .filter(|w| w.contains("cycle"))
+        .collect();
+    assert_eq!(cycles.len(), 1);
+    assert!(cycles[0].contains("task-a -> task-b -> task-a"));
+}
+
 #[test]
 fn test_validation_strict_mode_errors() {
     let temp_dir = TempDir::new().unwrap();
@@ -322,3 +360,179 @@ fn test_validation_archive_files() {
     assert!(result.errors.is_empty());
     assert!(result.warnings.is_empty());
 }
+
+#[test]
+fn test_validation_unknown_operation_warns() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    write_lines(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[
+            r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"tester","branch":"main","d":{"title":"Task"}}"#,
+            r#"{"v":1,"op":"estimate","id":"task-001","ts":"2024-01-15T10:01:00Z","by":"tester","branch":"main","d":{"points":3}}"#,
+        ],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    let result = spool::validation::validate(&ctx, false).unwrap();
+
+    assert!(result.errors.is_empty());
+    assert!(result
+        .warnings
+        .iter()
+        .any(|w| w.contains("Unknown operation 'estimate'")));
+}
+
+#[test]
+fn test_validation_reports_exact_duplicates() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "tester", "branch": "main",
+        "d": {"title": "Task"}
+    });
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create.clone(), create],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    let result = spool::validation::validate(&ctx, false).unwrap();
+
+    assert!(result.errors.is_empty());
+    assert_eq!(result.warnings.len(), 1);
+    assert!(
+        result.warnings[0].contains("2024-01-15.jsonl:2: Duplicate of event at 2024-01-15.jsonl:1")
+    );
+}
+
+#[test]
+fn test_remove_duplicates_rewrites_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let create = json!({
+        "v": 1, "op": "create", "id": "task-1",
+        "ts": "2024-01-15T10:00:00Z", "by": "tester", "branch": "main",
+        "d": {"title": "Task"}
+    });
+    let comment = json!({
+        "v": 1, "op": "comment", "id": "task-1",
+        "ts": "2024-01-15T11:00:00Z", "by": "tester", "branch": "main",
+        "d": {"body": "Twice"}
+    });
+    write_events(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create, comment.clone()],
+    );
+    write_lines(
+        &spool_dir.join("events"),
+        "2024-01-16.jsonl",
+        &[&comment.to_string(), "not json"],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    let removed = spool::validation::remove_duplicates(&ctx).unwrap();
+    assert_eq!(removed, 1);
+
+    let first = fs::read_to_string(spool_dir.join("events/2024-01-15.jsonl")).unwrap();
+    assert_eq!(first.lines().count(), 2);
+    let second = fs::read_to_string(spool_dir.join("events/2024-01-16.jsonl")).unwrap();
+    assert_eq!(second, "not json\n");
+
+    assert_eq!(spool::validation::remove_duplicates(&ctx).unwrap(), 0);
+}
+
+#[test]
+fn test_validation_reports_incomplete_transaction() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let events = vec![
+        json!({
+            "v": 1, "op": "create", "id": "task-1",
+            "ts": "2024-01-15T10:00:00Z", "by": "tester", "branch": "main",
+            "d": {"title": "Task"}
+        }),
+        json!({
+            "v": 1, "op": "update", "id": "task-1",
+            "ts": "2024-01-15T11:00:00Z", "txn": {"id": "abc-1234", "n": 3},
+            "by": "tester", "branch": "main",
+            "d": {"title": "Partial"}
+        }),
+    ];
+    write_events(&spool_dir.join("events"), "2024-01-15.jsonl", &events);
+
+    let ctx = create_test_context(&spool_dir);
+    let result = spool::validation::validate(&ctx, false).unwrap();
+
+    assert!(result
+        .warnings
+        .iter()
+        .any(|w| w.contains("Transaction abc-1234 is incomplete")));
+}
+
+#[test]
+fn test_repair_quarantines_corrupt_lines() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+
+    let create = r#"{"v":1,"op":"create","id":"task-1","ts":"2024-01-15T10:00:00Z","by":"tester","branch":"main","d":{"title":"Task"}}"#;
+    write_lines(
+        &spool_dir.join("events"),
+        "2024-01-15.jsonl",
+        &[create, r#"{"v":1,"op":"comm"#],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    assert_eq!(spool::validation::repair(&ctx, true).unwrap(), 1);
+    assert_eq!(spool::validation::repair(&ctx, false).unwrap(), 1);
+
+    let events = fs::read_to_string(spool_dir.join("events/2024-01-15.jsonl")).unwrap();
+    assert_eq!(events, format!("{}\n", create));
+
+    let rejected = fs::read_to_string(spool_dir.join("rejected/events/2024-01-15.jsonl")).unwrap();
+    let record: serde_json::Value = serde_json::from_str(rejected.trim()).unwrap();
+    assert_eq!(record["file"], "events/2024-01-15.jsonl");
+    assert_eq!(record["line"], 2);
+    assert_eq!(record["text"], r#"{"v":1,"op":"comm"#);
+
+    assert_eq!(spool::validation::repair(&ctx, false).unwrap(), 0);
+}
+
+#[test]
+fn test_validation_orders_author_shards_by_clock() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    fs::write(spool_dir.join("config.json"), r#"{"layout": "author"}"#).unwrap();
+    let day = spool_dir.join("events/2024-01-15");
+    fs::create_dir_all(&day).unwrap();
+
+    // Bob creates the task and Alice comments on it later the same day;
+    // Alice's shard is read first
+    write_lines(
+        &day,
+        "@bob.jsonl",
+        &[
+            r#"{"v":1,"op":"create","id":"task-001","ts":"2024-01-15T10:00:00Z","by":"@bob","branch":"main","d":{"title":"Bob's task"}}"#,
+        ],
+    );
+    write_lines(
+        &day,
+        "@alice.jsonl",
+        &[
+            r#"{"v":1,"op":"comment","id":"task-001","ts":"2024-01-15T11:00:00Z","by":"@alice","branch":"main","d":{"body":"Looks good"}}"#,
+        ],
+    );
+
+    let ctx = create_test_context(&spool_dir);
+    let result = spool::validation::validate(&ctx, true).unwrap();
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+}
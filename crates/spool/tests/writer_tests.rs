@@ -1,11 +1,14 @@
 use std::fs;
 use tempfile::TempDir;
 
+use spool::concurrency::{get_task_version, FileLock};
 use spool::context::SpoolContext;
 use spool::event::{Event, Operation};
+use spool::payload::{CreatePayload, Payload, UpdatePayload};
 use spool::writer::{
-    complete_task, create_stream, create_task, delete_stream, get_current_branch, get_current_user,
-    reopen_task, set_stream, update_stream, update_task, write_event, CreateTaskParams,
+    add_comment, complete_task, create_stream, create_task, delete_comment, delete_stream,
+    edit_comment, get_current_branch, get_current_user, reopen_task, set_stream, tag_task,
+    update_stream, update_task, write_event, write_event_checked, CreateTaskParams, Transaction,
 };
 
 fn setup_spool_dir(temp_dir: &TempDir) -> std::path::PathBuf {
@@ -30,6 +33,9 @@ fn test_write_event_creates_file() {
         op: Operation::Create,
         id: "test-001".to_string(),
         ts: chrono::Utc::now(),
+        hlc: None,
+        prev: vec![],
+        txn: None,
         by: "@tester".to_string(),
         branch: "main".to_string(),
         d: serde_json::json!({"title": "Test task"}),
@@ -58,6 +64,9 @@ fn test_write_event_appends_to_existing_file() {
         op: Operation::Create,
         id: "test-001".to_string(),
         ts: chrono::Utc::now(),
+        hlc: None,
+        prev: vec![],
+        txn: None,
         by: "@tester".to_string(),
         branch: "main".to_string(),
         d: serde_json::json!({"title": "First task"}),
@@ -68,6 +77,9 @@ fn test_write_event_appends_to_existing_file() {
         op: Operation::Create,
         id: "test-002".to_string(),
         ts: chrono::Utc::now(),
+        hlc: None,
+        prev: vec![],
+        txn: None,
         by: "@tester".to_string(),
         branch: "main".to_string(),
         d: serde_json::json!({"title": "Second task"}),
@@ -278,6 +290,98 @@ fn test_reopen_task_writes_event() {
     assert!(content.contains("reopen"));
 }
 
+#[test]
+fn test_comment_lifecycle() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+    let id = create_task(
+        &ctx,
+        CreateTaskParams {
+            title: "Commented",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+
+    add_comment(&ctx, &id, "First", Some("abc123"), "@tester", "main").unwrap();
+    add_comment(&ctx, &id, "Second", None, "@other", "main").unwrap();
+    let state = spool::state::materialize(&ctx).unwrap();
+    let comments = &state.tasks[&id].comments;
+    assert_eq!(comments.len(), 2);
+    assert_eq!(comments[0].r#ref.as_deref(), Some("abc123"));
+    assert_ne!(comments[0].id, comments[1].id);
+    let (first, second) = (comments[0].id.clone(), comments[1].id.clone());
+
+    edit_comment(
+        &ctx,
+        &id,
+        &first,
+        "First, corrected",
+        None,
+        "@tester",
+        "main",
+    )
+    .unwrap();
+    delete_comment(&ctx, &id, &second, "@tester", "main").unwrap();
+
+    let state = spool::state::materialize(&ctx).unwrap();
+    let comments = &state.tasks[&id].comments;
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].id, first);
+    assert_eq!(comments[0].body, "First, corrected");
+    // The ref is kept unless the edit replaces it
+    assert_eq!(comments[0].r#ref.as_deref(), Some("abc123"));
+    assert!(comments[0].edited.is_some());
+
+    // History is only appended to
+    let content = fs::read_to_string(&ctx.get_event_files().unwrap()[0]).unwrap();
+    assert_eq!(content.lines().count(), 5);
+    assert!(content.contains("\"op\":\"edit_comment\""));
+    assert!(content.contains("\"op\":\"delete_comment\""));
+}
+
+#[test]
+fn test_tag_task_adds_and_removes_in_one_transaction() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+    let id = create_task(
+        &ctx,
+        CreateTaskParams {
+            title: "Tagged",
+            tags: vec!["wip".to_string(), "ui".to_string()],
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+    tag_task(
+        &ctx,
+        &id,
+        &tags(&["bug"]),
+        &tags(&["wip"]),
+        "@tester",
+        "main",
+    )
+    .unwrap();
+    let state = spool::state::materialize(&ctx).unwrap();
+    assert_eq!(state.tasks[&id].tags, vec!["ui", "bug"]);
+
+    let content = fs::read_to_string(&ctx.get_event_files().unwrap()[0]).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].contains("\"op\":\"tag\"") && lines[1].contains("\"txn\""));
+    assert!(lines[2].contains("\"op\":\"untag\""));
+
+    assert!(tag_task(&ctx, &id, &[], &[], "@tester", "main").is_err());
+}
+
 #[test]
 fn test_get_current_user_returns_formatted_user() {
     let user = get_current_user().unwrap();
@@ -579,3 +683,191 @@ fn test_delete_stream_writes_event() {
     assert_eq!(delete_event["op"], "delete_stream");
     assert_eq!(delete_event["id"], id);
 }
+
+#[test]
+fn test_write_event_stamps_clock_after_existing_events() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+
+    let id = create_task(
+        &ctx,
+        CreateTaskParams {
+            title: "Clocked",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+    complete_task(&ctx, &id, None, "@tester", "main").unwrap();
+
+    let event_files = ctx.get_event_files().unwrap();
+    let events = ctx.parse_events_from_file(&event_files[0]).unwrap();
+    assert_eq!(events.len(), 2);
+    let first = events[0].hlc.expect("writer stamps a clock");
+    let second = events[1].hlc.expect("writer stamps a clock");
+    assert!(second > first);
+    assert_eq!(events[1].op, Operation::Complete);
+}
+
+#[test]
+fn test_write_event_waits_for_write_lock() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+
+    let lock = FileLock::acquire(&ctx).unwrap();
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        drop(lock);
+    });
+
+    complete_task(&ctx, "task-001", None, "@tester", "main").unwrap();
+    release.join().unwrap();
+    assert!(!spool_dir.join(".lock").exists());
+}
+
+#[test]
+fn test_write_event_checked_rejects_stale_version() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+
+    let id = create_task(
+        &ctx,
+        CreateTaskParams {
+            title: "Versioned",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+    let version = get_task_version(&ctx, &id).unwrap().unwrap();
+
+    let update = |title: &str| {
+        Event::new(
+            &id,
+            Payload::Update(UpdatePayload {
+                title: Some(title.to_string()),
+                ..Default::default()
+            }),
+            "@tester",
+            "main",
+        )
+    };
+
+    write_event_checked(&ctx, &update("First"), Some(&version)).unwrap();
+
+    let err = write_event_checked(&ctx, &update("Second"), Some(&version)).unwrap_err();
+    assert!(err.to_string().contains("modified concurrently"));
+
+    let content = fs::read_to_string(&ctx.get_event_files().unwrap()[0]).unwrap();
+    assert!(content.contains("First"));
+    assert!(!content.contains("Second"));
+}
+
+#[test]
+fn test_transaction_writes_group_with_shared_id() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+
+    let mut txn = Transaction::new(&ctx);
+    for title in ["One", "Two", "Three"] {
+        txn.push(Event::new(
+            "task-001",
+            Payload::Update(UpdatePayload {
+                title: Some(title.to_string()),
+                ..Default::default()
+            }),
+            "@tester",
+            "main",
+        ));
+    }
+    assert_eq!(txn.len(), 3);
+    txn.commit().unwrap();
+
+    let events = ctx
+        .parse_events_from_file(&ctx.get_event_files().unwrap()[0])
+        .unwrap();
+    let txns: Vec<_> = events.iter().filter_map(|e| e.txn.clone()).collect();
+    assert_eq!(txns.len(), 3);
+    assert!(txns.iter().all(|t| *t == txns[0] && t.n == 3));
+}
+
+#[test]
+fn test_transaction_writes_nothing_when_a_check_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+
+    let id = create_task(
+        &ctx,
+        CreateTaskParams {
+            title: "Existing",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+    let before = fs::read_to_string(&ctx.get_event_files().unwrap()[0]).unwrap();
+
+    let mut txn = Transaction::new(&ctx);
+    txn.push(Event::new(
+        &id,
+        Payload::Update(UpdatePayload {
+            title: Some("Renamed".to_string()),
+            ..Default::default()
+        }),
+        "@tester",
+        "main",
+    ));
+    // Reusing an existing ID fails the whole transaction
+    txn.push(Event::new(
+        &id,
+        Payload::Create(CreatePayload {
+            title: "Duplicate".to_string(),
+            ..Default::default()
+        }),
+        "@tester",
+        "main",
+    ));
+    assert!(txn.commit().is_err());
+
+    let after = fs::read_to_string(&ctx.get_event_files().unwrap()[0]).unwrap();
+    assert_eq!(before, after);
+}
+
+#[test]
+fn test_append_after_torn_write_starts_a_new_line() {
+    let temp_dir = TempDir::new().unwrap();
+    let spool_dir = setup_spool_dir(&temp_dir);
+    let ctx = create_test_context(&spool_dir);
+
+    let id = create_task(
+        &ctx,
+        CreateTaskParams {
+            title: "Survivor",
+            ..Default::default()
+        },
+        "@tester",
+        "main",
+    )
+    .unwrap();
+
+    // A writer killed mid-line leaves a fragment without a newline
+    let file = ctx.get_event_files().unwrap()[0].clone();
+    let mut content = fs::read_to_string(&file).unwrap();
+    content.push_str(r#"{"v":1,"op":"upd"#);
+    fs::write(&file, content).unwrap();
+
+    complete_task(&ctx, &id, None, "@tester", "main").unwrap();
+
+    let (events, bad) = ctx.read_events_lenient(&file).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(bad.len(), 1);
+    assert_eq!(bad[0].text, r#"{"v":1,"op":"upd"#);
+}